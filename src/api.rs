@@ -0,0 +1,20 @@
+// Requests to the Anthropic API with the headers every call needs; the
+// request and response bodies are in types
+
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+
+pub const API_VERSION: &str = "2023-06-01";
+
+pub fn get(client: &Client, url: &str, api_key: &str) -> RequestBuilder {
+    client.get(url)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", API_VERSION)
+}
+
+pub fn post<T: Serialize>(client: &Client, url: &str, api_key: &str, body: &T) -> RequestBuilder {
+    client.post(url)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", API_VERSION)
+        .json(body)
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+
+use perimedes::{browser, cli, config, daemon, doctor, ipc, logging, redact, report, secrets, service, stats, tamper, transcripts, verdicts, watchdog};
+
+#[tokio::main]
+async fn main() {
+    // Print errors ourselves so that they pass through redaction
+    if let Err(e) = run_command().await {
+        eprintln!("Error: {}", redact::scrub(&format!("{:?}", e)));
+        std::process::exit(1);
+    }
+}
+
+async fn run_command() -> Result<()> {
+    let args = cli::parse()?;
+    let profile = cli::resolve_profile(args.profile.as_deref())?;
+    redact::set_log_sensitive(args.log_sensitive);
+    // Only the daemon and its watchdog keep log files
+    let daemon = matches!(args.command, cli::Command::Run | cli::Command::Watchdog);
+    let _log = logging::init(args.log_level, daemon)?;
+
+    match args.command {
+        cli::Command::Stats => stats::print_stats(),
+        cli::Command::Report { private } => report::print_report(private),
+        cli::Command::Status { watch } => ipc::print_status(watch).await,
+        cli::Command::InstallService => service::install(args.profile.as_deref()),
+        cli::Command::SetKey => secrets::set_key().await,
+        cli::Command::ConfigCheck => config::check(),
+        cli::Command::Feedback { correct } => feedback(correct),
+        cli::Command::Doctor { lock_test } => doctor::run(lock_test).await,
+        cli::Command::Export => transcripts::export_judge(),
+        cli::Command::Audit => tamper::audit(),
+        cli::Command::NativeHost => browser::native_host().await,
+        cli::Command::Watchdog => watchdog::run(profile).await,
+        cli::Command::Control(command) => ipc::send(&command).await,
+        cli::Command::Lock { minutes, reason } => self_lock(minutes, reason).await,
+        cli::Command::Run => daemon::run(profile).await,
+    }
+}
+
+// `perimedes feedback`
+fn feedback(correct: bool) -> Result<()> {
+    match verdicts::feedback(correct)? {
+        Some(verdict) => {
+            let said = if verdict.procrastinating { "procrastinating" } else { "not procrastinating" };
+            println!("Labeled the verdict of {} ({}) {}", verdict.time, said, if correct { "right" } else { "wrong" });
+        },
+        None => println!("No verdict to label yet"),
+    }
+    Ok(())
+}
+
+// `perimedes lock`: hand the lock to the daemon, or lock right here if none is running
+async fn self_lock(minutes: u64, reason: Option<String>) -> Result<()> {
+    if ipc::daemon_running().await {
+        let command = format!("lock {}m {}", minutes, reason.as_deref().unwrap_or(""));
+        return ipc::send(command.trim_end()).await;
+    }
+
+    daemon::run_self_lock(minutes, reason.as_deref()).await;
+    Ok(())
+}
+
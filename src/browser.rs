@@ -0,0 +1,100 @@
+// The browser's active tab, reported by an extension over native messaging
+//
+// The browser starts `perimedes native-host` and writes messages to its
+// stdin, each a 32-bit length in native byte order followed by that much
+// JSON: {"url": "...", "title": "..."} whenever the active tab or its
+// address changes. The host passes each on to the daemon as a `tab` command
+// on the control socket and answers with {"ok": true} or {"error": "..."};
+// when the browser closes the pipe, the tab is forgotten. The host manifest
+// points at the binary itself, which recognizes the arguments browsers pass,
+// e.g. ~/.mozilla/native-messaging-hosts/perimedes.json:
+//   {"name": "perimedes", "description": "Active tab for perimedes",
+//    "path": "/usr/local/bin/perimedes", "type": "stdio",
+//    "allowed_extensions": ["perimedes@localhost"]}
+// (Chromium-based browsers take "allowed_origins" instead.) The extension
+// calls browser.runtime.connectNative("perimedes") and posts the tab on
+// tabs.onActivated, tabs.onUpdated and windows.onFocusChanged.
+//
+// The daemon adds the tab to captures while the focused window shows it,
+// for the prompt, RULES and the policies.
+
+use anyhow::{Result, Context, anyhow};
+use serde_json::json;
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::warn;
+
+use crate::focus::ActiveWindow;
+use crate::ipc;
+use crate::redact;
+use crate::types::Tab;
+
+// Browsers limit messages to the host to 4 GB; tabs are far smaller
+const MAX_MESSAGE_BYTES: usize = 1 << 20;
+
+static ACTIVE: Mutex<Option<Tab>> = Mutex::new(None);
+
+// Called for the `tab` command; None when the browser went away
+pub fn set_active(tab: Option<Tab>) {
+    if let Ok(mut active) = ACTIVE.lock() {
+        *active = tab;
+    }
+}
+
+// The active tab, if the focused window is the browser showing it. Browsers
+// put the tab's title into the window title.
+pub fn active(window: Option<&ActiveWindow>) -> Option<Tab> {
+    let tab = ACTIVE.lock().ok()?.clone()?;
+    let shown = window.is_none_or(|window| window.title.contains(tab.title.trim()));
+    shown.then(|| Tab { url: redact::ocr(&tab.url), title: tab.title })
+}
+
+async fn read_message(stdin: &mut tokio::io::Stdin) -> Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match stdin.read_exact(&mut length).await {
+        Ok(_) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read from the browser"),
+    }
+    let length = u32::from_ne_bytes(length) as usize;
+    if length > MAX_MESSAGE_BYTES {
+        return Err(anyhow!("Message of {} bytes from the browser is too long", length));
+    }
+    let mut message = vec![0; length];
+    stdin.read_exact(&mut message).await.context("Failed to read from the browser")?;
+    Ok(Some(message))
+}
+
+async fn write_message(stdout: &mut tokio::io::Stdout, message: &serde_json::Value) -> Result<()> {
+    let message = serde_json::to_vec(message)?;
+    stdout.write_all(&(message.len() as u32).to_ne_bytes()).await?;
+    stdout.write_all(&message).await?;
+    stdout.flush().await?;
+    Ok(())
+}
+
+// `perimedes native-host`: relay tabs from the browser to the daemon until
+// the browser closes the pipe
+pub async fn native_host() -> Result<()> {
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    while let Some(message) = read_message(&mut stdin).await? {
+        let relayed = match serde_json::from_slice::<Tab>(&message) {
+            Ok(tab) => ipc::request(&format!("tab {}", serde_json::to_string(&tab)?)).await,
+            Err(e) => Err(anyhow!("Unexpected message from the browser: {}", e)),
+        };
+        let reply = match relayed {
+            Ok(_) => json!({ "ok": true }),
+            Err(e) => {
+                warn!("{}", e);
+                json!({ "error": e.to_string() })
+            },
+        };
+        write_message(&mut stdout, &reply).await?;
+    }
+
+    if let Err(e) = ipc::request("tab").await {
+        warn!("Failed to clear the tab: {}", e);
+    }
+    Ok(())
+}
@@ -0,0 +1,322 @@
+// Meetings from the user's calendars, during which locks are suppressed or
+// judging is relaxed, see MEETING_MODE
+//
+// CALENDARS are iCalendar files or URLs; CalDAV servers serve a whole
+// calendar as .ics, e.g. Nextcloud at .../calendars/<user>/<name>?export,
+// with the user and an app password in the URL. An event is a meeting if it
+// has attendees or its summary, location or description matches
+// MEETING_PATTERN. Times with a TZID are taken as local time. Recurring
+// events repeat daily or weekly (with INTERVAL, COUNT, UNTIL, BYDAY and
+// EXDATE); other recurrences only count their first occurrence.
+
+use anyhow::{Result, Context, anyhow};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use regex::Regex;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::constants::{CALENDARS, CALENDAR_REFRESH_MINUTES, MEETING_PATTERN};
+
+// Days a recurring event is followed for
+const MAX_RECURRENCE_DAYS: i64 = 5 * 366;
+
+#[derive(Clone)]
+struct Recurrence {
+    weekly: bool,
+    interval: i64,
+    count: Option<usize>,
+    until: Option<DateTime<Local>>,
+    days: Vec<Weekday>,
+}
+
+#[derive(Clone)]
+pub struct Event {
+    pub summary: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub meeting: bool,
+    recurrence: Option<Recurrence>,
+    // Starts of occurrences that were cancelled or moved
+    exceptions: Vec<DateTime<Local>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meeting {
+    pub summary: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+impl Event {
+    // The occurrence going on at the time, if any
+    pub fn occurrence_at(&self, time: DateTime<Local>) -> Option<Meeting> {
+        let length = self.end - self.start;
+        let meeting = |start: DateTime<Local>| Meeting { summary: self.summary.clone(), start, end: start + length };
+        let Some(recurrence) = &self.recurrence else {
+            return (self.start <= time && time < self.end).then(|| meeting(self.start));
+        };
+
+        let first = self.start.date_naive();
+        let days = if recurrence.days.is_empty() { vec![first.weekday()] } else { recurrence.days.clone() };
+        let mut occurrences = 0;
+        for offset in 0..MAX_RECURRENCE_DAYS {
+            let date = first + Duration::days(offset);
+            let repeats = if recurrence.weekly {
+                let weeks = (date.week(Weekday::Mon).first_day() - first.week(Weekday::Mon).first_day()).num_weeks();
+                days.contains(&date.weekday()) && weeks % recurrence.interval == 0
+            } else {
+                offset % recurrence.interval == 0
+            };
+            if !repeats {
+                continue;
+            }
+            let Some(start) = local(date.and_time(self.start.time())) else {
+                continue;
+            };
+            occurrences += 1;
+            if start > time
+                || recurrence.count.is_some_and(|count| occurrences > count)
+                || recurrence.until.is_some_and(|until| start > until) {
+                return None;
+            }
+            if time < start + length && !self.exceptions.contains(&start) {
+                return Some(meeting(start));
+            }
+        }
+        None
+    }
+}
+
+fn local(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&time).earliest()
+}
+
+// DTSTART and the like: UTC with a trailing Z, local otherwise. Dates
+// without a time mark all-day events, which aren't meetings.
+fn parse_time(value: &str) -> Option<DateTime<Local>> {
+    match value.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()
+            .map(|time| Utc.from_utc_datetime(&time).with_timezone(&Local)),
+        None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok().and_then(local),
+    }
+}
+
+// DURATION like PT45M or P1DT2H
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.strip_prefix('P')?;
+    let (mut total, mut number) = (Duration::zero(), String::new());
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {},
+            unit => {
+                let n = number.parse().ok()?;
+                number.clear();
+                total += match unit {
+                    'W' => Duration::weeks(n),
+                    'D' => Duration::days(n),
+                    'H' => Duration::hours(n),
+                    'M' => Duration::minutes(n),
+                    'S' => Duration::seconds(n),
+                    _ => return None,
+                };
+            },
+        }
+    }
+    Some(total)
+}
+
+fn weekday(name: &str) -> Option<Weekday> {
+    // BYDAY may carry an ordinal, e.g. 1MO, which only matters monthly
+    let name = name.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '+');
+    ["MO", "TU", "WE", "TH", "FR", "SA", "SU"].iter()
+        .position(|day| *day == name)
+        .and_then(|index| Weekday::try_from(index as u8).ok())
+}
+
+fn parse_recurrence(value: &str) -> Option<Recurrence> {
+    let parts: HashMap<&str, &str> = value.split(';').filter_map(|part| part.split_once('=')).collect();
+    let weekly = match parts.get("FREQ") {
+        Some(&"DAILY") => false,
+        Some(&"WEEKLY") => true,
+        _ => return None,
+    };
+    Some(Recurrence {
+        weekly,
+        interval: parts.get("INTERVAL").and_then(|n| n.parse().ok()).filter(|n| *n > 0).unwrap_or(1),
+        count: parts.get("COUNT").and_then(|n| n.parse().ok()),
+        until: parts.get("UNTIL").and_then(|until| parse_time(until).or_else(|| {
+            NaiveDate::parse_from_str(until, "%Y%m%d").ok()
+                .and_then(|date| local(date.and_hms_opt(23, 59, 59)?))
+        })),
+        days: parts.get("BYDAY").map_or(Vec::new(), |days| days.split(',').filter_map(weekday).collect()),
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\N", "\n").replace("\\,", ",").replace("\\;", ";").replace("\\\\", "\\")
+}
+
+// "NAME;PARAM=...:VALUE", where quoted parameters may contain colons
+fn split_property(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        },
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let name = line[..colon].split(';').next()?;
+    Some((name, &line[colon + 1..]))
+}
+
+#[derive(Default)]
+struct Fields {
+    uid: String,
+    summary: String,
+    text: String,
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+    duration: Option<Duration>,
+    recurrence: Option<Recurrence>,
+    recurrence_id: Option<DateTime<Local>>,
+    exceptions: Vec<DateTime<Local>>,
+    attendees: bool,
+    cancelled: bool,
+}
+
+// The timed events of an iCalendar file
+pub fn parse(ics: &str, meeting_pattern: &Regex) -> Vec<Event> {
+    // Long lines continue on lines starting with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut moved: Vec<(String, DateTime<Local>)> = Vec::new();
+    let mut fields: Option<Fields> = None;
+    for line in &lines {
+        let Some((name, value)) = split_property(line) else {
+            continue;
+        };
+        match (name.to_ascii_uppercase().as_str(), &mut fields) {
+            ("BEGIN", None) if value == "VEVENT" => fields = Some(Fields::default()),
+            ("END", Some(_)) if value == "VEVENT" => {
+                let Some(event) = fields.take() else {
+                    continue;
+                };
+                if let Some(original) = event.recurrence_id {
+                    moved.push((event.uid.clone(), original));
+                }
+                let (Some(start), false) = (event.start, event.cancelled) else {
+                    continue;
+                };
+                let end = event.end.or(event.duration.map(|duration| start + duration)).unwrap_or(start);
+                events.push((event.uid, Event {
+                    meeting: event.attendees || meeting_pattern.is_match(&event.summary) || meeting_pattern.is_match(&event.text),
+                    summary: event.summary,
+                    start,
+                    end,
+                    recurrence: event.recurrence,
+                    exceptions: event.exceptions,
+                }));
+            },
+            ("UID", Some(fields)) => fields.uid = value.to_string(),
+            ("SUMMARY", Some(fields)) => fields.summary = unescape(value),
+            ("LOCATION" | "DESCRIPTION", Some(fields)) => {
+                fields.text.push_str(&unescape(value));
+                fields.text.push('\n');
+            },
+            ("DTSTART", Some(fields)) => fields.start = parse_time(value),
+            ("DTEND", Some(fields)) => fields.end = parse_time(value),
+            ("DURATION", Some(fields)) => fields.duration = parse_duration(value),
+            ("RRULE", Some(fields)) => fields.recurrence = parse_recurrence(value),
+            ("RECURRENCE-ID", Some(fields)) => fields.recurrence_id = parse_time(value),
+            ("EXDATE", Some(fields)) => fields.exceptions.extend(value.split(',').filter_map(parse_time)),
+            ("ATTENDEE", Some(fields)) => fields.attendees = true,
+            ("STATUS", Some(fields)) => fields.cancelled = value.eq_ignore_ascii_case("CANCELLED"),
+            _ => {},
+        }
+    }
+
+    // Moved occurrences replace theirs in the recurring event
+    for (uid, original) in moved {
+        for (_, event) in events.iter_mut().filter(|(id, event)| *id == uid && event.recurrence.is_some()) {
+            event.exceptions.push(original);
+        }
+    }
+    events.into_iter().map(|(_, event)| event).collect()
+}
+
+// The meeting going on at the time, the one ending last if they overlap
+pub fn meeting_at(events: &[Event], time: DateTime<Local>) -> Option<Meeting> {
+    events.iter()
+        .filter(|event| event.meeting)
+        .filter_map(|event| event.occurrence_at(time))
+        .max_by_key(|meeting| meeting.end)
+}
+
+static EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+
+pub fn meeting_pattern() -> Result<Regex> {
+    Regex::new(MEETING_PATTERN).context("MEETING_PATTERN")
+}
+
+// The meeting going on now, from the last refresh
+pub fn current() -> Option<Meeting> {
+    meeting_at(&EVENTS.lock().ok()?, Local::now())
+}
+
+async fn fetch(client: &Client, source: &str) -> Result<String> {
+    let url = match source.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => source.to_string(),
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return std::fs::read_to_string(source).with_context(|| format!("Failed to read {}", source));
+    }
+    // Calendar URLs often hold credentials, so errors leave them out
+    let response = client.get(&url).send().await.map_err(|e| anyhow!("{}", e.without_url()))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Server replied {}", response.status()));
+    }
+    response.text().await.map_err(|e| anyhow!("{}", e.without_url()))
+}
+
+// Read CALENDARS now and every CALENDAR_REFRESH_MINUTES; a calendar that
+// fails keeps its events from the last refresh
+pub fn spawn() -> Result<()> {
+    if CALENDARS.is_empty() {
+        return Ok(());
+    }
+    let pattern = meeting_pattern()?;
+    tokio::spawn(async move {
+        let client = Client::new();
+        let mut calendars: Vec<Vec<Event>> = CALENDARS.iter().map(|_| Vec::new()).collect();
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(CALENDAR_REFRESH_MINUTES * 60));
+        loop {
+            tick.tick().await;
+            for (i, source) in CALENDARS.iter().enumerate() {
+                match fetch(&client, source).await {
+                    Ok(ics) => {
+                        calendars[i] = parse(&ics, &pattern);
+                        debug!("CALENDARS[{}]: {} events", i, calendars[i].len());
+                    },
+                    Err(e) => warn!("Failed to read CALENDARS[{}]: {}", i, e),
+                }
+            }
+            if let Ok(mut events) = EVENTS.lock() {
+                *events = calendars.iter().flatten().cloned().collect();
+            }
+        }
+    });
+    Ok(())
+}
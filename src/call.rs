@@ -0,0 +1,89 @@
+// Live video calls, during which the screen doesn't lock by itself
+//
+// A call shows as an open window matching CALL_WINDOWS, e.g. Zoom's meeting
+// window or a Meet tab, or as the microphone being recorded: PipeWire and
+// PulseAudio list recording streams as source outputs, and ALSA marks
+// capture devices in use as RUNNING in /proc/asound. Locks asked for by the
+// user still happen. Inhibited locks go into events.jsonl, so a microphone
+// that's always open shows up in reviews.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::constants::{CALL_MICROPHONE, CALL_WINDOWS};
+use crate::events;
+use crate::focus::FocusMonitor;
+
+// A stuck pactl mustn't hold up the lock controller
+const PACTL_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    // Invalid patterns are reported by `perimedes config check`
+    PATTERNS.get_or_init(|| CALL_WINDOWS.iter().filter_map(|pattern| Regex::new(pattern).ok()).collect())
+}
+
+fn call_window(focus_monitor: &FocusMonitor) -> Option<String> {
+    let windows = focus_monitor.windows()
+        .map_err(|e| warn!("Failed to list windows: {}", e))
+        .ok()?;
+    windows.into_iter()
+        .map(|window| format!("{}: {}", window.class, window.title))
+        .find(|window| patterns().iter().any(|pattern| pattern.is_match(window)))
+}
+
+// Whether PipeWire or PulseAudio records from any source; None without pactl
+async fn pulse_recording() -> Option<bool> {
+    let output = Command::new("pactl").args(["list", "short", "source-outputs"]).output();
+    let output = tokio::time::timeout(PACTL_TIMEOUT, output).await.ok()?.ok()?;
+    output.status.success().then(|| !output.stdout.iter().all(u8::is_ascii_whitespace))
+}
+
+// Whether an ALSA capture device is open, e.g. /proc/asound/card0/pcm0c/sub0/status
+fn alsa_recording() -> bool {
+    let Ok(cards) = std::fs::read_dir("/proc/asound") else {
+        return false;
+    };
+    cards.flatten()
+        .flat_map(|card| std::fs::read_dir(card.path()).into_iter().flatten().flatten())
+        .filter(|device| device.file_name().to_string_lossy().ends_with('c'))
+        .flat_map(|device| std::fs::read_dir(device.path()).into_iter().flatten().flatten())
+        .any(|sub| std::fs::read_to_string(sub.path().join("status")).is_ok_and(|status| status.contains("RUNNING")))
+}
+
+// What gives away a call going on, if one is
+pub async fn detect(focus_monitor: Option<&FocusMonitor>) -> Option<String> {
+    if let Some(window) = focus_monitor.and_then(call_window) {
+        return Some(format!("call window {}", window));
+    }
+    if CALL_MICROPHONE && (pulse_recording().await.unwrap_or(false) || alsa_recording()) {
+        return Some("microphone in use".to_string());
+    }
+    None
+}
+
+// Kinds of lock and calls already logged, so a call only logs once per kind
+static LOGGED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+// Whether a call keeps the lock from starting; logged the first time
+pub async fn inhibits(lock: &str, focus_monitor: Option<&FocusMonitor>) -> bool {
+    let call = detect(focus_monitor).await;
+    let Ok(mut logged) = LOGGED.lock() else {
+        return call.is_some();
+    };
+    let Some(call) = call else {
+        *logged = None;
+        return false;
+    };
+    if logged.get_or_insert_with(HashSet::new).insert(format!("{} {}", lock, call)) {
+        info!("In a call ({}), not starting the {}", call, lock);
+        if let Err(e) = events::log("lock_inhibited", &format!("{}: {}", lock, call)) {
+            warn!("Failed to log the inhibited lock: {}", e);
+        }
+    }
+    true
+}
@@ -0,0 +1,98 @@
+// Capturing the screen and the focused window
+
+use anyhow::{Result, Context, anyhow};
+use image::RgbImage;
+use chrono::Local;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, ImageFormat};
+use tracing::warn;
+
+use crate::constants::{CAPTURE_BACKEND, EXCLUDED_APPS, SCROT_CMD};
+use crate::focus;
+use crate::tempstore;
+use crate::tiling;
+use crate::types::CaptureBackend;
+
+// Whether the window belongs to an application on EXCLUDED_APPS
+pub fn is_excluded(window: &focus::ActiveWindow) -> bool {
+    EXCLUDED_APPS.iter().any(|app| app.eq_ignore_ascii_case(&window.class))
+}
+
+// Excluded windows keep only their class; titles can be just as revealing
+pub fn without_title(window: focus::ActiveWindow) -> focus::ActiveWindow {
+    focus::ActiveWindow { title: String::new(), ..window }
+}
+
+// Focused window for a capture; failures only cost the annotation. i3 and
+// sway are asked directly, as they know the workspace.
+pub fn active_window(focus_monitor: Option<&focus::FocusMonitor>) -> Option<focus::ActiveWindow> {
+    if let Some(window) = tiling::focused() {
+        return window
+            .map_err(|e| warn!("Failed to read the focused window from the window manager: {}", e))
+            .ok();
+    }
+    focus_monitor?.active_window()
+        .map_err(|e| warn!("Failed to read the active window: {}", e))
+        .ok()
+        .flatten()
+}
+
+// Source of captures for the monitoring loop
+pub trait ScreenCapturer {
+    fn capture(&mut self) -> impl Future<Output = Result<PathBuf>> + Send;
+}
+
+// Captures of the whole screen, with scrot or natively per CAPTURE_BACKEND
+pub struct Scrot;
+
+impl ScreenCapturer for Scrot {
+    fn capture(&mut self) -> impl Future<Output = Result<PathBuf>> + Send {
+        take_screenshot()
+    }
+}
+
+pub async fn take_screenshot() -> Result<PathBuf> {
+    let timestamp = Local::now().format("%Y%m%d%H%M%S").to_string();
+    let path = tempstore::file(&format!("capture_{}.png", timestamp))?;
+
+    if let CaptureBackend::Native = CAPTURE_BACKEND {
+        let native = path.clone();
+        tokio::task::spawn_blocking(move || capture_native(&native)).await??;
+        return Ok(path);
+    }
+
+    Command::new(SCROT_CMD)
+        .arg(&path)
+        .status()
+        .await
+        .context("Failed to run scrot. Is it installed?")?;
+
+    Ok(path)
+}
+
+// The root window over the X connection, without a helper program. Only
+// 24 and 32 bit visuals in little-endian BGRX are supported.
+fn capture_native(path: &Path) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let screen = &conn.setup().roots[screen_num];
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let reply = conn.get_image(ImageFormat::Z_PIXMAP, screen.root, 0, 0, width, height, !0)?.reply()
+        .context("Failed to read the screen")?;
+
+    let pixels = width as usize * height as usize;
+    if !matches!(reply.depth, 24 | 32) || reply.data.len() < pixels * 4 {
+        return Err(anyhow!("Unsupported screen format, depth {}; use CaptureBackend::Scrot", reply.depth));
+    }
+    let rgb = reply.data.chunks_exact(4)
+        .take(pixels)
+        .flat_map(|bgrx| [bgrx[2], bgrx[1], bgrx[0]])
+        .collect();
+    let image = RgbImage::from_raw(width as u32, height as u32, rgb)
+        .ok_or_else(|| anyhow!("Screen image has the wrong size"))?;
+    image.save(path)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
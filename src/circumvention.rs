@@ -0,0 +1,137 @@
+// Detection of attempts to get around a lock: a nested X server to work in,
+// tools that fake input to the lock window, unplugging or swapping monitors
+// while locked, and setting the clock
+//
+// Attempts are logged as "circumvention" events, count as offenses for
+// trust, and per the CIRCUMVENTION_* constants make the next lock longer
+// and alert the accountability partner.
+
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::Client;
+use std::collections::HashSet;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use tracing::{info, warn};
+
+use crate::constants::{
+    CIRCUMVENTION_ALERT_PARTNER, CIRCUMVENTION_LONGER_LOCK, CIRCUMVENTION_POLL_SECS, CIRCUMVENTION_TOOLS,
+    CLOCK_TAMPER_SECS, PARTNER_APPROVAL,
+};
+use crate::deadline;
+use crate::events;
+use crate::ipc;
+use crate::notify;
+use crate::partner;
+use crate::stats;
+use crate::types::Notification;
+
+pub const EVENT_KIND: &str = "circumvention";
+
+// Start watching for attempts
+pub fn spawn() {
+    tokio::spawn(watch());
+}
+
+// Monitor names and geometry, to notice unplugging and swapping
+type Monitors = Vec<(u32, i16, i16, u16, u16)>;
+
+fn monitors(conn: &RustConnection, root: u32) -> Result<Monitors> {
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+    Ok(reply.monitors.iter()
+        .map(|monitor| (monitor.name, monitor.x, monitor.y, monitor.width, monitor.height))
+        .collect())
+}
+
+// Processes running one of CIRCUMVENTION_TOOLS
+fn tools() -> Vec<(u32, String)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries.flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+            let name = name.trim();
+            CIRCUMVENTION_TOOLS.contains(&name).then(|| (pid, name.to_string()))
+        })
+        .collect()
+}
+
+fn locked() -> bool {
+    ipc::current_status().state.starts_with("locked")
+}
+
+async fn watch() {
+    let display = x11rb::connect(None)
+        .map_err(|e| warn!("Monitor changes won't be detected: {}", e))
+        .ok();
+    let client = Client::new();
+    let mut seen = HashSet::new();
+    // Monitors when the lock began
+    let mut locked_monitors: Option<Monitors> = None;
+    let mut last_clock = (Utc::now().timestamp_millis(), deadline::boottime());
+
+    let mut tick = tokio::time::interval(Duration::from_secs(CIRCUMVENTION_POLL_SECS));
+    loop {
+        tick.tick().await;
+
+        // Wall time running apart from boot time, which counts suspend and
+        // can't be set
+        let clock = (Utc::now().timestamp_millis(), deadline::boottime());
+        let drift = (clock.0 - last_clock.0) - (clock.1.saturating_sub(last_clock.1)).as_millis() as i64;
+        last_clock = clock;
+        if drift.unsigned_abs() > CLOCK_TAMPER_SECS * 1000 {
+            let direction = if drift > 0 { "forward" } else { "back" };
+            report(&client, &format!("clock set {} by {} minutes", direction, drift.unsigned_abs() / 60_000)).await;
+        }
+
+        if !locked() {
+            locked_monitors = None;
+            continue;
+        }
+
+        for (pid, name) in tools() {
+            if seen.insert(pid) {
+                report(&client, &format!("{} (pid {}) running during a lock", name, pid)).await;
+            }
+        }
+
+        if let Some((conn, screen)) = &display {
+            let root = conn.setup().roots[*screen].root;
+            match monitors(conn, root) {
+                Ok(current) => match &locked_monitors {
+                    Some(before) if *before != current => {
+                        report(&client, &format!("monitors changed during a lock, {} before and {} now",
+                                                 before.len(), current.len())).await;
+                        locked_monitors = Some(current);
+                    },
+                    Some(_) => {},
+                    None => locked_monitors = Some(current),
+                },
+                Err(e) => warn!("Failed to list monitors: {}", e),
+            }
+        }
+    }
+}
+
+async fn report(client: &Client, detail: &str) {
+    info!("Circumvention attempt: {}", detail);
+    if let Err(e) = events::log(EVENT_KIND, detail) {
+        warn!("Failed to log the circumvention attempt: {}", e);
+    }
+    notify::send(Notification::Alert, "perimedes: circumvention attempt", &format!("{}. This was logged.", detail));
+
+    if CIRCUMVENTION_LONGER_LOCK {
+        if let Err(e) = stats::record_lock() {
+            warn!("Failed to escalate the next lock: {}", e);
+        }
+    }
+    if let Some(partner) = PARTNER_APPROVAL.as_ref().filter(|_| CIRCUMVENTION_ALERT_PARTNER) {
+        if let Err(e) = partner::alert(client, partner, &format!("perimedes: circumvention attempt, {}.", detail)).await {
+            warn!("Failed to alert the partner: {}", e);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// Command line parsing
+
+use anyhow::{Result, anyhow};
+use std::time::Duration;
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::constants::PROFILES;
+use crate::logging;
+use crate::types::Profile;
+
+pub enum Command {
+    Run,
+    Stats,
+    Report { private: bool },
+    Status { watch: bool },
+    InstallService,
+    // Store the API key in the keyring
+    SetKey,
+    // Judge conversations as a JSONL chat dataset
+    Export,
+    // Problems in the compiled-in configuration
+    ConfigCheck,
+    // Label the latest verdict right or wrong
+    Feedback { correct: bool },
+    // Environment checks; the lock path only with --lock-test
+    Doctor { lock_test: bool },
+    // Timed lock started by the user; handed to the daemon if it runs
+    Lock { minutes: u64, reason: Option<String> },
+    // Companion process started by the daemon, see watchdog
+    Watchdog,
+    // Verify the tamper log and show when enforcement was off
+    Audit,
+    // Relay the active tab from the browser extension, see browser
+    NativeHost,
+    // Commands sent to the running daemon over the control socket
+    Control(String),
+}
+
+pub struct Args {
+    pub profile: Option<String>,
+    pub log_level: LevelFilter,
+    pub log_sensitive: bool,
+    pub command: Command,
+}
+
+const USAGE: &str = "usage: perimedes [--profile <name>] [--log-level <level>] [--log-sensitive] [stats | report [--private] | status [--watch] | install-service | set-key |
+                 config check | feedback wrong|right | doctor [--lock-test] | watchdog | audit | native-host | export [--format jsonl] [--kind judge] |
+                 pause <duration> | resume | lock-now | last-decision |
+                 lock <duration> [--reason <text>]]";
+
+pub fn parse() -> Result<Args> {
+    let mut profile = std::env::var("PERIMEDES_PROFILE").ok();
+    let mut command = Command::Run;
+    let mut log_level = LevelFilter::INFO;
+    let mut log_sensitive = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                profile = Some(args.next().ok_or_else(|| anyhow!("--profile needs a name\n{}", USAGE))?);
+            },
+            "--log-level" => {
+                let level = args.next().ok_or_else(|| anyhow!("--log-level needs a level\n{}", USAGE))?;
+                log_level = logging::parse_level(&level)?;
+            },
+            "--log-sensitive" => log_sensitive = true,
+            "stats" => command = Command::Stats,
+            "report" => command = Command::Report { private: false },
+            "--private" => match command {
+                Command::Report { .. } => command = Command::Report { private: true },
+                _ => return Err(anyhow!("--private is only valid for report\n{}", USAGE)),
+            },
+            "status" => command = Command::Status { watch: false },
+            "install-service" => command = Command::InstallService,
+            "set-key" => command = Command::SetKey,
+            "config" => match args.next().as_deref() {
+                Some("check") => command = Command::ConfigCheck,
+                // Editors get no schema to go by: there is no config file,
+                // constants.rs is type-checked by the compiler instead
+                Some("schema") => {
+                    return Err(anyhow!("config schema: there is no config file to describe; the configuration is src/constants.rs, compiled in and type-checked by rustc"));
+                },
+                _ => return Err(anyhow!("config needs a subcommand, e.g. check\n{}", USAGE)),
+            },
+            "feedback" => match args.next().as_deref() {
+                Some("wrong") => command = Command::Feedback { correct: false },
+                Some("right") => command = Command::Feedback { correct: true },
+                _ => return Err(anyhow!("feedback needs wrong or right\n{}", USAGE)),
+            },
+            "doctor" => command = Command::Doctor { lock_test: false },
+            "watchdog" => command = Command::Watchdog,
+            "audit" => command = Command::Audit,
+            // Browsers start the host with the manifest path and extension
+            // id (Firefox) or the extension's origin (Chromium)
+            arg if arg == "native-host" || arg.starts_with("chrome-extension://") || arg.ends_with(".json") => {
+                command = Command::NativeHost;
+                break;
+            },
+            "export" => command = Command::Export,
+            // Only one format and kind so far
+            "--format" | "--kind" => {
+                if !matches!(command, Command::Export) {
+                    return Err(anyhow!("{} is only valid for export\n{}", arg, USAGE));
+                }
+                let value = args.next().ok_or_else(|| anyhow!("{} needs a value\n{}", arg, USAGE))?;
+                if !matches!((arg.as_str(), value.as_str()), ("--format", "jsonl") | ("--kind", "judge")) {
+                    return Err(anyhow!("Unsupported {} '{}'\n{}", arg, value, USAGE));
+                }
+            },
+            "--lock-test" => match command {
+                Command::Doctor { .. } => command = Command::Doctor { lock_test: true },
+                _ => return Err(anyhow!("--lock-test is only valid for doctor\n{}", USAGE)),
+            },
+            "pause" => {
+                let duration = args.next().ok_or_else(|| anyhow!("pause needs a duration, e.g. 30m\n{}", USAGE))?;
+                parse_duration(&duration)?;
+                command = Command::Control(format!("pause {}", duration));
+            },
+            "lock" => {
+                let duration = args.next().ok_or_else(|| anyhow!("lock needs a duration, e.g. 45m\n{}", USAGE))?;
+                let minutes = lock_minutes(parse_duration(&duration)?);
+                command = Command::Lock { minutes, reason: None };
+            },
+            "--reason" => match &mut command {
+                Command::Lock { reason, .. } => {
+                    *reason = Some(args.next().ok_or_else(|| anyhow!("--reason needs a text\n{}", USAGE))?);
+                },
+                _ => return Err(anyhow!("--reason is only valid for lock\n{}", USAGE)),
+            },
+            "resume" | "lock-now" | "last-decision" => command = Command::Control(arg.clone()),
+            "--watch" | "-w" => match command {
+                Command::Status { .. } => command = Command::Status { watch: true },
+                _ => return Err(anyhow!("--watch is only valid for status\n{}", USAGE)),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            _ => return Err(anyhow!("Unknown argument '{}'\n{}", arg, USAGE)),
+        }
+    }
+
+    Ok(Args { profile, log_level, log_sensitive, command })
+}
+
+// Look up a profile by name, defaulting to the first configured one
+pub fn resolve_profile(name: Option<&str>) -> Result<&'static Profile> {
+    match name {
+        Some(name) => PROFILES.iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("Unknown profile '{}'", name)),
+        None => PROFILES.first().ok_or_else(|| anyhow!("No profiles configured")),
+    }
+}
+
+// Parse durations like "90s", "45m", "2h"; a bare number means minutes
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| anyhow!("Invalid duration '{}'", text))?;
+
+    let seconds = match unit {
+        "s" => number,
+        "" | "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => return Err(anyhow!("Invalid duration '{}', use s, m or h", text)),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+// Whole minutes for the lock timer, rounding up and at least one
+pub fn lock_minutes(duration: Duration) -> u64 {
+    duration.as_secs().div_ceil(60).max(1)
+}
@@ -0,0 +1,150 @@
+// `perimedes config check`: the compiled-in configuration of constants.rs,
+// checked for values that compile but can't work, each named by its constant
+//
+// Most of these would otherwise only surface at the first lock, e.g. a
+// misspelled schedule entry or a persona selection naming no persona.
+// There is no `config schema`: without a config file there is nothing for
+// editor tooling to validate, and the types of constants.rs already are
+// the schema.
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::path::Path;
+
+use crate::constants::{
+    ACCURACY_THRESHOLD, CALL_WINDOWS, ENFORCEMENT, HANDOFF_URL, HARD_BLOCKS, LOCK_ESCALATION, LOCK_TRIGGER, MAX_LOCK_MINUTES,
+    MIN_CONFIDENCE, MIN_LOCK_MINUTES, MODEL_PRICES, OCR_REDACT_PATTERNS, PERSONAS, PERSONA_SELECTION, PROFILES,
+    SCRIPT_POLICY, WASM_POLICY,
+};
+use crate::calendar;
+use crate::persona;
+use crate::policy;
+use crate::prompts;
+use crate::schedule;
+use crate::types::{Action, LockTrigger, PersonaSelection};
+
+// Everything wrong with the configuration, as "CONSTANT[index]: problem"
+pub fn problems() -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if PROFILES.is_empty() {
+        problems.push("PROFILES: no profiles configured".to_string());
+    }
+    for (i, profile) in PROFILES.iter().enumerate() {
+        if PROFILES[..i].iter().any(|other| other.name == profile.name) {
+            problems.push(format!("PROFILES[{}]: duplicate name '{}'", i, profile.name));
+        }
+        if profile.daily_budget_usd < 0.0 {
+            problems.push(format!("PROFILES[{}]: negative daily budget", i));
+        }
+        for model in [profile.classify_model, profile.judge_model] {
+            if !MODEL_PRICES.iter().any(|(name, _, _)| *name == model) {
+                problems.push(format!("PROFILES[{}]: no MODEL_PRICES entry for '{}', costs will be guessed", i, model));
+            }
+        }
+    }
+
+    for (i, persona) in PERSONAS.iter().enumerate() {
+        if PERSONAS[..i].iter().any(|other| other.name == persona.name) {
+            problems.push(format!("PERSONAS[{}]: duplicate name '{}'", i, persona.name));
+        }
+        if persona.temperature.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+            problems.push(format!("PERSONAS[{}]: temperature must be between 0 and 1", i));
+        }
+        if let Some(journal) = persona.journal.filter(|journal| !Path::new(&persona::journal_path(journal)).exists()) {
+            problems.push(format!("PERSONAS[{}]: journal '{}' doesn't exist", i, journal));
+        }
+    }
+    match PERSONA_SELECTION {
+        PersonaSelection::Fixed(name) if !PERSONAS.iter().any(|persona| persona.name == name) => {
+            problems.push(format!("PERSONA_SELECTION: no persona named '{}'", name));
+        },
+        _ if PERSONAS.is_empty() => problems.push("PERSONAS: no personas configured".to_string()),
+        _ => {},
+    }
+
+    if MIN_LOCK_MINUTES > MAX_LOCK_MINUTES {
+        problems.push("MIN_LOCK_MINUTES: larger than MAX_LOCK_MINUTES".to_string());
+    }
+    if LOCK_ESCALATION.windows(2).any(|pair| pair[1] < pair[0]) {
+        problems.push("LOCK_ESCALATION: factors should not decrease".to_string());
+    }
+    match LOCK_TRIGGER {
+        LockTrigger::Consecutive(0) => problems.push("LOCK_TRIGGER: needs at least one verdict".to_string()),
+        LockTrigger::Majority { window, needed } if needed == 0 || needed > window => {
+            problems.push("LOCK_TRIGGER: needed must be between 1 and the window".to_string());
+        },
+        _ => {},
+    }
+    if !ENFORCEMENT.iter().any(|step| matches!(step.action, Action::LockChat | Action::TimedLock(_))) {
+        problems.push("ENFORCEMENT: no step locks the screen".to_string());
+    }
+    for (i, step) in ENFORCEMENT.iter().enumerate() {
+        if step.max_repeats.is_some_and(|max| max < step.min_repeats) {
+            problems.push(format!("ENFORCEMENT[{}]: max_repeats below min_repeats, the step never runs", i));
+        }
+    }
+    if MIN_CONFIDENCE.is_some_and(|confidence| confidence > 100) {
+        problems.push("MIN_CONFIDENCE: must be between 0 and 100".to_string());
+    }
+    if !(0.0..=1.0).contains(&ACCURACY_THRESHOLD) {
+        problems.push("ACCURACY_THRESHOLD: must be between 0 and 1".to_string());
+    }
+
+    for (i, block) in HARD_BLOCKS.iter().enumerate() {
+        if block.hour > 23 || block.minute > 59 {
+            problems.push(format!("HARD_BLOCKS[{}]: invalid time {}:{:02}", i, block.hour, block.minute));
+        }
+        if block.minutes == 0 {
+            problems.push(format!("HARD_BLOCKS[{}]: zero length", i));
+        }
+    }
+    if let Err(e) = schedule::CONFIGURED.check() {
+        problems.push(format!("{:#}", e));
+    }
+    if let Err(e) = calendar::meeting_pattern() {
+        problems.push(format!("{:#}", e));
+    }
+    if let Some(Err(e)) = HANDOFF_URL.map(reqwest::Url::parse) {
+        problems.push(format!("HANDOFF_URL: {}", e));
+    }
+
+    for (i, pattern) in CALL_WINDOWS.iter().enumerate() {
+        if let Err(e) = Regex::new(pattern) {
+            problems.push(format!("CALL_WINDOWS[{}]: {}", i, e));
+        }
+    }
+    for (i, pattern) in OCR_REDACT_PATTERNS.iter().enumerate() {
+        if let Err(e) = Regex::new(pattern) {
+            problems.push(format!("OCR_REDACT_PATTERNS[{}]: {}", i, e));
+        }
+    }
+    if let Err(e) = prompts::check() {
+        problems.push(format!("{:#}", e));
+    }
+    if let Err(e) = policy::Rules::load() {
+        problems.push(format!("{:#}", e));
+    }
+    if let Some(Err(e)) = SCRIPT_POLICY.map(|path| policy::Script::load(Path::new(path))) {
+        problems.push(format!("SCRIPT_POLICY: {:#}", e));
+    }
+    if let Some(Err(e)) = WASM_POLICY.map(|path| policy::Policy::load(Path::new(path))) {
+        problems.push(format!("WASM_POLICY: {:#}", e));
+    }
+
+    problems
+}
+
+pub fn check() -> Result<()> {
+    let problems = problems();
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    match problems.len() {
+        0 => {
+            println!("Configuration ok");
+            Ok(())
+        },
+        n => Err(anyhow!("{} problem{} in the configuration", n, if n == 1 { "" } else { "s" })),
+    }
+}
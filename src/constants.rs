@@ -1,35 +1,469 @@
 // Constants shared across multiple modules
 
-// X11 font options (uncomment the one you want)
-pub const FONT_NAME: &str = "-misc-fixed-medium-r-normal--18-180-75-75-c-90-iso8859-1"; // Large (18px)
-// pub const FONT_NAME: &str = "-misc-fixed-medium-r-normal--15-150-75-75-c-80-iso8859-1"; // Medium (15px)
-// pub const FONT_NAME: &str = "-misc-fixed-medium-r-normal--13-120-75-75-c-70-iso8859-1"; // Small (13px, original)
-
-// Colors for the lock screen
-pub const BG_COLOR: u32 = 0x282828; // Dark gray background
-pub const TEXT_COLOR: u32 = 0xebdbb2; // Light text color
-pub const SYSTEM_COLOR: u32 = 0xfabd2f; // Yellow for system messages
-pub const USER_COLOR: u32 = 0x83a598; // Blue for user messages
-pub const ASSISTANT_COLOR: u32 = 0xb8bb26; // Green for assistant messages
+use crate::types::{
+    Action, CaptureBackend, ContextReset, EnforcementStep, HardBlock, LockTrigger, Notification, OfflinePolicy,
+    Category, CategoryPolicy, LockReason, MeetingMode, OnboardingPhase, OutsideSchedule, PartnerApproval, Rule, Persona, PhoneApproval, PersonaSelection, Profile,
+    Theme, TrustLevel,
+};
+
+// TrueType font, resolved through fontconfig (`fc-match`). Used for all
+// text when available, so that non-ASCII replies render correctly.
+pub const FONT_FAMILY: &str = "monospace";
+
+// Fallback X11 core fonts, tried in order. If none of them can be opened,
+// text is drawn as one box per character so the lock still appears.
+pub const CORE_FONTS: &[&str] = &[
+    "-misc-fixed-medium-r-normal--18-180-75-75-c-90-iso8859-1", // Large (18px)
+    "-misc-fixed-medium-r-normal--15-150-75-75-c-80-iso8859-1", // Medium (15px)
+    "-misc-fixed-medium-r-normal--13-120-75-75-c-70-iso8859-1", // Small (13px, original)
+    "9x15",
+    "fixed",
+];
+
+// Colors, font size, layout and message prefixes of the lock screen, the
+// lock timer and the other windows: Theme::DARK or Theme::LIGHT, or a preset
+// with some fields changed, e.g.
+//   Theme { font_size: 24.0, input_position: InputPosition::Top, ..Theme::DARK }
+pub const THEME: Theme = Theme::DARK;
+
+// Show a blurred, darkened version of the screenshot that triggered the lock
+// behind the chat. Disable for privacy in shared spaces.
+pub const SHOW_SCREENSHOT_BACKGROUND: bool = true;
+pub const BACKGROUND_BLUR_FACTOR: u32 = 24; // Downscale factor; higher is blurrier
+pub const BACKGROUND_DIM: f32 = 0.75; // Share of the background color mixed into the image
+
+// Messages recalled with Up/Down in the lock chat. Persisting keeps them
+// across locks, in the state directory.
+pub const INPUT_HISTORY_SIZE: usize = 50;
+pub const PERSIST_INPUT_HISTORY: bool = false;
+
+// Fade-in of the lock screen when a compositor is running; 0 disables
+pub const FADE_IN_MS: u64 = 400;
+
+// API key sources, tried in this order: the output of API_KEY_COMMAND if set,
+// e.g. Some(&["pass", "show", "anthropic"]), the keyring entry under
+// KEYRING_SERVICE stored by `perimedes set-key`, then $ANTHROPIC_API_KEY
+pub const API_KEY_COMMAND: Option<&[&str]> = None;
+pub const KEYRING_SERVICE: &str = "perimedes";
 
 // API constants
 pub const API_URL: &str = "https://api.anthropic.com/v1/messages";
 pub const MAX_MESSAGES: usize = 4;
 pub const MIN_LOCK_MINUTES: u64 = 1;
 pub const MAX_LOCK_MINUTES: u64 = 10;
+// Repeat offenses: the nth judge lock within LOCK_ESCALATION_HOURS scales
+// MIN_LOCK_MINUTES and MAX_LOCK_MINUTES by the nth factor, the last one
+// repeating; &[1] keeps the range fixed
+pub const LOCK_ESCALATION: &[u64] = &[1, 2, 3, 5];
+pub const LOCK_ESCALATION_HOURS: u64 = 3;
+// Longest allowance the judge may grant with 'unlock_for'
+pub const MAX_ALLOWANCE_MINUTES: u64 = 30;
+// Without a key press in the lock chat for this long, e.g. after walking
+// away, the chat ends as if MAX_MESSAGES ran out: a lock for the shortest
+// time the judge could give, and then the timer
+pub const CHAT_INACTIVITY_SECS: Option<u64> = Some(300);
 
 pub const SCREENSHOT_INTERVAL_SECS: u64 = 10;
+// Estimated tokens of screen context per request; older captures beyond it
+// are dropped, or summarized if SUMMARIZE_OMITTED_CONTEXT is set
+pub const CONTEXT_TOKEN_BUDGET: usize = 6000;
+pub const SUMMARIZE_OMITTED_CONTEXT: bool = false;
+// Captures whose dHash differs from the previous one in at most this many
+// of 64 bits count as unchanged and are not OCRed; negative disables
+pub const DEDUP_MAX_DISTANCE: i32 = 3;
+// OCR results of this many distinct captures are kept, keyed by a hash of
+// the pixels, and reused for identical captures; 0 disables the cache
+pub const OCR_CACHE_MAX_ENTRIES: usize = 2000;
+// Captures are OCRed as a grid of (columns, rows) tiles, of which only the
+// changed ones are OCRed again; (1, 1) OCRs the whole capture at once. More
+// than one column splits lines across tiles.
+pub const OCR_TILE_GRID: (u32, u32) = (1, 8);
+// Vision mode: the classifier also gets the last VISION_FRAMES changed
+// captures, each scaled to VISION_FRAME_WIDTH pixels wide and tiled into one
+// image of at most VISION_MAX_SIDE pixels and VISION_MAX_BYTES; None sends
+// the text only
+pub const VISION_FRAMES: Option<usize> = None;
+// pub const VISION_FRAMES: Option<usize> = Some(4);
+pub const VISION_FRAME_WIDTH: u32 = 768;
+pub const VISION_MAX_SIDE: u32 = 1568;
+pub const VISION_MAX_BYTES: usize = 5 * 1024 * 1024;
+pub const VISION_PROMPT: &str = "The attached image shows my last captures, \
+oldest first, from left to right and top to bottom.";
+
+// Captures and OCR output are kept in a private temporary directory of at
+// most this size; files older than TEMP_STALE_MINUTES are swept at start
+pub const TEMP_MAX_MB: u64 = 200;
+pub const TEMP_STALE_MINUTES: u64 = 30;
+// Stop capturing after this long without keyboard or pointer input; 0 disables
+pub const IDLE_THRESHOLD_SECS: u64 = 300;
+// Lock the screen like a classic screen locker after this many minutes
+// without input, e.g. Some(10), until the account password (PAM_SERVICE) is
+// entered. Replaces slock or xss-lock, which would fight over the grabs.
+pub const IDLE_LOCK_MINUTES: Option<u64> = None;
 pub const API_CALL_INTERVAL_SECS: u64 = 60;
+// PROCRASTINATING verdicts the classifier is less sure of than this (0-100)
+// don't count towards a lock; a notification asks the user instead
+pub const MIN_CONFIDENCE: Option<u8> = Some(60);
+// After the judge unlocks, positive verdicts don't lock for this many
+// minutes; then a follow-up check holds the screen against what the user
+// said in the chat, and a broken promise locks without a nudge and counts
+// as an extra offense. None disables both.
+pub const UNLOCK_COOLDOWN_MINUTES: Option<u64> = Some(10);
 pub const UNLOCK_PHRASE: &str = "UNLOCK";
+// Minutes of detected procrastination tolerated per day, e.g. Some(45):
+// positive verdicts only count against it until it is spent, and only then
+// lead to warnings and locks. Reasons can have budgets of their own, e.g.
+// (LockReason::News, 20); the smaller applicable budget counts.
+pub const PROCRASTINATION_BUDGET_MINUTES: Option<u64> = None;
+pub const REASON_BUDGET_MINUTES: &[(LockReason, u64)] = &[];
+// Overrides of the classifier by the category it names, e.g.
+// (Category::Messaging, CategoryPolicy::Allow) or
+// (Category::Video, CategoryPolicy::Minutes(20)) for 20 minutes a day
+pub const CATEGORY_POLICIES: &[(Category, CategoryPolicy)] = &[];
+
+// Clear the 5-minute screen context when a lock ends; otherwise the same
+// screenshots can trigger the next lock right away
+pub const CONTEXT_RESET: ContextReset = ContextReset::Always;
+
+// Rhai policy script run before the classifier, see policy.rs
+// Local rules, decided before the policies below and the classifier: the
+// first matching Allow or Deny decides, otherwise the weights of all
+// matching rules add up and decide once they reach RULE_WEIGHT_THRESHOLD
+// either way. Anything else goes to the classifier. E.g.:
+// Rule { class: Some("(?i)^(code|emacs|jetbrains-)"), title: None, workspace: None, url: None, text: None, action: RuleAction::Allow },
+// Rule { class: None, title: None, workspace: None, url: Some("^https://(www\\.)?(amazon|ebay)\\."), text: None, action: RuleAction::Deny(LockReason::Shopping) },
+// Rule { class: None, title: Some("(?i)youtube|twitch"), workspace: None, url: None, text: None, action: RuleAction::Weight(2) },
+// Rule { class: None, title: None, workspace: None, url: None, text: Some("(?i)\\bshorts\\b"), action: RuleAction::Weight(1) },
+// A url pattern only matches while the browser extension reports the tab,
+// a workspace pattern only under i3 and sway. Workspaces named after what
+// they're for can be always work or always leisure:
+// Rule { class: None, title: None, workspace: Some("(?i)thesis|work"), url: None, text: None, action: RuleAction::Allow },
+// Rule { class: None, title: None, workspace: Some("(?i)games|fun"), url: None, text: None, action: RuleAction::Deny(LockReason::Video) },
+pub const RULES: &[Rule] = &[];
+pub const RULE_WEIGHT_THRESHOLD: i32 = 3;
+
+// pub const SCRIPT_POLICY: Option<&str> = Some("/home/user/.config/perimedes/policy.rhai");
+pub const SCRIPT_POLICY: Option<&str> = None;
+pub const SCRIPT_MAX_OPERATIONS: u64 = 1_000_000;
+pub const SCRIPT_TIMEOUT_MS: u64 = 100;
+
+// WebAssembly decision policy run before the classifier, see policy.rs
+// pub const WASM_POLICY: Option<&str> = Some("/home/user/.config/perimedes/policy.wasm");
+pub const WASM_POLICY: Option<&str> = None;
+pub const POLICY_FUEL: u64 = 10_000_000; // Instruction budget per decision
+
+// Rolling classifier accuracy over labeled verdicts. Below the threshold,
+// perimedes stops locking and asks for re-calibration.
+pub const SCOREBOARD_WINDOW: usize = 50;
+pub const SCOREBOARD_MIN_LABELS: usize = 10;
+pub const ACCURACY_THRESHOLD: f64 = 0.7;
+
+// `perimedes report`: days covered, and the privacy parameters of --private.
+// Lower epsilon means more noise and more privacy.
+pub const REPORT_DAYS: usize = 14;
+pub const REPORT_EPSILON: f64 = 0.5;
+pub const REPORT_ROUNDING: u64 = 5;
+
+// Verdicts needed before locking, so that a single noisy minute of OCR
+// doesn't trigger a lock
+pub const LOCK_TRIGGER: LockTrigger = LockTrigger::Consecutive(2);
+// pub const LOCK_TRIGGER: LockTrigger = LockTrigger::Majority { window: 5, needed: 3 };
+
+// Warning shown before a lock; 0 locks immediately. Pressing the contest
+// hotkey (Ctrl+Alt+C) during the warning skips that lock, but the next
+// positive verdict then locks without a warning.
+pub const GRACE_PERIOD_SECS: u64 = 60;
+pub const CONTEST_KEY: u32 = 0x63; // 'c'
+pub const CONTEST_KEY_NAME: &str = "Ctrl+Alt+C";
+
+// What happens once LOCK_TRIGGER fires: steps run in order, skipping those
+// whose conditions don't hold. A contested or successful nudge ends the
+// pipeline, as does a lock.
+pub const ENFORCEMENT: &[EnforcementStep] = &[
+    EnforcementStep { action: Action::Nudge, min_severity: 0, min_repeats: 0, max_repeats: None },
+    EnforcementStep { action: Action::LockChat, min_severity: 0, min_repeats: 0, max_repeats: None },
+];
+// E.g. only nudge the first time, and lock without a chat after that:
+// EnforcementStep { action: Action::Notify, min_severity: 0, min_repeats: 0, max_repeats: None },
+// EnforcementStep { action: Action::PauseMedia, min_severity: 0, min_repeats: 0, max_repeats: None },
+// EnforcementStep { action: Action::Nudge, min_severity: 0, min_repeats: 0, max_repeats: None },
+// EnforcementStep { action: Action::TimedLock(10), min_severity: 0, min_repeats: 1, max_repeats: None },
+// EnforcementStep { action: Action::LockChat, min_severity: 0, min_repeats: 0, max_repeats: None },
+// Or as a ladder that goes one rung up with every enforcement, except that
+// four positive verdicts in the window lock right away:
+// EnforcementStep { action: Action::Notify, min_severity: 0, min_repeats: 0, max_repeats: Some(0) },
+// EnforcementStep { action: Action::Banner, min_severity: 0, min_repeats: 1, max_repeats: Some(1) },
+// EnforcementStep { action: Action::LockChat, min_severity: 4, min_repeats: 0, max_repeats: Some(1) },
+// EnforcementStep { action: Action::LockChat, min_severity: 0, min_repeats: 2, max_repeats: Some(2) },
+// EnforcementStep { action: Action::TimedLock(15), min_severity: 0, min_repeats: 3, max_repeats: None },
+// The ladder starts over after LADDER_RESET_MINUTES without a positive
+// verdict; None starts it over at the first NOT PROCRASTINATING verdict.
+pub const LADDER_RESET_MINUTES: Option<u64> = None;
+pub const BANNER_SECS: u64 = 30;
+pub const BANNER_OPACITY: f32 = 0.85;
+
+// Media players are found over MPRIS: what's playing goes into the
+// captures, so music in the background isn't taken for watching videos, and
+// every lock pauses them
+pub const MEDIA_IN_CONTEXT: bool = true;
+pub const PAUSE_MEDIA_ON_LOCK: bool = true;
+
+// Desktop notifications to show, by category
+pub const NOTIFICATIONS: &[Notification] = &[
+    Notification::Warning,
+    Notification::LockEnd,
+    Notification::ApiError,
+    Notification::Budget,
+    Notification::Alert,
+    Notification::Clarification,
+    // Notification::Classification,
+    // Notification::Verdict,
+];
+
+// Daily snooze budget for `perimedes pause`, so pausing can't become the
+// new procrastination
+pub const MAX_PAUSES_PER_DAY: u32 = 2;
+pub const MAX_PAUSE_MINUTES_PER_DAY: u64 = 60;
+
+// Ask what the user intends to work on at the first activity of the day;
+// the answer goes into the classifier prompt's {intention} until midnight
+pub const MORNING_CHECK_IN: bool = false;
+pub const CHECK_IN_QUESTION: &str = "Good morning! What do you intend to work on today?";
+// Width and height of the check-in window
+pub const CHECK_IN_SIZE: (u16, u16) = (900, 160);
+pub const INTENTION_PROMPT: &str = "What I said this morning I'd work on today: {}\n\n";
+// Opens the screen context during a meeting with MeetingMode::Relax
+pub const MEETING_PROMPT: &str = "I'm in a meeting until {end}: {summary}. Video calls, \
+chat apps and shared screens are part of it.\n\n";
+
+// Phases and how many days each lasts, from the first run on; the full
+// policy applies after the last. Explicit lock-now requests aren't softened.
+pub const ONBOARDING: &[(OnboardingPhase, u64)] = &[];
+// E.g. a week of each before the full policy:
+// pub const ONBOARDING: &[(OnboardingPhase, u64)] = &[
+//     (OnboardingPhase::Observe, 7),
+//     (OnboardingPhase::NudgesOnly, 7),
+//     (OnboardingPhase::ShortLocks(3), 7),
+// ];
+
+// Monitoring eases as the user goes longer without a lock triggered by a
+// verdict or an emergency bypass; each offense resets to full monitoring.
+// Empty always monitors fully.
+pub const TRUST_LEVELS: &[TrustLevel] = &[
+    TrustLevel { clean_days: 7, interval_factor: 2, metadata_only: false, extra_pauses: 1 },
+    TrustLevel { clean_days: 14, interval_factor: 3, metadata_only: false, extra_pauses: 2 },
+    TrustLevel { clean_days: 28, interval_factor: 5, metadata_only: true, extra_pauses: 3 },
+];
+
+// Hand running timed locks over to the other machines sharing this URL,
+// e.g. Some("https://dav.example.org/perimedes/handoff.json"); they check it
+// every HANDOFF_POLL_SECS and lock for the rest of the time
+pub const HANDOFF_URL: Option<&str> = None;
+pub const HANDOFF_POLL_SECS: u64 = 30;
+
+// Scheduled hard blocks, e.g. bedtime:
+// HardBlock { hour: 23, minute: 0, minutes: 8 * 60 }
+pub const HARD_BLOCKS: &[HardBlock] = &[];
+
+// Focus hours, e.g. "Mon-Fri 09:00-18:00" or "Sat 10:00-12:00,14:00-16:00";
+// monitoring and locking only happen within them. Empty means always.
+// Overrides replace the hours of a date ("2026-12-24 09:00-12:00" or
+// "2026-12-31 off"), holidays are named dates or ranges without focus hours:
+// ("Vacation", "2026-08-03..2026-08-14")
+pub const SCHEDULE: &[&str] = &[];
+pub const SCHEDULE_OVERRIDES: &[&str] = &[];
+pub const HOLIDAYS: &[(&str, &str)] = &[];
+pub const OUTSIDE_SCHEDULE: OutsideSchedule = OutsideSchedule::Idle;
+
+// Calendars as iCalendar files or http(s)/webcal URLs, see calendar.rs.
+// Events with attendees or matching MEETING_PATTERN are meetings, during
+// which screen-shared calls full of chat apps shouldn't lock.
+pub const CALENDARS: &[&str] = &[];
+pub const CALENDAR_REFRESH_MINUTES: u64 = 15;
+pub const MEETING_PATTERN: &str = "(?i)meeting|call|standup|sync|1:1|interview|zoom\\.us|meet\\.google|teams\\.microsoft";
+pub const MEETING_MODE: MeetingMode = MeetingMode::Suppress;
+
+// Live video calls keep the screen from locking, except when the user asks
+// for a lock, see call.rs: any window whose "class: title" matches one of
+// CALL_WINDOWS, or with CALL_MICROPHONE, any program recording audio
+pub const CALL_WINDOWS: &[&str] = &[
+    "(?i)^zoom: zoom (meeting|webinar)",
+    "(?i)\\bmeet [-–] [a-z]{3}-[a-z]{4}-[a-z]{3}\\b",
+    "(?i)jitsi meet",
+    "(?i)(meeting|call) .*\\| microsoft teams",
+    "(?i)^slack: .*huddle",
+];
+pub const CALL_MICROPHONE: bool = true;
+
+// Wind-down before a hard block: brightness falls from 1 to
+// WIND_DOWN_MIN_BRIGHTNESS along progress^WIND_DOWN_EXPONENT, so higher
+// exponents keep the screen bright for longer. 0 minutes disables it.
+pub const WIND_DOWN_MINUTES: u64 = 15;
+pub const WIND_DOWN_MIN_BRIGHTNESS: f32 = 0.3;
+pub const WIND_DOWN_EXPONENT: f32 = 2.0;
+
+// When the judge is unreachable during the lock chat, retry the API call
+// this many times before applying OFFLINE_POLICY
+pub const OFFLINE_MAX_FAILURES: u32 = 3;
+pub const OFFLINE_RETRY_SECS: u64 = 5;
+pub const OFFLINE_POLICY: OfflinePolicy = OfflinePolicy::TimedLock(5);
+// pub const OFFLINE_POLICY: OfflinePolicy = OfflinePolicy::LocalJudge;
+// pub const OFFLINE_POLICY: OfflinePolicy = OfflinePolicy::Bypass;
+
+// Case-insensitive patterns; the local judge keeps the screen locked if any
+// of them appear in the conversation, including the triggering screen text
+pub const OFFLINE_DISTRACTIONS: &[&str] = &[
+    "twitter.com", "x.com", "youtube", "reddit", "hacker news", "news.ycombinator",
+    "lesswrong", "forum.effectivealtruism", "lobste.rs",
+];
+pub const OFFLINE_LOCK_MINUTES: u64 = 5;
+
+// Emergency unlock on the lock and timer screens: the chord opens a password
+// prompt checked with PAM against this service. Uses are logged.
+pub const EMERGENCY_KEY: u32 = 0x65; // 'e'
+pub const EMERGENCY_KEY_NAME: &str = "Ctrl+Alt+E";
+pub const PAM_SERVICE: &str = "login";
+
+// Labels the latest verdict wrong from the lock chat, like `perimedes feedback wrong`
+pub const FEEDBACK_KEY: u32 = 0x77; // 'w'
+pub const FEEDBACK_KEY_NAME: &str = "Ctrl+Alt+W";
+// Set to have a partner approve emergency unlocks instead, e.g.
+// Some(PartnerApproval { request_url: "https://example.org/ask",
+//     status_url: "https://example.org/status/", timeout_minutes: 60 })
+pub const PARTNER_APPROVAL: Option<PartnerApproval> = None;
+pub const PARTNER_POLL_SECS: u64 = 10;
+
+// Circumvention attempts: these programs running during a lock, monitors
+// changing during a lock, or the clock jumping by more than CLOCK_TAMPER_SECS.
+// They are logged, and can count as a lock for LOCK_ESCALATION and be sent
+// to the partner's PARTNER_APPROVAL webhook.
+pub const CIRCUMVENTION_TOOLS: &[&str] = &["Xephyr", "Xnest", "Xvfb", "Xvnc", "xdotool", "ydotool", "xte"];
+pub const CLOCK_TAMPER_SECS: u64 = 120;
+pub const CIRCUMVENTION_POLL_SECS: u64 = 5;
+pub const CIRCUMVENTION_LONGER_LOCK: bool = true;
+pub const CIRCUMVENTION_ALERT_PARTNER: bool = true;
+// Set to require a tap on the phone before the partner or password, e.g.
+// Some(PhoneApproval { push: ApprovalPush::Ntfy("https://ntfy.sh/<topic>"),
+//     relay_url: "https://relay.example.org", listen_port: 8797, timeout_minutes: 10 })
+// The links are signed with $PERIMEDES_APPROVAL_SECRET.
+pub const PHONE_APPROVAL: Option<PhoneApproval> = None;
+
+// Telegram bot announcing locks to, and taking commands from, this chat.
+// The bot token is read from $TELEGRAM_BOT_TOKEN; None disables the bot.
+pub const TELEGRAM_CHAT_ID: Option<i64> = None;
+pub const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+pub const TELEGRAM_POLL_SECS: u64 = 30;
+
+// Input grab retries: the delay doubles after every failed attempt
+pub const GRAB_ATTEMPTS: u32 = 8;
+pub const GRAB_INITIAL_DELAY_MS: u64 = 50;
+
+// Programs known to hold keyboard/pointer grabs. If a grab fails, running
+// instances are reported, and terminated when KILL_CONFLICTING_GRABBERS is set.
+pub const CONFLICTING_GRABBERS: &[&str] = &[
+    "xscreensaver", "slock", "i3lock", "xsecurelock", "light-locker",
+    "xlock", "flameshot", "maim", "scrot", "rofi", "dmenu",
+];
+pub const KILL_CONFLICTING_GRABBERS: bool = false;
+
+// Keep lock chats that ended in a judge decision, for `perimedes export`.
+// They contain the screen context that triggered the lock.
+pub const SAVE_TRANSCRIPTS: bool = true;
+
+// Check at daemon start that the lock window, grabs, fonts and timer work,
+// like `perimedes doctor --lock-test`. Briefly grabs the input.
+pub const LOCK_SELF_TEST: bool = false;
+
+// systemd watchdog timeout of the installed user service. The main loop
+// pings at least every SCREENSHOT_INTERVAL_SECS plus the time a check takes.
+pub const WATCHDOG_SEC: u64 = 300;
+
+// Run `perimedes watchdog` alongside, so killing either process restarts it
+pub const WATCHDOG_COMPANION: bool = false;
+pub const WATCHDOG_RESTART_SECS: u64 = 2;
+
+// Daily log files kept in the state directory's logs/, besides stderr
+pub const LOG_FILES_KEPT: usize = 7;
+pub const LOG_TO_JOURNALD: bool = false;
+
+// How often the tamper log gets a sign of life, bounding how long an
+// unclean stop can go unnoticed in `perimedes audit`
+pub const TAMPER_HEARTBEAT_MINUTES: u64 = 60;
+
+// Applications that are never captured, matched case-insensitively against
+// the class part of WM_CLASS (see `xprop WM_CLASS`). Their captures are
+// replaced by a placeholder so the gaps stay explainable.
+pub const EXCLUDED_APPS: &[&str] = &[
+    "KeePassXC", "1Password", "Bitwarden", "Seahorse",
+];
+pub const EXCLUDED_PLACEHOLDER: &str = "[excluded application, not captured]";
+
+// Extra regexes for screen text that must never leave the machine, on top
+// of the built-in ones in redact.rs (keys, emails, card numbers, passwords)
+pub const OCR_REDACT_PATTERNS: &[&str] = &[
+    // r"(?i)internal-project-name",
+];
+
+// Lines of perimedes' own log output, dropped from OCR text
+pub const OWN_OUTPUT_MARKERS: &[&str] = &[
+    " DEBUG ", "Captured screen ", "Claude's response:",
+];
 
 pub const SCROT_CMD: &str = "scrot";
+pub const CAPTURE_BACKEND: CaptureBackend = CaptureBackend::Scrot;
 pub const OCR_CMD: &str = "tesseract-ocr";
+// With several monitors, each is OCRed on its own, up to OCR_WORKERS at a
+// time, and its text is headed by the monitor's name
+pub const PER_MONITOR_OCR: bool = true;
+pub const OCR_WORKERS: usize = 4;
 
 // Models
 pub const PROCRASTINATION_MODEL: &str = "claude-3-5-haiku-20241022";
 pub const JUDGE_MODEL: &str = "claude-3-5-haiku-20241022";
 
-// Prompts
+// Profiles, selected with `--profile <name>` or PERIMEDES_PROFILE.
+// The first entry is used when no profile is given. For two-stage checks,
+// give a cheap classify_model and a stronger confirm_model, e.g.
+// confirm_model: Some("claude-sonnet-4-20250514").
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "default",
+        classify_model: PROCRASTINATION_MODEL,
+        confirm_model: None,
+        judge_model: JUDGE_MODEL,
+        daily_budget_usd: 1.00,
+    },
+    Profile {
+        name: "evening",
+        classify_model: "claude-3-5-haiku-20241022",
+        confirm_model: None,
+        judge_model: "claude-3-5-haiku-20241022",
+        daily_budget_usd: 0.25,
+    },
+    Profile {
+        name: "deep-work",
+        classify_model: "claude-sonnet-4-20250514",
+        confirm_model: None,
+        judge_model: "claude-sonnet-4-20250514",
+        daily_budget_usd: 3.00,
+    },
+];
+
+// Prices in USD per million (input, output) tokens, used for spend tracking.
+// Unknown models are priced with the last entry.
+pub const MODEL_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-3-5-haiku-20241022", 0.80, 4.00),
+    ("claude-sonnet-4-20250514", 3.00, 15.00),
+    ("claude-opus-4-20250514", 15.00, 75.00),
+];
+
+// Spending limit of all profiles together per calendar month. Once it's
+// spent, captures are judged locally by RULES and OFFLINE_DISTRACTIONS and
+// the lock chat applies OFFLINE_POLICY without asking the judge.
+pub const MONTHLY_BUDGET_USD: Option<f64> = None;
+
+// Prompts; classify.md and judge.md in ~/.config/perimedes/prompts replace
+// the classifier and judge prompts, see prompts.rs
 pub const CHECK_PROCRASTINATION_PROMPT: &str = "Here is text extracted from my computer screen over the past 5 minutes. \
 Based only on this text, am I procrastinating or working productively? \
 First, reason through the content; common patterns of procrastination are: \
@@ -39,14 +473,86 @@ First, reason through the content; common patterns of procrastination are: \
 Non-cases of procrastination are: \
  \
 * Responding to WhatsApp/Telegram/Signal messages \
+* Music or podcasts playing in the background (listed under a capture's \
+heading) while the screen shows work \
  \
+Write a line starting with 'CATEGORY: ' and exactly one of social-media, \
+video, news, shopping, reading, messaging, work or other for what the screen \
+mostly shows. \
+If I am procrastinating, then write a line starting with 'MESSAGE: ' and \
+one sentence addressed to me that names concretely what I was doing and \
+for how long, e.g. 'MESSAGE: 18 minutes of r/rust comment threads.' \
+Then write a line starting with 'CONFIDENCE: ' and a number from 0 to 100 \
+saying how sure you are of your verdict. \
 Finally respond, in a single line, with either exactly 'PROCRASTINATING' \
 or exactly 'NOT PROCRASTINATING', depending on the previous \
-reasoning.\n\n{}";
+reasoning. If I listed my goals, judge the screen against them rather \
+than against the patterns above.\n\n{goals}{intention}{history}{screen_text}";
 
-pub const JUDGE_PROMPT: &str = "You are a productivity enforcer. Your job is to \
-decide whether to unlock the user's screen or keep it locked for another \
-1-10 minutes. The user's screen was locked because they were detected \
+// The {history} of the classifier prompt: the user's last FEEDBACK_EXAMPLES
+// labels of verdicts, one per line
+pub const FEEDBACK_EXAMPLES: usize = 10;
+pub const FEEDBACK_PROMPT: &str = "I told you whether some of your earlier verdicts \
+were right; judge similar screens the same way:\n{}\n\n";
+
+pub const SUMMARY_PROMPT: &str = "Here is text extracted from my computer screen, \
+captured every few seconds. Summarize in at most five short sentences what I \
+was doing: which applications and sites, and on what topics. Don't reason \
+about whether it is productive.\n\n{}";
+
+// Judge personas. The first one is used unless PERSONA_SELECTION says otherwise.
+pub const PERSONAS: &[Persona] = &[
+    Persona {
+        name: "enforcer",
+        prompt: "You are a productivity enforcer.",
+        model: None,
+        temperature: None,
+        journal: None,
+    },
+    Persona {
+        name: "coach",
+        prompt: "You are a stern but fair coach. You don't accept excuses, \
+keep your replies short, and hold the user to the goals they set.",
+        model: None,
+        temperature: Some(0.3),
+        journal: None,
+    },
+    Persona {
+        name: "therapist",
+        prompt: "You are a compassionate therapist. You are curious about \
+why the user drifted off, help them notice what they are avoiding, and \
+are kind without being a pushover.",
+        model: None,
+        temperature: Some(0.8),
+        journal: None,
+    },
+    Persona {
+        name: "past-self",
+        prompt: "You are the user's past self, speaking from these journal \
+entries they wrote about what they want from their life:\n\n{journal}\n\n\
+Hold them to what they wrote, in their own voice.",
+        model: None,
+        temperature: Some(0.7),
+        journal: Some("~/journal.txt"),
+    },
+];
+pub const PERSONA_SELECTION: PersonaSelection = PersonaSelection::Fixed("enforcer");
+// pub const PERSONA_SELECTION: PersonaSelection = PersonaSelection::Pick;
+// Journal paragraphs quoted to the past-self persona, picked at random
+pub const JOURNAL_EXCERPTS: usize = 5;
+
+// Locks listed in the {history} of the judge prompt
+pub const PROMPT_HISTORY_LOCKS: usize = 5;
+
+// Let the API cache the judge prompt and screen context, and the chat up to
+// the latest message, so each turn of the lock chat only pays full price
+// for what's new. Prefixes shorter than 1024 tokens aren't cached.
+pub const PROMPT_CACHING: bool = true;
+
+// Rules for the judge, appended to the persona's prompt
+pub const JUDGE_PROMPT: &str = "Your job is to \
+decide whether to unlock the user's screen or keep it locked for a number \
+of minutes in the range make_decision allows. The user's screen was locked because they were detected \
 to be procrastinating. Ask them about what they were doing and what they \
 intend to do if unlocked. \
 \
@@ -59,9 +565,21 @@ Non-cases of procrastination are: \
 * Responding to WhatsApp/Telegram/Signal messages \
 \
 The conversation will last at most 4 messages, after which you MUST make \
-a decision. If you decide to unlock, respond with exactly 'UNLOCK'. If \
-you decide to keep it locked, respond with 'LOCK:X' where X is a number \
-of minutes between 1 and 10.";
+a decision. Announce your decision only by calling the make_decision tool, \
+with action 'unlock' to unlock the screen, action 'unlock_for' with a \
+number of minutes (at most 30) and the declared purpose to grant a \
+time-boxed allowance, or action 'lock' and a number of minutes between 1 \
+and 10 to keep it locked. Prefer 'unlock_for' when the user asks for a \
+short, specific break. If the user listed their goals, judge what they \
+were doing and intend to do against them rather than against the \
+patterns above.\n\n{goals}";
+
+// The {goals} of the prompts, with the contents of ~/.config/perimedes/goals.md;
+// nothing without the file
+pub const GOALS_PROMPT: &str = "The current projects and priorities the user wrote down:\n{}\n\n";
+
+// Name of the tool the judge calls to decide
+pub const DECISION_TOOL: &str = "make_decision";
 
 // X11 keysym constants for special keys
 pub mod keysym {
@@ -69,4 +587,6 @@ pub mod keysym {
     pub const ENTER: u32 = 0xff0d;
     pub const ESCAPE: u32 = 0xff1b;
     pub const BACKSPACE: u32 = 0xff08;
+    pub const UP: u32 = 0xff52;
+    pub const DOWN: u32 = 0xff54;
 }
\ No newline at end of file
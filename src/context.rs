@@ -0,0 +1,108 @@
+// Building the screen context sent to the classifier and the judge
+//
+// Captures and notes are merged in time order. Lines repeated from the
+// previous capture are dropped, and the oldest entries are cut to stay
+// within CONTEXT_TOKEN_BUDGET; those can optionally be summarized by a
+// cheap model instead of being lost.
+
+use anyhow::{Result, Context as _};
+use reqwest::Client;
+use std::collections::{HashSet, VecDeque};
+use tracing::warn;
+
+use crate::api;
+use crate::constants::{API_URL, CONTEXT_TOKEN_BUDGET, SUMMARY_PROMPT};
+use crate::redact;
+use crate::stats;
+use crate::types::{AnthropicRequest, AnthropicResponse, ContextNote, Message, Profile, ScreenRecord};
+
+// Rough token count; about four characters per token for English text
+fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+// The record's text without the lines the previous capture already had
+fn without_repeats(record: &ScreenRecord, previous: Option<&ScreenRecord>) -> String {
+    let Some(previous) = previous else {
+        return record.text.clone();
+    };
+
+    let seen = previous.text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<HashSet<_>>();
+
+    let mut repeated = false;
+    let mut lines = Vec::new();
+    for line in record.text.lines() {
+        if seen.contains(line.trim()) {
+            repeated = true;
+        } else {
+            lines.push(line);
+        }
+    }
+    if repeated {
+        lines.push("[other lines unchanged since the previous capture]");
+    }
+
+    lines.join("\n")
+}
+
+// Context within the token budget, and the older entries that didn't fit;
+// metadata only leaves out the screen text, keeping times and windows
+pub fn build(records: &VecDeque<ScreenRecord>, notes: &VecDeque<ContextNote>, metadata_only: bool) -> (String, Vec<String>) {
+    let mut previous = None;
+    let mut entries = Vec::new();
+    for record in records {
+        let text = if metadata_only { String::new() } else { without_repeats(record, previous) };
+        entries.push((record.timestamp, record.format_with(&text)));
+        previous = Some(record);
+    }
+    entries.extend(notes.iter().map(|note| (note.timestamp, note.text.clone())));
+    entries.sort_by_key(|(timestamp, _)| *timestamp);
+
+    // Keep the newest entries that fit
+    let mut tokens = 0;
+    let mut split = entries.len();
+    while split > 0 {
+        let cost = estimate_tokens(&entries[split - 1].1);
+        if tokens + cost > CONTEXT_TOKEN_BUDGET && split < entries.len() {
+            break;
+        }
+        tokens += cost;
+        split -= 1;
+    }
+
+    let mut texts = entries.into_iter().map(|(_, text)| text).collect::<Vec<_>>();
+    let kept = texts.split_off(split);
+    (kept.join("\n\n"), texts)
+}
+
+// Digest of context entries that were cut, written by the classifier model
+pub async fn summarize(client: &Client, api_key: &str, profile: &Profile, omitted: &[String]) -> Result<String> {
+    let request = AnthropicRequest {
+        model: profile.classify_model.to_string(),
+        system: Vec::new(),
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: SUMMARY_PROMPT.replace("{}", &omitted.join("\n\n")),
+        }],
+        max_tokens: 200,
+        tools: Vec::new(),
+        temperature: None,
+        stream: false,
+    };
+
+    let response = api::post(client, API_URL, api_key, &request)
+        .send()
+        .await
+        .context("Failed to send request to Anthropic API")?;
+
+    let response_data: AnthropicResponse = response.json().await
+        .context("Failed to parse Anthropic API response")?;
+    if let Err(e) = stats::record_usage(profile, profile.classify_model, &response_data.usage) {
+        warn!("Failed to record API usage: {}", redact::scrub(&e.to_string()));
+    }
+
+    Ok(response_data.text())
+}
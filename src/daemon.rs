@@ -0,0 +1,1418 @@
+// The monitoring loop: capture, classify, and enforce
+
+use anyhow::Result;
+use chrono::Local;
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+use crate::capture::{active_window, is_excluded, without_title, ScreenCapturer, Scrot};
+use crate::constants::{
+    SCREENSHOT_INTERVAL_SECS, API_CALL_INTERVAL_SECS,
+    LOCK_TRIGGER, CONTEXT_RESET, IDLE_THRESHOLD_SECS, MIN_CONFIDENCE, IDLE_LOCK_MINUTES, UNLOCK_COOLDOWN_MINUTES,
+    DEDUP_MAX_DISTANCE, LOCK_SELF_TEST, HANDOFF_POLL_SECS, MORNING_CHECK_IN, CHECK_IN_QUESTION, OUTSIDE_SCHEDULE, WASM_POLICY, SCRIPT_POLICY, EXCLUDED_PLACEHOLDER, SUMMARIZE_OMITTED_CONTEXT,
+    OFFLINE_DISTRACTIONS, MONTHLY_BUDGET_USD, MEETING_MODE, MEETING_PROMPT, LADDER_RESET_MINUTES,
+};
+use crate::judge::{AnthropicJudge, Classification, ProcrastinationJudge};
+use crate::ocr::{OcrEngine, Tesseract};
+use crate::types::{
+    ScreenRecord, Category, LockReason, LockResult, Stage, Profile, LockTrigger, ContextReset, ContextNote, Notification,
+    MeetingMode, OnboardingPhase, OutsideSchedule,
+};
+use crate::{
+    browser, calendar, call, circumvention, context, deadline, dedup, enforcement, events, focus, handoff, idle, intention, ipc, locks, lockscreen, media, notify, onboarding, policy, prompts, redact, schedule, secrets, selftest,
+    service, stats, tamper, telegram, tempstore, transcripts, trust, verdicts, vision, watchdog, winddown,
+};
+
+// Grace after the judge unlocked, ending in a follow-up check
+struct Cooldown {
+    started: chrono::DateTime<Local>,
+    until: chrono::DateTime<Local>,
+    // What the user wrote in the lock chat
+    said: Option<String>,
+}
+
+// Recent verdicts, deciding when enough of them were positive to lock
+struct Detector {
+    verdicts: VecDeque<bool>,
+}
+
+impl Detector {
+    fn new() -> Detector {
+        Detector { verdicts: VecDeque::new() }
+    }
+
+    fn window(&self) -> usize {
+        match LOCK_TRIGGER {
+            LockTrigger::Consecutive(n) => n,
+            LockTrigger::Majority { window, .. } => window,
+        }
+    }
+
+    fn positives(&self) -> usize {
+        self.verdicts.iter().filter(|v| **v).count()
+    }
+
+    // Record a verdict and return whether a lock should be triggered
+    fn record(&mut self, procrastinating: bool) -> bool {
+        self.verdicts.push_back(procrastinating);
+        while self.verdicts.len() > self.window().max(1) {
+            self.verdicts.pop_front();
+        }
+
+        match LOCK_TRIGGER {
+            LockTrigger::Consecutive(n) => self.verdicts.len() >= n && self.positives() == self.verdicts.len(),
+            LockTrigger::Majority { needed, .. } => self.positives() >= needed,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.verdicts.clear();
+    }
+
+    fn progress(&self) -> String {
+        match LOCK_TRIGGER {
+            LockTrigger::Consecutive(n) => {
+                let streak = self.verdicts.iter().rev().take_while(|v| **v).count();
+                format!("{}/{} consecutive", streak, n)
+            },
+            LockTrigger::Majority { window, needed } => {
+                format!("{}/{} of the last {}", self.positives(), needed, window)
+            },
+        }
+    }
+}
+
+pub async fn run(profile: &'static Profile) -> Result<()> {
+    let api_key = secrets::api_key().await?;
+    let judge = AnthropicJudge::new(Client::new(), &api_key);
+
+    // A broken lock should show up now rather than at the first enforcement
+    if LOCK_SELF_TEST {
+        if let Err(e) = selftest::lock_path().await {
+            warn!("Lock self-test failed: {}", e);
+            notify::send(Notification::Alert, "perimedes lock self-test failed", &e.to_string());
+        }
+    }
+    if let Err(e) = tempstore::sweep() {
+        warn!("Failed to sweep temporary files: {}", e);
+    }
+    run_with(profile, &api_key, Scrot, Tesseract, judge).await
+}
+
+// What the capture task saw
+enum Observation {
+    Screen { record: Box<ScreenRecord>, screenshot: Option<PathBuf>, changed: bool },
+    // The user went idle, so captures stop
+    Away,
+    // The user was away from `since` until now
+    Returned { since: chrono::DateTime<Local> },
+}
+
+// Set by the classifier: whether to capture at all, and how often the
+// context was cleared, so that the first capture after that is taken in full
+#[derive(Clone, Copy)]
+struct Gate {
+    open: bool,
+    clears: u64,
+}
+
+// Work for the lock controller
+enum LockRequest {
+    Enforce(Trigger),
+    SelfLock { minutes: u64, reason: Option<String> },
+    CheckIn,
+}
+
+// A lock triggered by the verdicts or requested over the control socket
+struct Trigger {
+    combined_text: String,
+    lock_message: Option<String>,
+    reason: Option<LockReason>,
+    screenshot: Option<PathBuf>,
+    severity: usize,
+    repeats: u32,
+    skip_nudge: bool,
+    forced: bool,
+    // The verdict that triggered the lock, labeled by the outcome
+    verdict: Option<String>,
+}
+
+// What the lock controller reports back once the screen is free again
+enum LockReport {
+    // Records are the fresh captures taken to see whether the user stopped
+    Enforced {
+        outcome: enforcement::Outcome,
+        forced: bool,
+        verdict: Option<String>,
+        records: VecDeque<ScreenRecord>,
+    },
+    // Self-locks and scheduled blocks
+    Locked { started: chrono::DateTime<Local>, minutes: u64, what: &'static str },
+}
+
+// The monitoring loop over any capturer, OCR engine and classifier, as three
+// tasks: captures every SCREENSHOT_INTERVAL_SECS, classification every
+// API_CALL_INTERVAL_SECS, and a lock controller for warnings, locks and hard
+// blocks. Captures keep accumulating while a lock runs.
+pub async fn run_with(
+    profile: &'static Profile,
+    api_key: &str,
+    capturer: impl ScreenCapturer + Send + 'static,
+    ocr: impl OcrEngine + Send + 'static,
+    judge: impl ProcrastinationJudge,
+) -> Result<()> {
+    info!("Using profile '{}' (classifier: {}, judge: {})",
+             profile.name, profile.classify_model, profile.judge_model);
+
+    schedule::CONFIGURED.check()?;
+    prompts::check()?;
+    let control = ipc::serve().await?;
+    ipc::set_profile(profile.name);
+    ipc::set_state("monitoring");
+    ipc::budget_left(stats::procrastination_left()?);
+    stats::show_spend(profile)?;
+    onboarding::start()?;
+    let onboarding = onboarding::current()?;
+    if let Some(phase) = onboarding.describe() {
+        info!("Onboarding: {}", phase);
+    }
+    ipc::onboarding(onboarding.describe());
+    telegram::spawn();
+    circumvention::spawn();
+    calendar::spawn()?;
+    tamper::start(profile.name);
+    watchdog::spawn(profile);
+    redact::register_secret(api_key);
+
+    // Focused application and title, attached to every capture
+    let focus_monitor = focus::FocusMonitor::new()
+        .map_err(|e| warn!("Active window detection disabled: {}", e))
+        .ok()
+        .map(Arc::new);
+
+    // Deterministic policies consulted before the classifier
+    let rules = policy::Rules::load()?;
+    let script = SCRIPT_POLICY
+        .map(|path| policy::Script::load(std::path::Path::new(path)))
+        .transpose()?;
+    let policy = WASM_POLICY
+        .map(|path| policy::Policy::load(std::path::Path::new(path)))
+        .transpose()?;
+
+    let (observations_tx, observations) = mpsc::unbounded_channel();
+    let (requests_tx, requests) = mpsc::unbounded_channel();
+    let (reports_tx, reports) = mpsc::unbounded_channel();
+    let (gate_tx, gate) = watch::channel(Gate { open: true, clears: 0 });
+
+    // Dropping the set stops the tasks along with the loop
+    let mut tasks = JoinSet::new();
+    tasks.spawn(capture_loop(capturer, ocr, focus_monitor.clone(), gate, observations_tx));
+    tasks.spawn(lock_controller(
+        Client::new(), api_key.to_string(), profile, focus_monitor, requests, reports_tx,
+    ));
+
+    let mut classifier = Classifier {
+        profile,
+        api_key: api_key.to_string(),
+        client: Client::new(),
+        judge,
+        rules,
+        script,
+        policy,
+        gate: gate_tx,
+        requests: requests_tx,
+        records: VecDeque::new(),
+        notes: VecDeque::new(),
+        screenshot: None,
+        last_api_call: None,
+        last_contested: false,
+        detector: Detector::new(),
+        enforcements: 0,
+        productive_since: None,
+        enforcing: false,
+        lock_message: None,
+        lock_reason: None,
+        category: None,
+        stage: None,
+        observe_notified: false,
+        budget_notified: false,
+        monthly_budget_notified: false,
+        paused_until: None,
+        paused_since: Local::now(),
+        allowance: None,
+        cooldown: None,
+        follow_up: false,
+        changed_since_check: true,
+        last_verdict: None,
+        schedule: schedule::Status::Focus,
+        meeting: None,
+        away: false,
+        trust: trust::current_or_none(),
+        frames: vision::Frames::default(),
+        checked_in: String::new(),
+    };
+    info!("Trust: {}", classifier.trust.describe());
+
+    classifier.expire();
+    service::ready();
+
+    // The tasks only end on errors
+    tokio::select! {
+        result = classifier.run(observations, control, reports) => result,
+        Some(result) = tasks.join_next() => result?,
+    }
+}
+
+// Capture the screen every SCREENSHOT_INTERVAL_SECS, unless the gate is
+// closed, the user is away, or one of our own windows is showing
+async fn capture_loop(
+    mut capturer: impl ScreenCapturer,
+    mut ocr: impl OcrEngine,
+    focus_monitor: Option<Arc<focus::FocusMonitor>>,
+    gate: watch::Receiver<Gate>,
+    observations: mpsc::UnboundedSender<Observation>,
+) -> Result<()> {
+    // Captures are skipped while the user is away
+    let idle_monitor = if IDLE_THRESHOLD_SECS > 0 {
+        idle::IdleMonitor::new()
+            .map_err(|e| warn!("Idle detection disabled: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let mut idle_since: Option<chrono::DateTime<Local>> = None;
+
+    // Hash of the previous capture, for skipping unchanged screens, and the
+    // number of context clears it was taken after
+    let mut last_hash: Option<u64> = None;
+    let mut last_clears = 0;
+    // Text of the previous capture, repeated for unchanged screens so that
+    // a static screen keeps its text in the context once older records are
+    // pruned; the context drops the repeated lines anyway
+    let mut last_text: Option<String> = None;
+
+    let mut tick = time::interval(Duration::from_secs(SCREENSHOT_INTERVAL_SECS));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tick.tick().await;
+        service::watchdog();
+
+        let Gate { open, clears } = *gate.borrow();
+        if !open {
+            continue;
+        }
+
+        // Nothing to capture while the user is away
+        if let Some(monitor) = &idle_monitor {
+            match monitor.idle_time() {
+                Ok(idle_time) if idle_time.as_secs() >= IDLE_THRESHOLD_SECS => {
+                    if idle_since.is_none() {
+                        info!("Idle for {} minutes, suspending captures", idle_time.as_secs() / 60);
+                        observations.send(Observation::Away)?;
+                        idle_since = Some(Local::now() - chrono::Duration::from_std(idle_time)?);
+                    }
+                    continue;
+                },
+                Ok(_) if idle_since.is_some() => {
+                    info!("Activity detected, resuming captures");
+                    if let Some(since) = idle_since.take() {
+                        observations.send(Observation::Returned { since })?;
+                    }
+                },
+                Ok(_) => {},
+                Err(e) => warn!("Failed to query idle time: {}", e),
+            }
+        }
+
+        // Our own windows would end up in the context
+        if let Some(monitor) = &focus_monitor {
+            if monitor.own_window_visible().unwrap_or(false) {
+                continue;
+            }
+        }
+
+        // Applications on the exclusion list are never captured
+        let window = active_window(focus_monitor.as_deref());
+        let (screenshot, text, window, tab, changed) = match window {
+            Some(window) if is_excluded(&window) => {
+                info!("Excluded application in focus, not capturing");
+                last_hash = None;
+                (None, EXCLUDED_PLACEHOLDER.to_string(), Some(without_title(window)), None, true)
+            },
+            window => {
+                // 1. Take screenshot with scrot; a failed capture only costs this tick
+                let screenshot_path = match capturer.capture().await {
+                    Ok(path) => path,
+                    Err(e) => {
+                        warn!("Failed to capture the screen: {}", e);
+                        continue;
+                    },
+                };
+
+                // 2. OCR the screenshot with tesseract, unless the screen didn't change
+                let hash = if DEDUP_MAX_DISTANCE >= 0 {
+                    dedup::dhash(&screenshot_path)
+                        .map_err(|e| warn!("Failed to hash screenshot: {}", e))
+                        .ok()
+                } else {
+                    None
+                };
+                // A cleared context always starts with a full capture
+                let unchanged = last_text.is_some() && clears == last_clears && matches!((hash, last_hash), (Some(hash), Some(last))
+                    if dedup::distance(hash, last) as i32 <= DEDUP_MAX_DISTANCE);
+                last_hash = hash;
+                last_clears = clears;
+
+                let text = match last_text.as_ref().filter(|_| unchanged) {
+                    Some(text) => text.clone(),
+                    _ => match ocr.text(&screenshot_path).await {
+                        Ok(text) => {
+                            last_text = Some(text.clone());
+                            text
+                        },
+                        Err(e) => {
+                            warn!("Failed to OCR the capture: {}", e);
+                            tempstore::release(&screenshot_path);
+                            // The next capture mustn't count as unchanged from this one
+                            last_hash = None;
+                            continue;
+                        },
+                    },
+                };
+                let tab = browser::active(window.as_ref());
+                (Some(screenshot_path), text, window, tab, !unchanged)
+            },
+        };
+
+        let timestamp = Local::now();
+        let id = events::new_id();
+        info!("Captured screen {} at {}", id, timestamp.format("%H:%M:%S"));
+
+        // 3. Hand the record to the classifier
+        let media = media::playing().await;
+        let record = ScreenRecord { id, timestamp, text, window, tab, media };
+        observations.send(Observation::Screen { record: Box::new(record), screenshot, changed })?;
+    }
+}
+
+// Run warnings and locks for the classifier, self-locks requested over the
+// control socket, scheduled hard blocks and the idle lock
+async fn lock_controller(
+    client: Client,
+    api_key: String,
+    profile: &'static Profile,
+    focus_monitor: Option<Arc<focus::FocusMonitor>>,
+    mut requests: mpsc::UnboundedReceiver<LockRequest>,
+    reports: mpsc::UnboundedSender<LockReport>,
+) -> Result<()> {
+    // Restores the screen brightness when dropped
+    let mut dimmer: Option<winddown::Dimmer> = None;
+    let idle_lock = IDLE_LOCK_MINUTES.and_then(|minutes| {
+        idle::IdleMonitor::new()
+            .map(|monitor| (monitor, minutes))
+            .map_err(|e| warn!("Idle lock disabled: {}", e))
+            .ok()
+    });
+
+    let mut tick = time::interval(Duration::from_secs(SCREENSHOT_INTERVAL_SECS));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut handoff_tick = time::interval(Duration::from_secs(HANDOFF_POLL_SECS));
+    handoff_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The handed over lock last enforced, so it isn't enforced twice
+    let mut handed_over: Option<(String, i64)> = None;
+
+    // A lock the last run didn't finish, e.g. because it was killed
+    match deadline::load() {
+        Ok(Some(lock)) => {
+            let _keep_alive = service::keep_alive();
+            let minutes = lock.deadline.remaining().as_secs().div_ceil(60);
+            info!("Resuming the lock from before the restart, {} more minutes", minutes);
+            ipc::set_state(&format!("locked: {} minute timer, resumed", minutes));
+            let started = Local::now();
+            match lockscreen::resume_lock(lock).await {
+                Ok(()) => {
+                    let what = "screen locked by a lock resumed after a restart";
+                    reports.send(LockReport::Locked { started, minutes, what })?;
+                },
+                Err(e) => {
+                    error!("Error in resumed lock: {}", redact::scrub(&e.to_string()));
+                    ipc::set_state("monitoring");
+                },
+            }
+        },
+        Ok(None) => {},
+        Err(e) => warn!("Failed to read the lock from before the restart: {}", e),
+    }
+
+    loop {
+        tokio::select! {
+            Some(request) = requests.recv() => {
+                // Warning and lock block the controller for an open-ended time
+                let _keep_alive = service::keep_alive();
+                match request {
+                    LockRequest::Enforce(trigger) => {
+                        let mut records = VecDeque::new();
+                        let situation = enforcement::Situation {
+                            client: &client,
+                            api_key: &api_key,
+                            profile,
+                            focus_monitor: focus_monitor.as_deref(),
+                            combined_text: &trigger.combined_text,
+                            lock_message: trigger.lock_message.as_deref(),
+                            screenshot: trigger.screenshot.as_deref(),
+                            severity: trigger.severity,
+                            repeats: trigger.repeats,
+                            skip_nudge: trigger.skip_nudge,
+                            forced: trigger.forced,
+                            verdict: trigger.verdict.as_deref(),
+                            reason: trigger.reason,
+                        };
+                        let outcome = enforcement::run(&situation, &mut records).await;
+                        // The trigger owns its screenshot
+                        if let Some(screenshot) = &trigger.screenshot {
+                            tempstore::release(screenshot);
+                        }
+                        let outcome = outcome?;
+                        let (forced, verdict) = (trigger.forced, trigger.verdict);
+                        reports.send(LockReport::Enforced { outcome, forced, verdict, records })?;
+                    },
+                    LockRequest::SelfLock { minutes, reason } => {
+                        let started = Local::now();
+                        run_self_lock(minutes, reason.as_deref()).await;
+                        reports.send(LockReport::Locked { started, minutes, what: "screen self-locked" })?;
+                    },
+                    LockRequest::CheckIn => {
+                        let answer = lockscreen::check_in(CHECK_IN_QUESTION).await.unwrap_or_else(|e| {
+                            warn!("Failed to show the check-in: {}", e);
+                            None
+                        });
+                        if let Some(answer) = &answer {
+                            info!("Today's intention: {}", redact::sensitive(answer));
+                        }
+                        // An empty intention marks the check-in as done
+                        if let Err(e) = intention::save(answer.as_deref().unwrap_or("")) {
+                            warn!("Failed to save today's intention: {}", e);
+                        }
+                    },
+                }
+            },
+            _ = tick.tick() => {
+                // Locked first, so a block ending while away doesn't leave the screen open
+                let idle = match idle_lock.as_ref().filter(|(monitor, minutes)| idle_for(monitor, *minutes)) {
+                    Some((_, minutes)) if !call::inhibits("idle lock", focus_monitor.as_deref()).await => Some(*minutes),
+                    _ => None,
+                };
+                if let Some(minutes) = idle {
+                    let _keep_alive = service::keep_alive();
+                    info!("Idle for {} minutes, locking the screen", minutes);
+                    ipc::set_state("locked: idle");
+                    locks::started("idle lock", None, None);
+                    if let Err(e) = lockscreen::idle_lock().await {
+                        error!("Error in idle lock: {}", redact::scrub(&e.to_string()));
+                    }
+                    ipc::set_state("monitoring");
+                    continue;
+                }
+
+                // Scheduled hard blocks take precedence over pauses
+                match winddown::phase(Local::now()) {
+                    winddown::Phase::Free => dimmer = None,
+                    winddown::Phase::WindDown(progress) => {
+                        if dimmer.is_none() {
+                            info!("Winding down before a hard block");
+                            ipc::set_state("winding down");
+                            dimmer = winddown::Dimmer::new()
+                                .map_err(|e| warn!("Can't dim the screen: {}", e))
+                                .ok();
+                        }
+                        if let Some(dimmer) = &dimmer {
+                            if let Err(e) = dimmer.set_brightness(winddown::brightness(progress)) {
+                                warn!("Failed to dim the screen: {}", e);
+                            }
+                        }
+                    },
+                    // Starts once the call ends, for the rest of the block
+                    winddown::Phase::Blocked(_) if call::inhibits("hard block", focus_monitor.as_deref()).await => {},
+                    winddown::Phase::Blocked(minutes) => {
+                        dimmer = None;
+                        let _keep_alive = service::keep_alive();
+                        info!("Hard block for {} minutes", minutes);
+                        ipc::set_state(&format!("locked: {} minute hard block", minutes));
+                        locks::started(&format!("{} minute hard block", minutes), None, None);
+                        let started = Local::now();
+                        match lockscreen::display_lock_timer(minutes, Some("Scheduled block")).await {
+                            Ok(()) => {
+                                ipc::decision(&format!("hard block for {} minutes", minutes));
+                                notify::send(Notification::LockEnd, "perimedes", "The scheduled block has ended");
+                                let what = "screen locked by a scheduled block";
+                                reports.send(LockReport::Locked { started, minutes, what })?;
+                            },
+                            Err(e) => {
+                                error!("Error in hard block: {}", redact::scrub(&e.to_string()));
+                                ipc::set_state("monitoring");
+                            },
+                        }
+                    },
+                }
+            },
+            _ = handoff_tick.tick() => {
+                let handoff = match handoff::fetch().await {
+                    Ok(Some(handoff)) => handoff,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("{:#}", e);
+                        continue;
+                    },
+                };
+                let key = (handoff.host.clone(), handoff.until_ms);
+                if handed_over.as_ref() == Some(&key) || call::inhibits("handed over lock", focus_monitor.as_deref()).await {
+                    continue;
+                }
+                handed_over = Some(key);
+
+                let _keep_alive = service::keep_alive();
+                let deadline = handoff.deadline();
+                let minutes = deadline.remaining().as_secs().div_ceil(60);
+                info!("Lock handed over from {} for {} more minutes", handoff.host, minutes);
+                ipc::set_state(&format!("locked: handed over from {}", handoff.host));
+                locks::started(&format!("lock handed over from {}", handoff.host), None, None);
+                let label = handoff.label.unwrap_or_else(|| format!("Locked on {}", handoff.host));
+                let started = Local::now();
+                match lockscreen::display_handed_over_lock(deadline, &label).await {
+                    Ok(()) => {
+                        ipc::decision(&format!("handed over lock from {} for {} minutes", handoff.host, minutes));
+                        notify::send(Notification::LockEnd, "perimedes", "The handed over lock has ended");
+                        let what = "screen locked by a lock handed over from another machine";
+                        reports.send(LockReport::Locked { started, minutes, what })?;
+                    },
+                    Err(e) => {
+                        error!("Error in handed over lock: {}", redact::scrub(&e.to_string()));
+                        ipc::set_state("monitoring");
+                    },
+                }
+            },
+        }
+    }
+}
+
+fn idle_for(monitor: &idle::IdleMonitor, minutes: u64) -> bool {
+    match monitor.idle_time() {
+        Ok(idle_time) => idle_time.as_secs() >= minutes * 60,
+        Err(e) => {
+            warn!("Failed to query idle time: {}", e);
+            false
+        },
+    }
+}
+
+// Collects the captures into the screen context, classifies it, and asks
+// the lock controller to act on positive verdicts
+struct Classifier<J> {
+    profile: &'static Profile,
+    api_key: String,
+    client: Client,
+    judge: J,
+    rules: policy::Rules,
+    script: Option<policy::Script>,
+    policy: Option<policy::Policy>,
+    gate: watch::Sender<Gate>,
+    requests: mpsc::UnboundedSender<LockRequest>,
+
+    records: VecDeque<ScreenRecord>,
+    // Locks, pauses and idle stretches within the records' window
+    notes: VecDeque<ContextNote>,
+    // Recent changed captures, for vision mode
+    frames: vision::Frames,
+    // Day of the last morning check-in
+    checked_in: String,
+    // Latest screenshot, shown behind the lock chat; removed once replaced,
+    // or by the lock controller if handed over with a trigger
+    screenshot: Option<PathBuf>,
+    // The first check happens right away
+    last_api_call: Option<Instant>,
+
+    // A contested warning only postpones the lock until the next positive verdict
+    last_contested: bool,
+    detector: Detector,
+    // Enforcements since the last NOT PROCRASTINATING verdict
+    enforcements: u32,
+    // Since the last positive verdict, for starting the ladder over
+    productive_since: Option<chrono::DateTime<Local>>,
+    // Whether the lock controller is busy with one of our triggers
+    enforcing: bool,
+    // What the classifier said the user was doing, shown on the lock screen
+    lock_message: Option<String>,
+    lock_reason: Option<LockReason>,
+    // What the classifier last said the screen showed, and which step of
+    // the check decided
+    category: Option<Category>,
+    stage: Option<Stage>,
+    // Whether the user was told about observe-only mode, or the spent budgets
+    observe_notified: bool,
+    budget_notified: bool,
+    monthly_budget_notified: bool,
+
+    // Set over the control socket
+    paused_until: Option<chrono::DateTime<Local>>,
+    paused_since: chrono::DateTime<Local>,
+    // Allowance granted by the judge: no checks until then, and the purpose
+    // goes into the context afterwards
+    allowance: Option<(chrono::DateTime<Local>, u64, String)>,
+    // After the judge unlocked, positive verdicts don't lock until the
+    // follow-up check, whose positive verdict locks right away
+    cooldown: Option<Cooldown>,
+    follow_up: bool,
+
+    // For skipping checks of unchanged screens
+    changed_since_check: bool,
+    // With the classifier's confidence
+    last_verdict: Option<(bool, Option<u8>)>,
+    // Outside the focus hours, verdicts don't lock
+    schedule: schedule::Status,
+    // The meeting from CALENDARS going on, see MEETING_MODE
+    meeting: Option<calendar::Meeting>,
+    // Idle beyond IDLE_THRESHOLD_SECS, with captures suspended
+    away: bool,
+    // Eases monitoring, updated before every check
+    trust: trust::Trust,
+}
+
+impl<J: ProcrastinationJudge> Classifier<J> {
+    async fn run(
+        mut self,
+        mut observations: mpsc::UnboundedReceiver<Observation>,
+        mut control: mpsc::UnboundedReceiver<ipc::Control>,
+        mut reports: mpsc::UnboundedReceiver<LockReport>,
+    ) -> Result<()> {
+        let mut tick = time::interval(Duration::from_secs(SCREENSHOT_INTERVAL_SECS));
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            // A failed check or command mustn't end monitoring and lose the context
+            tokio::select! {
+                Some(observation) = observations.recv() => {
+                    if let Err(e) = self.observe(observation).await {
+                        warn!("{}", redact::scrub(&e.to_string()));
+                    }
+                },
+                Some(command) = control.recv() => {
+                    if let Err(e) = self.command(command).await {
+                        warn!("{}", redact::scrub(&e.to_string()));
+                    }
+                },
+                Some(report) = reports.recv() => self.report(report),
+                _ = tick.tick() => self.expire(),
+            }
+        }
+    }
+
+    async fn observe(&mut self, observation: Observation) -> Result<()> {
+        match observation {
+            Observation::Away => {
+                self.away = true;
+                if !self.enforcing {
+                    self.set_state();
+                }
+                return Ok(());
+            },
+            Observation::Returned { since } => {
+                self.away = false;
+                if !self.enforcing {
+                    self.set_state();
+                }
+                annotate(&mut self.notes, since, "user away");
+                return Ok(());
+            },
+            Observation::Screen { record, screenshot, changed } => {
+                self.check_in();
+                if let Some(path) = screenshot.as_ref().filter(|_| changed && vision::Frames::enabled()) {
+                    if let Err(e) = self.frames.push(record.timestamp, path) {
+                        warn!("Failed to keep the capture for vision: {}", e);
+                    }
+                }
+                self.records.push_back(*record);
+                if let Some(previous) = std::mem::replace(&mut self.screenshot, screenshot) {
+                    tempstore::release(&previous);
+                }
+                self.changed_since_check |= changed;
+            },
+        }
+
+        // Keep only the last 5 minutes of records
+        let five_minutes_ago = Local::now() - chrono::Duration::minutes(5);
+        while self.records.front().is_some_and(|record| record.timestamp < five_minutes_ago) {
+            self.records.pop_front();
+        }
+        while self.notes.front().is_some_and(|note| note.timestamp < five_minutes_ago) {
+            self.notes.pop_front();
+        }
+        self.frames.prune(five_minutes_ago);
+
+        // 4. Check if it's time to call the API (every minute)
+        let due = self.last_api_call
+            .is_none_or(|last| last.elapsed() >= self.check_interval());
+        if due && !self.enforcing && self.gate.borrow().open {
+            self.check(false).await?;
+        }
+        Ok(())
+    }
+
+    async fn command(&mut self, command: ipc::Control) -> Result<()> {
+        match command {
+            ipc::Control::Pause(duration) => {
+                let until = Local::now() + chrono::Duration::from_std(duration)?;
+                info!("Paused until {}", until.format("%H:%M"));
+                if self.paused_until.is_none() {
+                    self.paused_since = Local::now();
+                }
+                self.paused_until = Some(until);
+                self.update_gate();
+            },
+            ipc::Control::Resume => {
+                info!("Resumed");
+                if self.paused_until.take().is_some() {
+                    annotate(&mut self.notes, self.paused_since, "monitoring paused");
+                }
+                self.update_gate();
+            },
+            ipc::Control::LockNow if self.enforcing => info!("Already enforcing, ignoring the lock request"),
+            ipc::Control::LockNow => self.check(true).await?,
+            ipc::Control::Lock { minutes, reason } => {
+                self.requests.send(LockRequest::SelfLock { minutes, reason })?;
+            },
+        }
+        Ok(())
+    }
+
+    // Ask for the day's intention at the first capture of the day within the
+    // focus hours
+    fn check_in(&mut self) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        if !MORNING_CHECK_IN || self.checked_in == today || !self.gate.borrow().open {
+            return;
+        }
+        self.checked_in = today;
+        if !intention::asked_today() {
+            let _ = self.requests.send(LockRequest::CheckIn);
+        }
+    }
+
+    // The recent captures for vision mode, unless trust limits the
+    // classifier to window titles
+    fn tiled_frames(&self) -> Option<Vec<u8>> {
+        if self.trust.metadata_only() {
+            return None;
+        }
+        self.frames.tiled()
+            .map_err(|e| warn!("Sending the text only: {}", e))
+            .ok()
+            .flatten()
+    }
+
+    // Ask the classifier; with a confirm_model it screens only the latest
+    // capture, and the stronger model decides positive verdicts on the full
+    // context
+    async fn classify_in_stages(&mut self, profile: &Profile, combined_text: &str) -> Result<(Classification, Stage)> {
+        let frames = self.tiled_frames();
+        let Some(confirm_model) = profile.confirm_model else {
+            let classification = self.judge.classify(profile, combined_text, frames.as_deref()).await?;
+            return Ok((classification, Stage::Classifier));
+        };
+
+        let latest = self.records.back()
+            .map(|record| if self.trust.metadata_only() { record.format_with("") } else { record.format() })
+            .unwrap_or_default();
+        let screened = self.judge.classify(profile, &latest, None).await?;
+        if !screened.procrastinating {
+            return Ok((screened, Stage::Classifier));
+        }
+        info!("Screening flagged procrastination, confirming with {}", confirm_model);
+        let confirmed = self.judge.confirm(profile, combined_text, frames.as_deref()).await?;
+        Ok((confirmed, Stage::Confirm))
+    }
+
+    // Checks are further apart as trust grows
+    fn check_interval(&self) -> Duration {
+        Duration::from_secs(API_CALL_INTERVAL_SECS * self.trust.interval_factor())
+    }
+
+    // End pauses and allowances that ran out, and follow the focus hours
+    fn expire(&mut self) {
+        let status = schedule::status();
+        if status != self.schedule {
+            match &status {
+                schedule::Status::Focus => info!("Focus hours begin"),
+                schedule::Status::Off(Some(holiday)) => info!("{}, outside the focus hours", holiday),
+                schedule::Status::Off(None) => info!("Focus hours over"),
+            }
+            self.schedule = status;
+            self.detector.reset();
+            self.update_gate();
+        }
+
+        let meeting = calendar::current();
+        if meeting != self.meeting {
+            match &meeting {
+                Some(meeting) => info!("In a meeting until {}: {}", meeting.end.format("%H:%M"), meeting.summary),
+                None => info!("Meeting over"),
+            }
+            self.meeting = meeting;
+            if self.suppressed_by_meeting() {
+                self.detector.reset();
+            }
+            if !self.enforcing {
+                self.set_state();
+            }
+        }
+
+        if self.paused_until.is_some_and(|until| Local::now() >= until) {
+            info!("Pause over");
+            self.paused_until = None;
+            annotate(&mut self.notes, self.paused_since, "monitoring paused");
+            self.update_gate();
+        }
+
+        if let Some((until, minutes, purpose)) = &self.allowance {
+            if Local::now() >= *until {
+                info!("Allowance over, checking again");
+                self.notes.push_back(ContextNote {
+                    timestamp: Local::now(),
+                    text: format!(
+                        "[allowance of {} min ended here; the user was unlocked for: {}, and promised to get back to work after it]",
+                        minutes, purpose
+                    ),
+                });
+                self.allowance = None;
+                self.update_gate();
+            }
+        }
+
+        if let Some(cooldown) = self.cooldown.take_if(|cooldown| Local::now() >= cooldown.until) {
+            info!("Cooldown over, checking whether the user kept their word");
+            let said = match &cooldown.said {
+                Some(said) => format!("after the user said: {}", said),
+                None => "after a chat with the user".to_string(),
+            };
+            self.notes.push_back(ContextNote {
+                timestamp: Local::now(),
+                text: format!(
+                    "[follow-up: the judge unlocked the screen at {} {}; does what is on the screen since match that?]",
+                    cooldown.started.format("%H:%M"), said
+                ),
+            });
+            self.follow_up = true;
+            self.last_verdict = None;
+            self.last_api_call = None;
+        }
+    }
+
+    fn off_schedule(&self) -> bool {
+        matches!(self.schedule, schedule::Status::Off(_))
+    }
+
+    fn suppressed_by_meeting(&self) -> bool {
+        self.meeting.is_some() && matches!(MEETING_MODE, MeetingMode::Suppress)
+    }
+
+    // Captures stop during pauses and allowances, and outside the focus hours unless logging
+    fn update_gate(&self) {
+        let idle = self.off_schedule() && matches!(OUTSIDE_SCHEDULE, OutsideSchedule::Idle);
+        let open = self.paused_until.is_none() && self.allowance.is_none() && !idle;
+        self.gate.send_modify(|gate| gate.open = open);
+        if !self.enforcing {
+            self.set_state();
+        }
+    }
+
+    fn set_state(&self) {
+        match (&self.paused_until, &self.allowance) {
+            (Some(until), _) => ipc::set_state(&format!("paused until {}", until.format("%H:%M"))),
+            (None, Some((until, _, _))) => ipc::set_state(&format!("allowance until {}", until.format("%H:%M"))),
+            (None, None) if self.away => ipc::set_state("idle"),
+            (None, None) => match &self.schedule {
+                schedule::Status::Focus => match &self.meeting {
+                    Some(meeting) if self.suppressed_by_meeting() => {
+                        ipc::set_state(&format!("in a meeting until {}", meeting.end.format("%H:%M")));
+                    },
+                    _ => ipc::set_state("monitoring"),
+                },
+                schedule::Status::Off(Some(holiday)) => ipc::set_state(&format!("off schedule: {}", holiday)),
+                schedule::Status::Off(None) => ipc::set_state("off schedule"),
+            },
+        }
+    }
+
+    // Classify the screen context, or lock right away if forced
+    async fn check(&mut self, forced: bool) -> Result<()> {
+        let profile = self.profile;
+        let previous_check = self.last_api_call.replace(Instant::now());
+        let interval = self.check_interval().as_secs();
+        let since_check = previous_check.map_or(interval, |last| last.elapsed().as_secs().min(interval));
+
+        let trust = trust::current_or_none();
+        if trust.level.map(|level| level.clean_days) != self.trust.level.map(|level| level.clean_days) {
+            info!("Trust: {}", trust.describe());
+        }
+        self.trust = trust;
+
+        // Move to separate file
+        // Format all records with timestamps
+        let (mut combined_text, omitted) = context::build(&self.records, &self.notes, self.trust.metadata_only());
+        if !omitted.is_empty() {
+            info!("Context over budget, {} older entries omitted", omitted.len());
+        }
+        // An unreadable stats file doesn't stop the checks
+        let over_monthly_budget = stats::over_monthly_budget().unwrap_or_else(|e| {
+            warn!("Failed to check the monthly budget: {}", e);
+            false
+        });
+        let over_budget = stats::over_budget(profile).unwrap_or_else(|e| {
+            warn!("Failed to check the daily budget: {}", e);
+            false
+        });
+        if SUMMARIZE_OMITTED_CONTEXT && !omitted.is_empty() && !forced && !over_budget && !over_monthly_budget {
+            match context::summarize(&self.client, &self.api_key, profile, &omitted).await {
+                Ok(summary) => {
+                    combined_text = format!("--- Summary of earlier captures ---\n{}\n\n{}", summary, combined_text);
+                },
+                Err(e) => warn!("Failed to summarize older context: {}", redact::scrub(&e.to_string())),
+            }
+        }
+        if let (Some(meeting), MeetingMode::Relax) = (&self.meeting, MEETING_MODE) {
+            let prompt = MEETING_PROMPT
+                .replace("{end}", &meeting.end.format("%H:%M").to_string())
+                .replace("{summary}", &meeting.summary);
+            combined_text = format!("{}{}", prompt, combined_text);
+        }
+
+        // Skip the check once the profile's daily budget is spent
+        if !forced && over_budget {
+            info!("Daily budget of ${:.2} for profile '{}' exhausted, skipping check",
+                     profile.daily_budget_usd, profile.name);
+            if !self.budget_notified {
+                notify::send(
+                    Notification::Budget,
+                    "perimedes budget exhausted",
+                    &format!("The daily budget of ${:.2} for profile '{}' is spent, so the screen isn't checked.",
+                             profile.daily_budget_usd, profile.name),
+                );
+                self.budget_notified = true;
+            }
+            return Ok(());
+        }
+
+        let (is_procrastinating, confidence, verdict) = if forced {
+            info!("Lock requested over the control socket");
+            (true, None, None)
+        } else {
+            // An unchanged screen gets the same verdict as last time
+            let policy_verdict = run_policies(
+                &self.rules, self.script.as_ref(), self.policy.as_mut(), profile, &self.records, &combined_text, &self.detector
+            );
+
+            let (is_procrastinating, confidence) = match (policy_verdict, self.last_verdict) {
+                (Some((verdict, reason)), _) => {
+                    self.lock_message = None;
+                    self.lock_reason = reason;
+                    self.category = None;
+                    self.stage = Some(Stage::Policy);
+                    (verdict, None)
+                },
+                (None, Some(verdict)) if !self.changed_since_check => {
+                    info!("Screen unchanged since the last check, keeping the verdict");
+                    verdict
+                },
+                _ if over_monthly_budget => {
+                    if !self.monthly_budget_notified {
+                        warn!("Monthly budget of ${:.2} spent, judging captures locally", MONTHLY_BUDGET_USD.unwrap_or_default());
+                        notify::send(
+                            Notification::Budget,
+                            "perimedes monthly budget spent",
+                            "The monthly API budget is spent, so captures are judged by local rules until next month.",
+                        );
+                        self.monthly_budget_notified = true;
+                    }
+                    self.lock_message = None;
+                    self.lock_reason = None;
+                    self.category = None;
+                    self.stage = Some(Stage::Local);
+                    (local_verdict(&self.records), None)
+                },
+                _ => {
+                    self.monthly_budget_notified = false;
+                    ipc::set_state("checking");
+                    notify::send(Notification::Classification, "perimedes", "Checking your screen");
+                    match self.classify_in_stages(profile, &combined_text).await {
+                        Ok((classification, stage)) => {
+                            self.category = classification.category;
+                            self.stage = Some(stage);
+                            let allowed = classification.category.filter(|category| {
+                                stats::category_allowed(*category, since_check).unwrap_or_else(|e| {
+                                    warn!("Failed to check the category policy: {}", e);
+                                    false
+                                })
+                            });
+                            if let (Some(category), true) = (allowed, classification.procrastinating) {
+                                info!("{} is allowed by CATEGORY_POLICIES, not procrastinating", category.name());
+                                self.lock_message = None;
+                                self.lock_reason = None;
+                                (false, classification.confidence)
+                            } else {
+                                self.lock_message = classification.message;
+                                self.lock_reason = classification.reason;
+                                (classification.procrastinating, classification.confidence)
+                            }
+                        },
+                        // Monitoring goes on; the next check tries again
+                        Err(e) => {
+                            notify::send(Notification::ApiError, "perimedes: API error", &redact::scrub(&e.to_string()));
+                            warn!("Check failed: {}", redact::scrub(&e.to_string()));
+                            if !self.enforcing {
+                                self.set_state();
+                            }
+                            return Ok(());
+                        },
+                    }
+                }
+            };
+            self.budget_notified = false;
+            notify::send(
+                Notification::Verdict,
+                "perimedes",
+                if is_procrastinating { "Verdict: procrastinating" } else { "Verdict: not procrastinating" },
+            );
+            self.last_verdict = Some((is_procrastinating, confidence));
+            self.changed_since_check = false;
+            let capture = self.records.back().map(|record| record.id.as_str());
+            let window = self.records.back()
+                .and_then(|record| record.window.as_ref())
+                .map(|window| format!("{} \"{}\"", window.class, window.title));
+            let reason = if is_procrastinating { self.lock_reason } else { None };
+            let verdict = verdicts::record(profile.name, is_procrastinating, confidence, reason, self.category, self.stage, capture, window.as_deref())
+                .map_err(|e| warn!("Failed to record verdict: {}", e))
+                .ok();
+            (is_procrastinating, confidence, verdict)
+        };
+
+        // Unsure positive verdicts ask the user instead of counting towards a lock
+        let uncertain = is_procrastinating && !forced
+            && confidence.zip(MIN_CONFIDENCE).is_some_and(|(confidence, min)| confidence < min);
+        let lock_triggered = forced || (!uncertain && self.detector.record(is_procrastinating));
+        if is_procrastinating && !uncertain {
+            self.productive_since = None;
+        }
+
+        // A positive follow-up verdict means the user didn't do what they said
+        let broken_promise = std::mem::take(&mut self.follow_up) && is_procrastinating && !uncertain && !forced;
+        let lock_triggered = lock_triggered || broken_promise;
+
+        // Positive verdicts count the time since the last check against the daily budget
+        let budget_left = if is_procrastinating && !forced && !uncertain && !self.off_schedule() && !self.suppressed_by_meeting() {
+            let left = stats::spend_procrastination(since_check, self.lock_reason).unwrap_or_else(|e| {
+                warn!("Failed to count against the procrastination budget: {}", e);
+                None
+            });
+            ipc::budget_left(left);
+            left
+        } else {
+            None
+        };
+
+        // A classifier that is often wrong shouldn't lock; explicit requests still do
+        let poor_accuracy = if forced { None } else { verdicts::poor_accuracy()? };
+        let onboarding = onboarding::current()?;
+        ipc::onboarding(onboarding.describe());
+        let onboarding_observe = !forced && matches!(onboarding.phase, Some(OnboardingPhase::Observe));
+        if poor_accuracy.is_none() {
+            self.observe_notified = false;
+        }
+
+        // Output the result
+        if is_procrastinating && self.off_schedule() && !forced {
+            info!("PROCRASTINATING, but outside the focus hours");
+            self.detector.reset();
+        } else if is_procrastinating && self.suppressed_by_meeting() && !forced {
+            info!("PROCRASTINATING, but in a meeting");
+            self.detector.reset();
+        } else if uncertain {
+            info!("PROCRASTINATING, but only {}% sure, asking", confidence.unwrap_or_default());
+            let question = match &self.lock_message {
+                Some(message) => format!("Is this work? The classifier isn't sure: {}", message),
+                None => "Is this work? The classifier isn't sure what's on your screen is.".to_string(),
+            };
+            notify::send(Notification::Clarification, "perimedes isn't sure", &question);
+        } else if let (Some(left @ 1..), false) = (budget_left, broken_promise) {
+            let minutes = left.div_ceil(60);
+            info!("PROCRASTINATING, {} min of today's budget left", minutes);
+            notify::send(
+                Notification::Warning,
+                "Procrastination detected",
+                &format!("{} minutes of today's procrastination budget left.", minutes),
+            );
+        } else if is_procrastinating && self.cooldown.is_some() && !forced {
+            info!("PROCRASTINATING, but in the cooldown after the unlock");
+        } else if is_procrastinating && !lock_triggered {
+            info!("PROCRASTINATING ({}), not locking yet", self.detector.progress());
+        } else if let (true, Some(accuracy)) = (is_procrastinating, poor_accuracy) {
+            info!("PROCRASTINATING, but observing only: accuracy {:.0}%", accuracy * 100.0);
+            if !self.observe_notified {
+                notify::send(
+                    Notification::Alert,
+                    "perimedes is observing only",
+                    &format!("Only {:.0}% of recent verdicts were right, so it won't lock. \
+                              Label more verdicts to re-calibrate.", accuracy * 100.0),
+                );
+                self.observe_notified = true;
+            }
+            self.detector.reset();
+        } else if is_procrastinating && onboarding_observe {
+            info!("PROCRASTINATING, but observing only on day {} of onboarding", onboarding.day);
+            self.detector.reset();
+        } else if is_procrastinating {
+            info!("PROCRASTINATING");
+            if broken_promise {
+                info!("The follow-up check contradicts the lock chat, locking harder");
+                // Counting as a lock of its own, the next range escalates
+                if let Err(e) = stats::record_lock() {
+                    warn!("Failed to record the broken promise: {}", e);
+                }
+            }
+
+            // The lock controller reports back once the screen is free
+            self.requests.send(LockRequest::Enforce(Trigger {
+                combined_text,
+                lock_message: if forced { None } else { self.lock_message.clone() },
+                reason: if forced { None } else { self.lock_reason },
+                screenshot: self.screenshot.take(),
+                severity: self.detector.positives(),
+                repeats: self.enforcements,
+                skip_nudge: self.last_contested || forced || broken_promise,
+                forced,
+                verdict,
+            }))?;
+            self.enforcing = true;
+            return Ok(());
+        } else {
+            info!("NOT PROCRASTINATING");
+            self.last_contested = false;
+            let since = *self.productive_since.get_or_insert_with(Local::now);
+            let sustained = LADDER_RESET_MINUTES
+                .is_none_or(|minutes| Local::now() - since >= chrono::Duration::minutes(minutes as i64));
+            if sustained && self.enforcements > 0 {
+                info!("Back to work for a while, the enforcement ladder starts over");
+                self.enforcements = 0;
+            }
+        }
+        self.set_state();
+        Ok(())
+    }
+
+    fn report(&mut self, report: LockReport) {
+        let (outcome, forced, verdict) = match report {
+            LockReport::Locked { started, minutes, what } => {
+                self.reset_context(&LockResult::TimedLock(minutes));
+                annotate(&mut self.notes, started, what);
+                if !self.enforcing {
+                    self.set_state();
+                }
+                return;
+            },
+            LockReport::Enforced { outcome, forced, verdict, records } => {
+                self.records.extend(records);
+                (outcome, forced, verdict)
+            },
+        };
+        self.enforcing = false;
+        self.enforcements += 1;
+
+        match outcome {
+            enforcement::Outcome::Contested => {
+                info!("Lock contested, skipping this lock");
+                label(verdict.as_deref(), false);
+                self.last_contested = true;
+                self.set_state();
+                return;
+            },
+            enforcement::Outcome::BackToWork => {
+                info!("Back to work after the warning, not locking");
+                // The warning worked, so the verdict was presumably right
+                label(verdict.as_deref(), true);
+                self.detector.reset();
+                self.last_verdict = None;
+                self.last_api_call = Some(Instant::now());
+                self.set_state();
+                return;
+            },
+            enforcement::Outcome::Locked { started, result, judged } => {
+                match result {
+                    LockResult::Unlocked => {
+                        info!("Screen was unlocked by user or Claude.");
+                        ipc::decision("unlocked");
+                        annotate(&mut self.notes, started, "screen locked until the judge unlocked it");
+                        if let (true, Some(minutes)) = (judged, UNLOCK_COOLDOWN_MINUTES) {
+                            let since = started.format("%Y-%m-%d %H:%M:%S").to_string();
+                            let said = transcripts::last_said(self.profile.name, &since).unwrap_or_else(|e| {
+                                warn!("Failed to read the lock chat: {}", e);
+                                None
+                            });
+                            self.cooldown = Some(Cooldown {
+                                started: Local::now(),
+                                until: Local::now() + chrono::Duration::minutes(minutes as i64),
+                                said,
+                            });
+                        }
+                    },
+                    LockResult::UnlockedFor { minutes, ref purpose } => {
+                        info!("Allowance of {} minutes granted: {}", minutes, purpose);
+                        ipc::decision(&format!("unlocked for {} minutes: {}", minutes, purpose));
+                        annotate(&mut self.notes, started, "screen locked until the judge granted an allowance");
+                        let until = Local::now() + chrono::Duration::minutes(minutes as i64);
+                        self.allowance = Some((until, minutes, purpose.clone()));
+                    },
+                    LockResult::TimedLock(minutes) => {
+                        info!("Lock period of {} minutes completed.", minutes);
+                        // The judge upheld the lock
+                        if judged && !forced {
+                            label(verdict.as_deref(), true);
+                        }
+                        ipc::decision(&format!("locked for {} minutes", minutes));
+                        annotate(&mut self.notes, started, "screen locked");
+                    },
+                }
+                self.reset_context(&result);
+                notify::send(Notification::LockEnd, "perimedes", "The lock has ended");
+            },
+            enforcement::Outcome::Done => {},
+        }
+        self.last_contested = false;
+        self.detector.reset();
+        self.last_verdict = None;
+        self.update_gate();
+    }
+
+    // Drop the screen records after a lock, as configured by CONTEXT_RESET
+    fn reset_context(&mut self, result: &LockResult) {
+        let reset = matches!(
+            (CONTEXT_RESET, result),
+            (ContextReset::Always, _)
+                | (ContextReset::AfterUnlock, LockResult::Unlocked | LockResult::UnlockedFor { .. })
+                | (ContextReset::AfterTimedLock, LockResult::TimedLock(_))
+        );
+
+        if reset && !self.records.is_empty() {
+            info!("Clearing {} screen records after the lock", self.records.len());
+            self.records.clear();
+            self.frames.clear();
+            self.gate.send_modify(|gate| gate.clears += 1);
+        }
+    }
+}
+
+// Verdict without the classifier once the monthly budget is spent: any of
+// OFFLINE_DISTRACTIONS in the latest capture counts as procrastinating
+fn local_verdict(records: &VecDeque<ScreenRecord>) -> bool {
+    let text = records.back().map_or(String::new(), |record| record.text.to_lowercase());
+    let distraction = OFFLINE_DISTRACTIONS.iter().find(|pattern| text.contains(&pattern.to_lowercase()));
+    if let Some(pattern) = distraction {
+        info!("Local verdict: PROCRASTINATING ({})", pattern);
+    }
+    distraction.is_some()
+}
+
+// Ask the rules, the script, then the WASM policy for a verdict; None defers to the classifier
+fn run_policies(
+    rules: &policy::Rules,
+    script: Option<&policy::Script>,
+    policy: Option<&mut policy::Policy>,
+    profile: &Profile,
+    records: &VecDeque<ScreenRecord>,
+    combined_text: &str,
+    detector: &Detector,
+) -> Option<(bool, Option<LockReason>)> {
+    let window = records.back().and_then(|record| record.window.as_ref());
+    let url = records.back().and_then(|record| record.tab.as_ref()).map_or("", |tab| tab.url.as_str());
+    let dwell = records.iter().rev()
+        .take_while(|record| record.window.as_ref().map(|w| &w.class) == window.map(|w| &w.class))
+        .count();
+
+    let signals = policy::Signals {
+        time: Local::now().to_rfc3339(),
+        profile: profile.name,
+        text: combined_text,
+        window,
+        url,
+        domains: policy::domains(&format!("{}\n{}", url, combined_text)),
+        dwell_minutes: (dwell as u64 * SCREENSHOT_INTERVAL_SECS) as f64 / 60.0,
+        winding_down: matches!(winddown::phase(Local::now()), winddown::Phase::WindDown(_)),
+        history: detector.verdicts.iter().copied().collect(),
+    };
+
+    let latest = records.back().map_or("", |record| record.text.as_str());
+    let (mut decision, mut reason, lock_reason) = rules.decide(window, url, latest);
+    if let (policy::Decision::Defer, Some(script)) = (&decision, script) {
+        match script.decide(&signals) {
+            Ok(result) => (decision, reason) = result,
+            Err(e) => warn!("{}", e),
+        }
+    }
+    if let (policy::Decision::Defer, Some(policy)) = (&decision, policy) {
+        match policy.decide(&signals) {
+            Ok(result) => (decision, reason) = result,
+            Err(e) => warn!("Policy failed, deferring to the classifier: {}", e),
+        }
+    }
+
+    let verdict = match decision {
+        policy::Decision::Procrastinating => true,
+        policy::Decision::Focused => false,
+        policy::Decision::Defer => return None,
+    };
+    info!("Policy verdict: {} {}", if verdict { "PROCRASTINATING" } else { "NOT PROCRASTINATING" }, reason);
+    ipc::verdict(verdict, None, &format!("policy: {}", reason));
+    Some((verdict, lock_reason))
+}
+
+// Label the verdict that triggered an enforcement
+fn label(verdict: Option<&str>, correct: bool) {
+    if let Some(id) = verdict {
+        if let Err(e) = verdicts::label(id, correct) {
+            warn!("Failed to label verdict: {}", e);
+        }
+    }
+}
+
+// Note that something kept the user off their screen from `since` until now.
+// The note is timestamped at its end, so the pruning of the context window
+// doesn't drop it right away after a long gap.
+fn annotate(notes: &mut VecDeque<ContextNote>, since: chrono::DateTime<Local>, what: &str) {
+    let now = Local::now();
+    let minutes = (now - since).num_minutes().max(1);
+    notes.push_back(ContextNote {
+        timestamp: now,
+        text: format!("[{} since {}: {} min]", what, since.format("%H:%M"), minutes),
+    });
+}
+
+// Voluntary timed lock, independent of detection
+pub async fn run_self_lock(minutes: u64, reason: Option<&str>) {
+    info!("Self-lock for {} minutes{}", minutes,
+             reason.map(|r| format!(" ({})", r)).unwrap_or_default());
+    ipc::set_state(&format!("locked: {} minute self-lock", minutes));
+    locks::started(&format!("{} minute self-lock", minutes), None, None);
+
+    match lockscreen::display_lock_timer(minutes, reason).await {
+        Ok(()) => {
+            ipc::decision(&format!("self-locked for {} minutes", minutes));
+            notify::send(Notification::LockEnd, "perimedes", "The self-lock has ended");
+        },
+        Err(e) => error!("Error in self-lock: {}", redact::scrub(&e.to_string())),
+    }
+}
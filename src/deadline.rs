@@ -0,0 +1,113 @@
+// Lock deadlines that keep running while the machine is suspended
+//
+// Instant stops with the monotonic clock during suspend, so a lock would
+// last its full length after the lid is opened again. The deadline is kept
+// on CLOCK_BOOTTIME, which counts suspended time and can't be set by the
+// user, and as wall-clock time for after a reboot. It is persisted in the
+// state directory while the lock runs, so a restart resumes the lock.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::storage;
+
+const DEADLINE_FILE: &str = "lock_deadline.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Deadline {
+    // Unix time of the deadline, in milliseconds
+    wall_ms: i64,
+    // CLOCK_BOOTTIME at the deadline, only meaningful within the same boot
+    boot_ms: u64,
+    boot_id: String,
+}
+
+pub fn boottime() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: ts is a valid timespec and CLOCK_BOOTTIME exists on Linux
+    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+// Can't change while we run
+fn boot_id() -> &'static str {
+    static BOOT_ID: OnceLock<String> = OnceLock::new();
+    BOOT_ID.get_or_init(|| {
+        std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .map(|id| id.trim().to_string())
+            .unwrap_or_default()
+    })
+}
+
+impl Deadline {
+    pub fn after(duration: Duration) -> Deadline {
+        Deadline {
+            wall_ms: now_ms() + duration.as_millis() as i64,
+            boot_ms: (boottime() + duration).as_millis() as u64,
+            boot_id: boot_id().to_string(),
+        }
+    }
+
+    // A deadline from another machine, on the wall clock only
+    pub fn at_wall_ms(wall_ms: i64) -> Deadline {
+        Deadline { wall_ms, boot_ms: 0, boot_id: String::new() }
+    }
+
+    pub fn wall_ms(&self) -> i64 {
+        self.wall_ms
+    }
+
+    // Saturates, so no extension can wrap around and end the lock early
+    pub fn extend(&mut self, duration: Duration) {
+        self.wall_ms = self.wall_ms.saturating_add(i64::try_from(duration.as_millis()).unwrap_or(i64::MAX));
+        self.boot_ms = self.boot_ms.saturating_add(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX));
+    }
+
+    // Time left; within the boot the lock started in, changes of the wall
+    // clock don't count
+    pub fn remaining(&self) -> Duration {
+        let wall = Duration::from_millis((self.wall_ms - now_ms()).max(0) as u64);
+        if self.boot_id.is_empty() || self.boot_id != boot_id() {
+            return wall;
+        }
+
+        Duration::from_millis(self.boot_ms).saturating_sub(boottime())
+    }
+
+    pub fn clear() -> Result<()> {
+        storage::save_json(DEADLINE_FILE, &None::<ActiveLock>)
+    }
+}
+
+// The running timed lock, as persisted
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ActiveLock {
+    #[serde(flatten)]
+    pub deadline: Deadline,
+    #[serde(default)]
+    pub label: Option<String>,
+    // What the judge said last, if the lock chat decided on the lock
+    #[serde(default)]
+    pub snippet: Option<String>,
+    // Handed over from another machine, see handoff
+    #[serde(default)]
+    pub handed_over: bool,
+}
+
+impl ActiveLock {
+    pub fn save(&self) -> Result<()> {
+        storage::save_json(DEADLINE_FILE, self)
+    }
+}
+
+// The lock a killed or crashed run left, if it hasn't expired
+pub fn load() -> Result<Option<ActiveLock>> {
+    let lock: Option<ActiveLock> = storage::load_json(DEADLINE_FILE)?;
+    Ok(lock.filter(|lock| !lock.deadline.remaining().is_zero()))
+}
@@ -0,0 +1,49 @@
+// Hashing of captures, to skip OCR when the screen hasn't changed
+
+use anyhow::{Result, Context};
+use image::RgbImage;
+use image::imageops::FilterType;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+// dHash: shrink to 9x8 grayscale and record whether each pixel is brighter
+// than its right neighbour. Similar images differ in few bits.
+pub fn dhash(path: &Path) -> Result<u64> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load {}", path.display()))?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .into_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = image.get_pixel(x, y)[0];
+            let right = image.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+    Ok(hash)
+}
+
+// Number of differing bits between two hashes
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// SHA-256 of the decoded pixels, identical only for identical captures
+pub fn content_hash(path: &Path) -> Result<String> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load {}", path.display()))?
+        .into_rgb8();
+    Ok(pixels_hash(&image))
+}
+
+// SHA-256 of an image already in memory, e.g. one tile of a capture
+pub fn pixels_hash(image: &RgbImage) -> String {
+    let digest = Sha256::new()
+        .chain_update(image.width().to_le_bytes())
+        .chain_update(image.height().to_le_bytes())
+        .chain_update(image.as_raw())
+        .finalize();
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,138 @@
+// `perimedes doctor`: checks of the environment, each printed as ok, warn
+// or FAIL with what to do about it
+//
+// The lock path itself briefly grabs the input, so it only runs with
+// --lock-test; see selftest.
+
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use std::path::PathBuf;
+use std::sync::Arc;
+use x11rb::connection::Connection;
+
+use crate::api;
+use crate::config;
+use crate::constants::{API_URL, CAPTURE_BACKEND, FONT_FAMILY, OCR_CMD, SCROT_CMD};
+use crate::secrets;
+use crate::selftest;
+use crate::types::CaptureBackend;
+use crate::window::{self, TextFont};
+
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn ok(&self, what: &str) {
+        println!("ok    {}", what);
+    }
+
+    fn warn(&self, what: &str) {
+        println!("warn  {}", what);
+    }
+
+    fn fail(&mut self, what: &str) {
+        println!("FAIL  {}", what);
+        self.failures += 1;
+    }
+}
+
+// The program as found on $PATH, or the path itself if it has a slash
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| path.is_file());
+    }
+    std::env::var_os("PATH")
+        .into_iter()
+        .flat_map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+pub async fn run(lock_test: bool) -> Result<()> {
+    let mut report = Report::default();
+
+    // Display server
+    if std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland") {
+        report.warn("Wayland session: only XWayland windows can be captured and locked; log in to an X11 session");
+    }
+    let x11 = match x11rb::connect(None) {
+        Ok((conn, screen_num)) => {
+            report.ok("X connection");
+            Some((Arc::new(conn), screen_num))
+        },
+        Err(e) => {
+            report.fail(&format!("X connection: {}; is $DISPLAY set?", e));
+            None
+        },
+    };
+
+    // Capture and OCR
+    match CAPTURE_BACKEND {
+        CaptureBackend::Scrot => match find_program(SCROT_CMD) {
+            Some(path) => report.ok(&format!("capture with {}", path.display())),
+            None => report.fail(&format!("SCROT_CMD '{}' not found; install scrot or set CAPTURE_BACKEND to Native", SCROT_CMD)),
+        },
+        CaptureBackend::Native => report.ok("capture over the X connection"),
+    }
+    match find_program(OCR_CMD) {
+        Some(path) => report.ok(&format!("OCR with {}", path.display())),
+        None => report.fail(&format!(
+            "OCR_CMD '{}' not found; install tesseract (packaged as tesseract-ocr on Debian) or point OCR_CMD at it",
+            OCR_CMD
+        )),
+    }
+
+    // Fonts
+    if let Some((conn, screen_num)) = &x11 {
+        match window::load_text_font(conn, &conn.setup().roots[*screen_num]) {
+            TextFont::TrueType(_) => report.ok(&format!("font {}", FONT_FAMILY)),
+            TextFont::Core(_) => report.warn(&format!("FONT_FAMILY '{}' not found, using a core X font; install it or change FONT_FAMILY", FONT_FAMILY)),
+            TextFont::Boxes => report.fail("no usable font, text is drawn as boxes; install fontconfig and a TrueType font"),
+        }
+    }
+
+    // API key, checked against the models endpoint, which costs nothing
+    match secrets::api_key().await {
+        Ok(key) => match check_api_key(&key).await {
+            Ok(()) => report.ok("API key"),
+            Err(e) => report.fail(&format!("API key: {}", e)),
+        },
+        Err(e) => report.fail(&e.to_string()),
+    }
+
+    // Configuration
+    match config::problems().as_slice() {
+        [] => report.ok("configuration"),
+        problems => {
+            for problem in problems {
+                report.fail(&format!("configuration: {}", problem));
+            }
+        },
+    }
+
+    if !lock_test {
+        println!("skip  lock path, pass --lock-test to check it (briefly grabs the input)");
+    } else if let Err(e) = selftest::lock_path().await {
+        report.fail(&format!("lock path: {}", e));
+    }
+
+    match report.failures {
+        0 => Ok(()),
+        n => Err(anyhow!("{} check{} failed", n, if n == 1 { "" } else { "s" })),
+    }
+}
+
+async fn check_api_key(key: &str) -> Result<()> {
+    let url = API_URL.replace("/messages", "/models");
+    let response = api::get(&Client::new(), &url, key)
+        .send()
+        .await
+        .map_err(|e| anyhow!("can't reach {}: {}", url, e))?;
+    match response.status().as_u16() {
+        200..=299 => Ok(()),
+        401 | 403 => Err(anyhow!("rejected; store a valid key with `perimedes set-key`")),
+        status => Err(anyhow!("{} answered {}", url, status)),
+    }
+}
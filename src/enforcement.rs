@@ -0,0 +1,154 @@
+// The response to a PROCRASTINATING verdict: the ENFORCEMENT pipeline of
+// notifications, nudges and locks
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+use crate::call;
+use crate::constants::{ENFORCEMENT, GRACE_PERIOD_SECS, UNLOCK_PHRASE};
+use crate::focus::FocusMonitor;
+use crate::ipc;
+use crate::judge;
+use crate::locks;
+use crate::lockscreen;
+use crate::media;
+use crate::redact;
+use crate::notify;
+use crate::onboarding;
+use crate::overlay;
+use crate::types::{Action, EnforcementStep, LockReason, LockResult, Notification, OnboardingPhase, Profile, ScreenRecord};
+use crate::warning;
+
+// What the verdict is enforced on, and how hard
+pub struct Situation<'a> {
+    pub client: &'a Client,
+    pub api_key: &'a str,
+    pub profile: &'a Profile,
+    pub focus_monitor: Option<&'a FocusMonitor>,
+    pub combined_text: &'a str,
+    // Written by the classifier, e.g. "18 minutes of r/rust comment threads"
+    pub lock_message: Option<&'a str>,
+    pub screenshot: Option<&'a Path>,
+    // Positive verdicts in the LOCK_TRIGGER window
+    pub severity: usize,
+    // Earlier enforcements since the last NOT PROCRASTINATING verdict
+    pub repeats: u32,
+    // Lock right away, e.g. for lock-now or after a contested nudge
+    pub skip_nudge: bool,
+    // Explicitly requested, e.g. lock-now; onboarding doesn't soften it
+    pub forced: bool,
+    // Id of the verdict that triggered the enforcement, if any
+    pub verdict: Option<&'a str>,
+    pub reason: Option<LockReason>,
+}
+
+pub enum Outcome {
+    // The user contested the nudge
+    Contested,
+    // The user stopped procrastinating during the nudge
+    BackToWork,
+    // The screen was locked; `judged` if the lock chat decided the result
+    Locked { started: DateTime<Local>, result: LockResult, judged: bool },
+    // The pipeline ran without locking
+    Done,
+}
+
+fn applies(step: &EnforcementStep, situation: &Situation) -> bool {
+    situation.severity >= step.min_severity && situation.repeats >= step.min_repeats
+        && step.max_repeats.is_none_or(|max| situation.repeats <= max)
+}
+
+// Run the steps of ENFORCEMENT that apply, until one ends the pipeline
+pub async fn run(situation: &Situation<'_>, records: &mut VecDeque<ScreenRecord>) -> Result<Outcome> {
+    if !situation.forced && call::inhibits("lock", situation.focus_monitor).await {
+        return Ok(Outcome::Done);
+    }
+    let phase = if situation.forced { None } else { onboarding::phase() };
+    for step in ENFORCEMENT.iter().filter(|step| applies(step, situation)) {
+        let action = match (step.action, phase) {
+            (Action::LockChat | Action::TimedLock(_), Some(OnboardingPhase::NudgesOnly)) => continue,
+            (Action::LockChat, Some(OnboardingPhase::ShortLocks(max))) => Action::TimedLock(max),
+            (Action::TimedLock(minutes), Some(OnboardingPhase::ShortLocks(max))) => Action::TimedLock(minutes.min(max)),
+            (action, _) => action,
+        };
+        match action {
+            Action::Notify => notify::send(
+                Notification::Warning,
+                "Procrastination detected",
+                "perimedes thinks you are procrastinating. Get back to work.",
+            ),
+            Action::PauseMedia => media::pause_all().await,
+            Action::Banner => {
+                let message = situation.lock_message.unwrap_or("perimedes thinks you are procrastinating. Get back to work.");
+                let message = message.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) = overlay::show(&message).await {
+                        warn!("Failed to show the overlay: {}", e);
+                    }
+                });
+            },
+            Action::Nudge => {
+                if GRACE_PERIOD_SECS == 0 || situation.skip_nudge {
+                    continue;
+                }
+                if let Some(outcome) = nudge(situation, records).await? {
+                    return Ok(outcome);
+                }
+            },
+            Action::LockChat => {
+                // Start the integrated lock screen process
+                info!("Starting interactive lock screen...");
+                locks::started("lock chat", situation.verdict, situation.reason);
+
+                // Run the interactive lock screen with existing combined_text
+                let started = Local::now();
+                return match lockscreen::run_interactive_lock_screen(
+                    situation.api_key, situation.profile, UNLOCK_PHRASE,
+                    situation.combined_text, situation.lock_message, situation.screenshot,
+                ).await {
+                    Ok(result) => Ok(Outcome::Locked { started, result, judged: true }),
+                    Err(e) => {
+                        error!("Error in interactive lock screen: {}", redact::scrub(&e.to_string()));
+                        Ok(Outcome::Done)
+                    },
+                };
+            },
+            Action::TimedLock(minutes) => {
+                info!("Locking for {} minutes", minutes);
+                locks::started(&format!("{} minute timer", minutes), situation.verdict, situation.reason);
+                ipc::set_state(&format!("locked: {} minute timer", minutes));
+                let started = Local::now();
+                return match lockscreen::display_lock_timer(minutes, Some("Procrastination detected")).await {
+                    Ok(()) => Ok(Outcome::Locked { started, result: LockResult::TimedLock(minutes), judged: false }),
+                    Err(e) => {
+                        error!("Error in lock timer: {}", redact::scrub(&e.to_string()));
+                        Ok(Outcome::Done)
+                    },
+                };
+            },
+        }
+    }
+    Ok(Outcome::Done)
+}
+
+// Show the warning overlay; Some ends the pipeline
+async fn nudge(situation: &Situation<'_>, records: &mut VecDeque<ScreenRecord>) -> Result<Option<Outcome>> {
+    ipc::set_state("warning");
+    match warning::run_grace_period(GRACE_PERIOD_SECS).await {
+        Ok(warning::GraceOutcome::Contested) => Ok(Some(Outcome::Contested)),
+        Ok(warning::GraceOutcome::Elapsed) => {
+            let still = judge::still_procrastinating(
+                situation.client, situation.api_key, situation.profile, situation.focus_monitor, records
+            ).await?;
+            Ok((!still).then_some(Outcome::BackToWork))
+        },
+        Err(e) => {
+            warn!("Failed to show lock warning: {}", redact::scrub(&e.to_string()));
+            Ok(None)
+        },
+    }
+}
@@ -0,0 +1,49 @@
+// Notable events, such as emergency bypasses, appended to events.jsonl in
+// the state directory so they show up in later reviews, and to the tamper log
+//
+// Captures, verdicts, locks and logged events carry a UUID, so integrations
+// reading the state files, the control socket or exports can deduplicate
+// them and follow a lock back to its verdict and capture.
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::ipc;
+use crate::storage;
+use crate::tamper;
+
+#[derive(Serialize, Deserialize)]
+pub struct LoggedEvent {
+    #[serde(default)]
+    pub id: String,
+    pub time: String,
+    pub kind: String,
+    pub detail: String,
+    // The running or last lock, e.g. the one bypassed
+    #[serde(default)]
+    pub lock: Option<String>,
+}
+
+const EVENTS_FILE: &str = "events.jsonl";
+
+pub fn log(kind: &str, detail: &str) -> Result<()> {
+    let event = LoggedEvent {
+        id: new_id(),
+        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        kind: kind.to_string(),
+        detail: detail.to_string(),
+        lock: ipc::current_status().lock,
+    };
+
+    storage::append_jsonl(EVENTS_FILE, &event)?;
+    tamper::record(kind, detail)
+}
+
+pub fn load() -> Result<Vec<LoggedEvent>> {
+    storage::load_jsonl(EVENTS_FILE)
+}
+
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
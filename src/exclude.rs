@@ -0,0 +1,63 @@
+// Keeping perimedes' own output out of the screen context
+//
+// Our windows carry the WM_CLASS below, and captures are skipped while one
+// of them is visible. Lock chat transcripts and log output can still show
+// up elsewhere (e.g. in the terminal running perimedes), so OCR lines that
+// match remembered transcript lines are dropped.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::constants::OWN_OUTPUT_MARKERS;
+
+pub const WM_CLASS: &[u8] = b"perimedes\0perimedes\0";
+
+// How many transcript lines to remember
+const TRANSCRIPT_LINES: usize = 200;
+// Shorter lines match too much unrelated text
+const MIN_MATCH_LEN: usize = 12;
+
+static TRANSCRIPT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+// Lowercase letters and digits only, so OCR noise in spacing and
+// punctuation doesn't prevent a match
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Remember text shown or logged by perimedes, e.g. a judge reply
+pub fn remember(text: &str) {
+    if let Ok(mut transcript) = TRANSCRIPT.lock() {
+        for line in text.lines().map(normalize).filter(|line| line.len() >= MIN_MATCH_LEN) {
+            transcript.push_back(line);
+            if transcript.len() > TRANSCRIPT_LINES {
+                transcript.pop_front();
+            }
+        }
+    }
+}
+
+fn is_own(line: &str, transcript: &VecDeque<String>) -> bool {
+    if OWN_OUTPUT_MARKERS.iter().any(|marker| line.contains(marker)) {
+        return true;
+    }
+
+    let line = normalize(line);
+    line.len() >= MIN_MATCH_LEN
+        && transcript.iter().any(|own| own.contains(&line) || line.contains(own.as_str()))
+}
+
+// OCR text without the lines that perimedes produced itself
+pub fn filter(text: &str) -> String {
+    let Ok(transcript) = TRANSCRIPT.lock() else {
+        return text.to_string();
+    };
+
+    text.lines()
+        .filter(|line| !is_own(line, &transcript))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,103 @@
+// Focused application and window title, read through EWMH, and those of
+// all open windows
+
+use anyhow::{Result, Context};
+use serde::Serialize;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, MapState, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::exclude;
+use crate::redact;
+
+#[derive(Serialize)]
+pub struct ActiveWindow {
+    pub class: String,
+    pub title: String,
+    // Only known under i3 and sway, see tiling
+    pub workspace: Option<String>,
+}
+
+pub struct FocusMonitor {
+    conn: RustConnection,
+    root: Window,
+    net_active_window: Atom,
+    net_client_list: Atom,
+    net_wm_name: Atom,
+    utf8_string: Atom,
+}
+
+impl FocusMonitor {
+    pub fn new() -> Result<FocusMonitor> {
+        let (conn, screen_num) = x11rb::connect(None)
+            .context("Failed to connect to X server")?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let net_active_window = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+        let net_client_list = conn.intern_atom(false, b"_NET_CLIENT_LIST")?.reply()?.atom;
+        let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+        let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+
+        Ok(FocusMonitor { conn, root, net_active_window, net_client_list, net_wm_name, utf8_string })
+    }
+
+    // Whether one of perimedes' own windows is on screen
+    pub fn own_window_visible(&self) -> Result<bool> {
+        let tree = self.conn.query_tree(self.root)?.reply()?;
+        for win in tree.children {
+            let attributes = self.conn.get_window_attributes(win)?.reply()?;
+            if attributes.map_state != MapState::VIEWABLE {
+                continue;
+            }
+
+            let class = self.conn.get_property(false, win, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 64)?.reply()?;
+            if class.value == exclude::WM_CLASS {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // The focused window, if the window manager reports one
+    pub fn active_window(&self) -> Result<Option<ActiveWindow>> {
+        let reply = self.conn.get_property(false, self.root, self.net_active_window, AtomEnum::WINDOW, 0, 1)?.reply()?;
+        let win = match reply.value32().and_then(|mut values| values.next()) {
+            Some(win) if win != 0 => win,
+            _ => return Ok(None),
+        };
+        self.describe(win).map(Some)
+    }
+
+    // Every window the window manager manages, e.g. minimized call windows
+    pub fn windows(&self) -> Result<Vec<ActiveWindow>> {
+        let reply = self.conn.get_property(false, self.root, self.net_client_list, AtomEnum::WINDOW, 0, 4096)?.reply()?;
+        let Some(windows) = reply.value32() else {
+            return Ok(Vec::new());
+        };
+        // Windows may close while they're read
+        Ok(windows.filter_map(|win| self.describe(win).ok()).collect())
+    }
+
+    fn describe(&self, win: Window) -> Result<ActiveWindow> {
+        // WM_CLASS holds the instance and class names, NUL separated
+        let class = self.conn.get_property(false, win, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?.reply()?;
+        let class = class.value.split(|b| *b == 0)
+            .rfind(|part| !part.is_empty())
+            .map(|part| String::from_utf8_lossy(part).into_owned())
+            .unwrap_or_default();
+
+        // Prefer the UTF-8 EWMH title over the legacy WM_NAME
+        let title = self.conn.get_property(false, win, self.net_wm_name, self.utf8_string, 0, 1024)?.reply()?;
+        let title = if title.value.is_empty() {
+            self.conn.get_property(false, win, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?.reply()?.value
+        } else {
+            title.value
+        };
+
+        Ok(ActiveWindow {
+            class,
+            title: redact::ocr(&String::from_utf8_lossy(&title)),
+            workspace: None,
+        })
+    }
+}
@@ -0,0 +1,291 @@
+// Taking the input grabs for the lock windows, diagnosis of grab conflicts
+// with other X clients, and the guard that releases our own grabs
+//
+// X11 can't tell us who holds a grab, so we list the connected clients
+// through the X-Resource extension and look for known grabbing programs.
+
+use anyhow::{Result, anyhow};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::res::{ClientIdMask, ClientIdSpec, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    ChangeWindowAttributesAux, ConfigureWindowAux, ConnectionExt as _, Cursor, EventMask, GrabMode, GrabStatus, InputFocus,
+    Screen, StackMode, Visibility, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+use tracing::{info, warn};
+
+use crate::constants::{CONFLICTING_GRABBERS, GRAB_ATTEMPTS, GRAB_INITIAL_DELAY_MS, KILL_CONFLICTING_GRABBERS};
+use crate::notify;
+use crate::types::Notification;
+use crate::window;
+
+pub struct Conflict {
+    pub pid: u32,
+    pub name: String,
+}
+
+// Process name of a pid, as shown by ps
+fn process_name(pid: u32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|name| name.trim().to_string())
+}
+
+// X clients that are known grabbing programs
+pub fn find_conflicts(conn: &Arc<x11rb::rust_connection::RustConnection>) -> Result<Vec<Conflict>> {
+    // Client 0 with the PID mask asks for the PIDs of all local clients
+    let spec = ClientIdSpec { client: 0, mask: ClientIdMask::LOCAL_CLIENT_PID };
+    let reply = conn.res_query_client_ids(&[spec])?.reply()?;
+    let own_pid = std::process::id();
+
+    let mut conflicts: Vec<Conflict> = reply.ids.iter()
+        .filter_map(|id| id.value.first().copied())
+        .filter(|pid| *pid != own_pid)
+        .filter_map(|pid| process_name(pid).map(|name| Conflict { pid, name }))
+        .filter(|conflict| CONFLICTING_GRABBERS.contains(&conflict.name.as_str()))
+        .collect();
+    conflicts.dedup_by_key(|conflict| conflict.pid);
+
+    Ok(conflicts)
+}
+
+pub fn describe(conflicts: &[Conflict]) -> String {
+    conflicts.iter()
+        .map(|conflict| format!("{} (pid {})", conflict.name, conflict.pid))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Ask the conflicting programs to exit
+pub fn terminate(conflicts: &[Conflict]) {
+    for conflict in conflicts {
+        info!("Terminating {} (pid {}) to acquire input grab", conflict.name, conflict.pid);
+        let _ = Command::new("kill")
+            .arg("-TERM")
+            .arg(conflict.pid.to_string())
+            .status();
+    }
+}
+
+// Releases the keyboard and pointer grabs and destroys the lock windows when
+// dropped, so an error or panic never leaves the session with a dead grab
+pub struct GrabGuard {
+    id: u64,
+}
+
+struct Held {
+    id: u64,
+    conn: Arc<RustConnection>,
+    root: Window,
+    // The first one has the focus and confines the pointer
+    windows: Vec<Window>,
+    cursor: Cursor,
+    reasserted: Option<Instant>,
+}
+
+// Our own grab moves the focus too; at most one re-grab in this time keeps
+// that from looping
+const REASSERT_INTERVAL: Duration = Duration::from_millis(250);
+
+static HELD: Mutex<Vec<Held>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static PANIC_HOOK: Once = Once::new();
+
+impl GrabGuard {
+    pub fn new(conn: &Arc<RustConnection>, screen: &Screen, windows: Vec<Window>, cursor: Cursor) -> GrabGuard {
+        PANIC_HOOK.call_once(install_panic_hook);
+        // Map notifications of other windows, see reassert
+        let values = ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_NOTIFY);
+        if let Err(e) = conn.change_window_attributes(screen.root, &values) {
+            warn!("Failed to watch for windows mapped over the lock: {}", e);
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut held) = HELD.lock() {
+            held.push(Held { id, conn: conn.clone(), root: screen.root, windows, cursor, reasserted: None });
+        }
+        GrabGuard { id }
+    }
+}
+
+impl Drop for GrabGuard {
+    fn drop(&mut self) {
+        let Ok(mut held) = HELD.lock() else {
+            return;
+        };
+        if let Some(i) = held.iter().position(|held| held.id == self.id) {
+            release(&held.remove(i));
+        }
+    }
+}
+
+// Try to grab keyboard and mouse, doubling the delay after each failure.
+// The pointer is confined to the lock window and shows the given cursor
+// everywhere, like slock does across multi-head setups.
+pub async fn try_grab(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    screen: &Screen,
+    win: Window,
+    cursor: Cursor,
+) -> Result<bool> {
+    let mut delay = Duration::from_millis(GRAB_INITIAL_DELAY_MS);
+
+    for attempt in 0..GRAB_ATTEMPTS {
+        let kb_grab = conn.grab_keyboard(
+            false,
+            screen.root,
+            CURRENT_TIME,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+        )?.reply();
+
+        let ptr_grab = conn.grab_pointer(
+            false,
+            screen.root,
+            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+            win,
+            cursor,
+            CURRENT_TIME,
+        )?.reply();
+
+        if let (Ok(kb), Ok(ptr)) = (&kb_grab, &ptr_grab) {
+            if kb.status == GrabStatus::SUCCESS && ptr.status == GrabStatus::SUCCESS {
+                return Ok(true);
+            }
+        }
+
+        if attempt + 1 < GRAB_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    Ok(false)
+}
+
+pub async fn grab_keyboard_and_mouse(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    screen: &Screen,
+    win: Window,
+    cursor: Cursor,
+) -> Result<()> {
+    if try_grab(conn, screen, win, cursor).await? {
+        window::recenter_pointer(conn, win, screen)?;
+        return Ok(());
+    }
+
+    // Someone else holds a grab; find out who and tell the user, since a
+    // lock that silently never happens is the worst failure mode
+    let conflicts = find_conflicts(conn).unwrap_or_else(|e| {
+        warn!("Could not query X clients: {}", e);
+        Vec::new()
+    });
+
+    let message = if conflicts.is_empty() {
+        "Another program is holding the keyboard or mouse grab".to_string()
+    } else {
+        format!("Input grab held by {}", describe(&conflicts))
+    };
+    warn!("{}", message);
+    notify::send(Notification::Alert, "perimedes could not lock the screen", &message);
+
+    if KILL_CONFLICTING_GRABBERS && !conflicts.is_empty() {
+        terminate(&conflicts);
+        if try_grab(conn, screen, win, cursor).await? {
+            window::recenter_pointer(conn, win, screen)?;
+            return Ok(());
+        }
+    }
+
+    Err(anyhow!("Failed to grab keyboard and mouse: {}", message))
+}
+
+// Raise the lock windows and take the grabs back when another client maps a
+// window over them, covers them, or takes the focus, like slock does. The
+// lock windows have to select VISIBILITY_CHANGE and FOCUS_CHANGE.
+pub fn reassert(conn: &Arc<RustConnection>, event: &Event) -> Result<()> {
+    let Ok(mut held) = HELD.lock() else {
+        return Ok(());
+    };
+    let Some(held) = held.iter_mut().find(|held| Arc::ptr_eq(&held.conn, conn)) else {
+        return Ok(());
+    };
+    let ours = |win| held.windows.contains(&win);
+    let stolen = match event {
+        Event::VisibilityNotify(event) => ours(event.window) && event.state != Visibility::UNOBSCURED,
+        Event::MapNotify(event) => !ours(event.window),
+        Event::FocusOut(event) => ours(event.event),
+        _ => false,
+    };
+    if !stolen || held.reasserted.is_some_and(|last| last.elapsed() < REASSERT_INTERVAL) {
+        return Ok(());
+    }
+    held.reasserted = Some(Instant::now());
+
+    for &win in &held.windows {
+        conn.configure_window(win, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+    }
+    let Some(&first) = held.windows.first() else {
+        return Ok(());
+    };
+    conn.set_input_focus(InputFocus::PARENT, first, CURRENT_TIME)?;
+    // One attempt; failing, the next event tries again
+    conn.grab_keyboard(false, held.root, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)?;
+    conn.grab_pointer(
+        false,
+        held.root,
+        EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+        GrabMode::ASYNC,
+        GrabMode::ASYNC,
+        first,
+        held.cursor,
+        CURRENT_TIME,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+// Next X event, polled so the runtime stays free for API calls. A blocking
+// wait on another thread would swallow the first event after the caller
+// stopped listening, e.g. a key typed while the judge is answering.
+pub async fn next_event(conn: &Arc<RustConnection>) -> Result<Event> {
+    loop {
+        if let Some(event) = conn.poll_for_event()? {
+            reassert(conn, &event)?;
+            return Ok(event);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn release(held: &Held) {
+    let conn = &held.conn;
+    let _ = conn.ungrab_keyboard(CURRENT_TIME);
+    let _ = conn.ungrab_pointer(CURRENT_TIME);
+    for &win in &held.windows {
+        let _ = conn.destroy_window(win);
+    }
+    let _ = conn.flush();
+}
+
+// A panic on another thread, or with panic=abort, skips the guard's drop;
+// free the screen before anything else
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // The panic may have happened while the list was locked
+        if let Ok(mut held) = HELD.try_lock() {
+            for held in held.drain(..) {
+                release(&held);
+            }
+        }
+        previous(info);
+    }));
+}
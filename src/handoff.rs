@@ -0,0 +1,107 @@
+// Session handoff between machines: while a timed lock runs, its deadline
+// is published to HANDOFF_URL, and the daemons of other machines sharing the
+// URL enforce the remainder, so moving to the laptop doesn't escape it
+//
+// The URL holds a single JSON document, written with PUT and read with GET,
+// e.g. a file on a WebDAV share; $PERIMEDES_HANDOFF_TOKEN is sent as the
+// access token if set. No lock is stored as `null`.
+
+use anyhow::{Result, Context};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::constants::HANDOFF_URL;
+use crate::deadline::Deadline;
+use crate::ipc;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Handoff {
+    pub host: String,
+    // Id of the lock on that host
+    pub lock: Option<String>,
+    pub label: Option<String>,
+    // Unix time the lock ends, in milliseconds
+    pub until_ms: i64,
+}
+
+impl Handoff {
+    pub fn deadline(&self) -> Deadline {
+        Deadline::at_wall_ms(self.until_ms)
+    }
+}
+
+fn host() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+fn client() -> Result<Client> {
+    Ok(Client::builder().timeout(Duration::from_secs(5)).build()?)
+}
+
+fn authorized(request: RequestBuilder) -> RequestBuilder {
+    match std::env::var("PERIMEDES_HANDOFF_TOKEN") {
+        Ok(token) => request.bearer_auth(token),
+        Err(_) => request,
+    }
+}
+
+async fn put(url: &str, handoff: Option<&Handoff>) -> Result<()> {
+    authorized(client()?.put(url)).json(&handoff).send().await
+        .context("Failed to publish the lock for handoff")?
+        .error_for_status()
+        .context("Handoff remote rejected the lock")?;
+    Ok(())
+}
+
+async fn get(url: &str) -> Result<Option<Handoff>> {
+    let response = authorized(client()?.get(url)).send().await
+        .context("Failed to fetch the handed over lock")?;
+    // Nothing published yet
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    response.error_for_status()?
+        .json().await
+        .context("Failed to parse the handed over lock")
+}
+
+// Publish the running lock of this machine
+pub async fn publish(deadline: &Deadline, label: Option<&str>) {
+    let Some(url) = HANDOFF_URL else {
+        return;
+    };
+    let handoff = Handoff {
+        host: host(),
+        lock: ipc::current_status().lock,
+        label: label.map(str::to_string),
+        until_ms: deadline.wall_ms(),
+    };
+    if let Err(e) = put(url, Some(&handoff)).await {
+        warn!("{:#}", e);
+    }
+}
+
+// Withdraw this machine's lock once it ended, leaving other machines' alone
+pub async fn clear() {
+    let Some(url) = HANDOFF_URL else {
+        return;
+    };
+    let result = match get(url).await {
+        Ok(Some(handoff)) if handoff.host == host() => put(url, None).await,
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("{:#}", e);
+    }
+}
+
+// A lock another machine is still running
+pub async fn fetch() -> Result<Option<Handoff>> {
+    let Some(url) = HANDOFF_URL else {
+        return Ok(None);
+    };
+    Ok(get(url).await?.filter(|handoff| handoff.host != host() && !handoff.deadline().remaining().is_zero()))
+}
@@ -0,0 +1,81 @@
+// Recall of previously sent chat messages with the Up/Down keys
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::constants::{INPUT_HISTORY_SIZE, PERSIST_INPUT_HISTORY};
+use crate::storage;
+
+#[derive(Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    position: Option<usize>, // Entry currently recalled, None while typing
+    draft: String,           // Unsent input, restored when moving past the newest entry
+}
+
+const HISTORY_FILE: &str = "input_history.json";
+
+impl InputHistory {
+    // History of earlier lock chats if persisting is enabled, else empty
+    pub fn load() -> InputHistory {
+        if !PERSIST_INPUT_HISTORY {
+            return InputHistory::default();
+        }
+
+        let entries = storage::load_json(HISTORY_FILE).unwrap_or_default();
+        InputHistory { entries, ..InputHistory::default() }
+    }
+
+    fn save(&self) -> Result<()> {
+        storage::save_json(HISTORY_FILE, &self.entries)
+    }
+
+    // Remember a sent message and stop recalling
+    pub fn push(&mut self, entry: &str) {
+        self.position = None;
+        self.draft.clear();
+
+        if entry.trim().is_empty() || self.entries.last().map(String::as_str) == Some(entry) {
+            return;
+        }
+
+        self.entries.push(entry.to_string());
+        if self.entries.len() > INPUT_HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+
+        if PERSIST_INPUT_HISTORY {
+            if let Err(e) = self.save() {
+                warn!("Failed to save input history: {}", e);
+            }
+        }
+    }
+
+    // Step back to an older entry; `current` is kept as the draft
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            },
+            Some(0) => 0,
+            Some(position) => position - 1,
+        };
+
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    // Step forward to a newer entry, ending at the draft
+    pub fn next(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(&self.entries[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+}
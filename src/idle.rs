@@ -0,0 +1,33 @@
+// Input idle time through the X11 ScreenSaver extension
+
+use anyhow::{Result, Context, anyhow};
+use std::time::Duration;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::screensaver::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+pub struct IdleMonitor {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl IdleMonitor {
+    pub fn new() -> Result<IdleMonitor> {
+        let (conn, screen_num) = x11rb::connect(None)
+            .context("Failed to connect to X server")?;
+        let root = conn.setup().roots[screen_num].root;
+
+        if conn.extension_information(screensaver::X11_EXTENSION_NAME)?.is_none() {
+            return Err(anyhow!("X server lacks the MIT-SCREEN-SAVER extension"));
+        }
+
+        Ok(IdleMonitor { conn, root })
+    }
+
+    // Time since the last keyboard or pointer input
+    pub fn idle_time(&self) -> Result<Duration> {
+        let info = self.conn.screensaver_query_info(self.root)?.reply()?;
+        Ok(Duration::from_millis(info.ms_since_user_input as u64))
+    }
+}
@@ -0,0 +1,63 @@
+// Classic idle lock: after IDLE_LOCK_MINUTES without input the screen locks
+// until the account password is entered, so no second locker such as slock
+// or xss-lock has to compete with ours for the grabs
+
+use anyhow::{Result, Context};
+use std::sync::Arc;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+
+use crate::constants::THEME;
+use crate::emergency;
+use crate::grab::{self, GrabGuard};
+use crate::window;
+
+// Lock the screen until the password is entered
+pub async fn run() -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+
+    let win = conn.generate_id()?;
+    let values = CreateWindowAux::new()
+        .background_pixel(THEME.background)
+        .override_redirect(1)
+        .event_mask(EventMask::EXPOSURE | EventMask::KEY_PRESS | EventMask::POINTER_MOTION
+            | EventMask::VISIBILITY_CHANGE | EventMask::FOCUS_CHANGE);
+    conn.create_window(
+        screen.root_depth,
+        win,
+        screen.root,
+        0, 0,
+        screen.width_in_pixels, screen.height_in_pixels,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &values,
+    )?;
+    window::set_class(&conn, win)?;
+
+    let cursor = window::create_invisible_cursor(&conn, win)?;
+    let values = ChangeWindowAttributesAux::new().cursor(cursor);
+    conn.change_window_attributes(win, &values)?;
+
+    let font = window::load_text_font(&conn, screen);
+    let gc = conn.generate_id()?;
+    let gc_aux = CreateGCAux::new()
+        .foreground(THEME.text)
+        .background(THEME.background)
+        .font(font.core_font());
+    conn.create_gc(gc, win, &gc_aux)?;
+
+    conn.map_window(win)?;
+    conn.flush()?;
+    let guard = GrabGuard::new(&conn, screen, vec![win], cursor);
+    grab::grab_keyboard_and_mouse(&conn, screen, win, cursor).await?;
+
+    // Escape only clears the prompt
+    while !emergency::password_prompt(&conn, win, gc, &font, screen, "Locked - enter your password").await? {}
+
+    drop(guard);
+    Ok(())
+}
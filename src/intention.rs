@@ -0,0 +1,38 @@
+// What the user intends to work on today, asked by the morning check-in
+// and shown to the classifier until the day is over
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::storage;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Intention {
+    day: String,
+    text: String,
+}
+
+const INTENTION_FILE: &str = "intention.json";
+
+fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+pub fn save(text: &str) -> Result<()> {
+    storage::save_json(INTENTION_FILE, &Intention { day: today(), text: text.to_string() })
+}
+
+// Today's intention, if the user gave one
+pub fn current() -> Option<String> {
+    let intention: Intention = storage::load_json(INTENTION_FILE)
+        .map_err(|e| warn!("Failed to load today's intention: {}", e))
+        .ok()?;
+    (intention.day == today() && !intention.text.is_empty()).then_some(intention.text)
+}
+
+// Whether the check-in already happened today, answered or not
+pub fn asked_today() -> bool {
+    storage::load_json::<Intention>(INTENTION_FILE).is_ok_and(|intention| intention.day == today())
+}
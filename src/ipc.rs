@@ -0,0 +1,528 @@
+// Unix socket for observing the running daemon
+//
+// Clients connect to the socket and send a single command line:
+//   status           - reply with the current status as one JSON line
+//   watch            - reply with the status, then stream events as JSON lines
+//   pause <duration> - stop checking for a while, e.g. `pause 30m`
+//   resume           - end a pause early
+//   lock-now         - lock immediately, skipping detection and the warning
+//   last-decision    - reply with the last lock decision as one JSON line
+//   lock <duration> [reason] - start a timed lock right away
+//   tab [json]       - the browser's active tab, or none; see browser.rs
+// Control commands reply with a single "ok: ..." or "error: ..." line.
+
+use anyhow::{Result, Context, anyhow};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+use crate::browser;
+use crate::cli;
+use crate::constants::MONTHLY_BUDGET_USD;
+use crate::events::new_id;
+use crate::locks::LockRecord;
+use crate::stats::{self, Month, Spend};
+use crate::tamper;
+use crate::types::{LockReason, Tab};
+
+// Events published by the daemon, each with its own id
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    State { id: String, time: String, state: String },
+    Verdict { id: String, time: String, procrastinating: bool, confidence: Option<u8>, response: String },
+    Cost { id: String, time: String, profile: String, model: String, cost_usd: f64 },
+    Offline { id: String, time: String, action: String },
+    // The id is the lock's, as in transcripts and logged events; the verdict
+    // is the one that triggered it
+    Lock { id: String, time: String, what: String, verdict: Option<String>, reason: Option<LockReason> },
+}
+
+// Requests to the main loop
+pub enum Control {
+    Pause(Duration),
+    Resume,
+    LockNow,
+    Lock { minutes: u64, reason: Option<String> },
+}
+
+// Outcome of the last lock
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Decision {
+    pub time: String,
+    pub decision: String,
+    #[serde(default)]
+    pub lock: Option<String>,
+}
+
+// Snapshot of the daemon returned by the `status` command
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Status {
+    pub profile: String,
+    pub state: String,
+    pub last_check: Option<String>,
+    pub last_verdict: Option<bool>,
+    pub spent_today_usd: f64,
+    #[serde(default)]
+    pub input_tokens_today: u64,
+    #[serde(default)]
+    pub output_tokens_today: u64,
+    // Of all profiles, against MONTHLY_BUDGET_USD
+    #[serde(default)]
+    pub spent_month_usd: f64,
+    #[serde(default)]
+    pub input_tokens_month: u64,
+    #[serde(default)]
+    pub output_tokens_month: u64,
+    #[serde(default)]
+    pub last_decision: Option<Decision>,
+    // Id of the running or last lock
+    #[serde(default)]
+    pub lock: Option<String>,
+    // Left of PROCRASTINATION_BUDGET_MINUTES today
+    #[serde(default)]
+    pub budget_left_minutes: Option<u64>,
+    // The ONBOARDING phase, while it lasts
+    #[serde(default)]
+    pub onboarding: Option<String>,
+}
+
+// Changes to a running lock requested remotely, picked up by the lock screen
+#[derive(Default)]
+struct LockOverride {
+    unlock: bool,
+    extend_minutes: u64,
+}
+
+static EVENTS: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+static STATUS: Mutex<Option<Status>> = Mutex::new(None);
+static LOCK_OVERRIDE: Mutex<LockOverride> = Mutex::new(LockOverride { unlock: false, extend_minutes: 0 });
+
+fn uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+// Without a runtime directory the socket goes into a per-user directory in
+// the shared temp dir, as anyone could take over a socket right in it
+pub fn socket_path() -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("perimedes.sock"),
+        _ => std::env::temp_dir().join(format!("perimedes-ipc-{}", uid())).join("perimedes.sock"),
+    }
+}
+
+// The socket's directory, created 0700 if needed; one we don't own is
+// refused, as other users could drive or replace the socket
+fn private_dir(path: &Path) -> Result<()> {
+    let dir = path.parent().ok_or_else(|| anyhow!("{} has no directory", path.display()))?;
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {},
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != uid() {
+        return Err(anyhow!("{} is not a directory of ours, not serving the control socket there", dir.display()));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    Local::now().format("%H:%M:%S").to_string()
+}
+
+fn events() -> &'static broadcast::Sender<Event> {
+    EVENTS.get_or_init(|| broadcast::channel(64).0)
+}
+
+fn update_status(f: impl FnOnce(&mut Status)) {
+    if let Ok(mut status) = STATUS.lock() {
+        f(status.get_or_insert_with(Status::default));
+    }
+}
+
+pub fn current_status() -> Status {
+    STATUS.lock().ok().and_then(|s| s.clone()).unwrap_or_default()
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    events().subscribe()
+}
+
+// Whether the screen is locked right now
+pub fn locked() -> bool {
+    current_status().state.starts_with("locked")
+}
+
+// End the running lock; false if the screen isn't locked
+pub fn unlock_remotely() -> bool {
+    if !locked() {
+        return false;
+    }
+    if let Ok(mut lock) = LOCK_OVERRIDE.lock() {
+        lock.unlock = true;
+    }
+    true
+}
+
+// Add minutes to the running timed lock; false if there is none
+pub fn extend_lock(minutes: u64) -> bool {
+    if !(locked() && current_status().state.contains(" minute ")) {
+        return false;
+    }
+    if let Ok(mut lock) = LOCK_OVERRIDE.lock() {
+        lock.extend_minutes = lock.extend_minutes.saturating_add(minutes);
+    }
+    true
+}
+
+pub fn take_unlock() -> bool {
+    LOCK_OVERRIDE.lock().map(|mut lock| std::mem::take(&mut lock.unlock)).unwrap_or(false)
+}
+
+pub fn take_extension() -> u64 {
+    LOCK_OVERRIDE.lock().map(|mut lock| std::mem::take(&mut lock.extend_minutes)).unwrap_or(0)
+}
+
+// Publish an event to all watching clients; nobody listening is fine
+fn publish(event: Event) {
+    let _ = events().send(event);
+}
+
+pub fn set_profile(profile: &str) {
+    update_status(|s| s.profile = profile.to_string());
+}
+
+pub fn set_state(state: &str) {
+    update_status(|s| s.state = state.to_string());
+    // Overrides only apply to the lock they were requested for
+    if !state.starts_with("locked") {
+        if let Ok(mut lock) = LOCK_OVERRIDE.lock() {
+            *lock = LockOverride::default();
+        }
+    }
+    publish(Event::State { id: new_id(), time: now(), state: state.to_string() });
+}
+
+pub fn verdict(procrastinating: bool, confidence: Option<u8>, response: &str) {
+    update_status(|s| {
+        s.last_check = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+        s.last_verdict = Some(procrastinating);
+    });
+    publish(Event::Verdict { id: new_id(), time: now(), procrastinating, confidence, response: response.to_string() });
+}
+
+pub fn budget_left(seconds: Option<u64>) {
+    update_status(|s| s.budget_left_minutes = seconds.map(|seconds| seconds.div_ceil(60)));
+}
+
+pub fn onboarding(phase: Option<String>) {
+    update_status(|s| s.onboarding = phase);
+}
+
+pub fn cost(profile: &str, model: &str, cost_usd: f64) {
+    publish(Event::Cost { id: new_id(), time: now(), profile: profile.to_string(), model: model.to_string(), cost_usd });
+}
+
+pub fn spend(today: &Spend, month: &Month) {
+    update_status(|s| {
+        s.spent_today_usd = today.cost_usd;
+        s.input_tokens_today = today.input_tokens;
+        s.output_tokens_today = today.output_tokens;
+        s.spent_month_usd = month.cost_usd;
+        s.input_tokens_month = month.input_tokens;
+        s.output_tokens_month = month.output_tokens;
+    });
+}
+
+// A lock began
+pub fn lock_started(lock: &LockRecord) {
+    update_status(|s| s.lock = Some(lock.id.clone()));
+    publish(Event::Lock {
+        id: lock.id.clone(),
+        time: now(),
+        what: lock.what.clone(),
+        verdict: lock.verdict.clone(),
+        reason: lock.reason,
+    });
+}
+
+pub fn decision(decision: &str) {
+    let decision = Decision {
+        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        decision: decision.to_string(),
+        lock: current_status().lock,
+    };
+    update_status(|s| s.last_decision = Some(decision));
+}
+
+// The judge was unreachable and the offline policy decided instead
+pub fn offline(action: &str) {
+    publish(Event::Offline { id: new_id(), time: now(), action: action.to_string() });
+}
+
+// Listen on the control socket, serving each client on its own task.
+// Control commands are passed to the main loop through the returned channel.
+pub async fn serve() -> Result<mpsc::UnboundedReceiver<Control>> {
+    let path = socket_path();
+    private_dir(&path)?;
+
+    // Remove a stale socket left behind by a previous run, but nothing else
+    if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+        if !metadata.file_type().is_socket() || metadata.uid() != uid() {
+            return Err(anyhow!("{} is not a socket of ours, not replacing it", path.display()));
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind {}", path.display()))?;
+
+    let (control, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let control = control.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_client(stream, control).await {
+                            warn!("IPC client error: {}", e);
+                        }
+                    });
+                },
+                Err(e) => warn!("IPC accept error: {}", e),
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+async fn handle_client(stream: UnixStream, control: mpsc::UnboundedSender<Control>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let mut words = line.split_whitespace();
+    let reply = match (words.next().unwrap_or(""), words.next()) {
+        ("status", None) | ("watch", None) => {
+            // Subscribe before taking the snapshot so no event is lost in between
+            let mut rx = events().subscribe();
+            let status = serde_json::to_string(&current_status())?;
+            writer.write_all(format!("{}\n", status).as_bytes()).await?;
+
+            if line.trim() == "status" {
+                return Ok(());
+            }
+
+            loop {
+                match rx.recv().await {
+                    Ok(event) => {
+                        let json = serde_json::to_string(&event)?;
+                        writer.write_all(format!("{}\n", json).as_bytes()).await?;
+                    },
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        },
+        ("last-decision", None) => match current_status().last_decision {
+            Some(decision) => serde_json::to_string(&decision)?,
+            None => "error: no lock decision yet".to_string(),
+        },
+        ("pause", Some(duration)) => match cli::parse_duration(duration) {
+            Ok(duration) => {
+                let minutes = duration.as_secs().div_ceil(60);
+                match stats::record_pause(minutes) {
+                    Ok(()) => {
+                        tamper::record("pause", &format!("{} minutes", minutes))?;
+                        control.send(Control::Pause(duration))?;
+                        format!("ok: pausing for {} minutes", minutes)
+                    },
+                    Err(e) => format!("error: {}", e),
+                }
+            },
+            Err(e) => format!("error: {}", e),
+        },
+        ("resume", None) => {
+            tamper::record("resume", "")?;
+            control.send(Control::Resume)?;
+            "ok: resuming".to_string()
+        },
+        ("lock", Some(duration)) => match cli::parse_duration(duration) {
+            Ok(duration) => {
+                let minutes = cli::lock_minutes(duration);
+                let reason = words.collect::<Vec<_>>().join(" ");
+                let reason = (!reason.is_empty()).then_some(reason);
+                control.send(Control::Lock { minutes, reason })?;
+                format!("ok: locking for {} minutes", minutes)
+            },
+            Err(e) => format!("error: {}", e),
+        },
+        ("lock-now", None) => {
+            control.send(Control::LockNow)?;
+            "ok: locking".to_string()
+        },
+        ("tab", None) => {
+            browser::set_active(None);
+            "ok: no tab".to_string()
+        },
+        ("tab", Some(_)) => match serde_json::from_str::<Tab>(line.trim()["tab".len()..].trim()) {
+            Ok(tab) => {
+                browser::set_active(Some(tab));
+                "ok: tab".to_string()
+            },
+            Err(e) => format!("error: {}", e),
+        },
+        _ => format!("error: unknown command '{}'", line.trim()),
+    };
+
+    writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+    Ok(())
+}
+
+// Whether a daemon is listening on the control socket
+pub async fn daemon_running() -> bool {
+    UnixStream::connect(socket_path()).await.is_ok()
+}
+
+// Client side of control commands: send one and print the reply
+pub async fn send(command: &str) -> Result<()> {
+    let reply = request(command).await?;
+    match serde_json::from_str::<Decision>(&reply) {
+        Ok(decision) => println!("{} {}", decision.time, decision.decision),
+        Err(_) => println!("{}", reply.strip_prefix("ok: ").unwrap_or(&reply)),
+    }
+    Ok(())
+}
+
+// Send a command and return the reply, or the error it reports
+pub async fn request(command: &str) -> Result<String> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path).await
+        .with_context(|| format!("Failed to connect to {}. Is perimedes running?", path.display()))?;
+
+    stream.write_all(format!("{}\n", command).as_bytes()).await?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await?;
+    let reply = reply.trim();
+
+    if let Some(error) = reply.strip_prefix("error: ") {
+        return Err(anyhow!("{}", error));
+    }
+    Ok(reply.to_string())
+}
+
+// Client side of the `status` command
+pub async fn print_status(watch: bool) -> Result<()> {
+    let path = socket_path();
+    let stream = UnixStream::connect(&path).await
+        .with_context(|| format!("Failed to connect to {}. Is perimedes running?", path.display()))?;
+
+    let (reader, mut writer) = stream.into_split();
+    let command = if watch { "watch\n" } else { "status\n" };
+    writer.write_all(command.as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+
+    if let Some(line) = lines.next_line().await? {
+        let status: Status = serde_json::from_str(&line)
+            .with_context(|| format!("Unexpected reply: {}", line))?;
+        print_status_line(&status);
+    }
+
+    if watch {
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str::<Event>(&line) {
+                Ok(event) => print_event(&event),
+                Err(_) => println!("{}", line),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ANSI colors for terminal output
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const RESET: &str = "\x1b[0m";
+
+fn print_status_line(status: &Status) {
+    let verdict = match status.last_verdict {
+        Some(true) => format!("{}PROCRASTINATING{}", RED, RESET),
+        Some(false) => format!("{}NOT PROCRASTINATING{}", GREEN, RESET),
+        None => "none yet".to_string(),
+    };
+
+    println!("profile: {}", status.profile);
+    println!("state: {}{}{}", YELLOW, status.state, RESET);
+    println!("last check: {} ({})", status.last_check.as_deref().unwrap_or("never"), verdict);
+    println!("spent today: ${:.4} ({} tokens in, {} out)",
+             status.spent_today_usd, status.input_tokens_today, status.output_tokens_today);
+    let budget = match MONTHLY_BUDGET_USD {
+        Some(budget) if status.spent_month_usd >= budget => format!(" of ${:.2}, {}over budget, judging locally{}", budget, RED, RESET),
+        Some(budget) => format!(" of ${:.2}", budget),
+        None => String::new(),
+    };
+    println!("spent this month: ${:.4}{} ({} tokens in, {} out)",
+             status.spent_month_usd, budget, status.input_tokens_month, status.output_tokens_month);
+    if let Some(minutes) = status.budget_left_minutes {
+        println!("procrastination budget: {} min left", minutes);
+    }
+    if let Some(phase) = &status.onboarding {
+        println!("onboarding: {}", phase);
+    }
+    if let Some(decision) = &status.last_decision {
+        println!("last decision: {} ({})", decision.decision, decision.time);
+    }
+}
+
+fn print_event(event: &Event) {
+    match event {
+        Event::State { time, state, .. } => {
+            println!("{} {}state{} {}", time, YELLOW, RESET, state);
+        },
+        Event::Verdict { time, procrastinating, confidence, response, .. } => {
+            let (color, verdict) = if *procrastinating {
+                (RED, "PROCRASTINATING")
+            } else {
+                (GREEN, "NOT PROCRASTINATING")
+            };
+            let confidence = confidence.map(|c| format!(" ({}%)", c)).unwrap_or_default();
+            println!("{} {}verdict{} {}{}{}{}", time, color, RESET, color, verdict, RESET, confidence);
+            for line in response.lines() {
+                println!("         {}", line);
+            }
+        },
+        Event::Cost { time, profile, model, cost_usd, .. } => {
+            println!("{} {}cost{} ${:.4} ({}, {})", time, BLUE, RESET, cost_usd, profile, model);
+        },
+        Event::Offline { time, action, .. } => {
+            println!("{} {}offline{} {}", time, RED, RESET, action);
+        },
+        Event::Lock { id, time, what, reason, .. } => {
+            let reason = reason.map(|reason| format!(", {}", reason.name())).unwrap_or_default();
+            println!("{} {}lock{} {} ({}{})", time, RED, RESET, what, id, reason);
+        },
+    }
+}
@@ -0,0 +1,224 @@
+// The classifier deciding whether the screen shows procrastination
+
+use anyhow::{Result, Context};
+use chrono::Local;
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::future::Future;
+use tracing::{debug, warn};
+
+use crate::api;
+use crate::browser;
+use crate::capture::{active_window, is_excluded, take_screenshot, without_title};
+use crate::constants::{API_URL, EXCLUDED_PLACEHOLDER, VISION_PROMPT};
+use crate::events;
+use crate::exclude;
+use crate::focus;
+use crate::ipc;
+use crate::media;
+use crate::ocr::ocr_screenshot;
+use crate::prompts::{self, Template};
+use crate::redact;
+use crate::stats;
+use crate::tempstore;
+use crate::trust;
+use crate::types::{
+    AnthropicRequest, AnthropicResponse, Category, ImageSource, InputBlock, LockReason, Message, Profile, ScreenRecord, VisionMessage,
+};
+use crate::vision;
+
+// Verdict of the classifier on the screen context
+#[derive(Debug, PartialEq, Default)]
+pub struct Classification {
+    pub procrastinating: bool,
+    // Lock message for a positive verdict
+    pub message: Option<String>,
+    // 0 to 100, if the classifier said
+    pub confidence: Option<u8>,
+    // For a positive verdict
+    pub reason: Option<LockReason>,
+    pub category: Option<Category>,
+}
+
+// Classifier for the monitoring loop; in vision mode it also gets the
+// recent captures tiled into one PNG
+pub trait ProcrastinationJudge {
+    fn classify(&mut self, profile: &Profile, text: &str, frames: Option<&[u8]>)
+        -> impl Future<Output = Result<Classification>> + Send;
+
+    // Second look by the profile's confirm_model at a positive verdict
+    fn confirm(&mut self, profile: &Profile, text: &str, frames: Option<&[u8]>)
+        -> impl Future<Output = Result<Classification>> + Send {
+        self.classify(profile, text, frames)
+    }
+}
+
+// The classifier model of the profile, over the Messages API
+pub struct AnthropicJudge {
+    client: Client,
+    api_key: String,
+    url: String,
+}
+
+impl AnthropicJudge {
+    pub fn new(client: Client, api_key: &str) -> AnthropicJudge {
+        AnthropicJudge { client, api_key: api_key.to_string(), url: API_URL.to_string() }
+    }
+
+    // Send requests elsewhere, e.g. to a fake server
+    pub fn with_url(mut self, url: &str) -> AnthropicJudge {
+        self.url = url.to_string();
+        self
+    }
+}
+
+impl ProcrastinationJudge for AnthropicJudge {
+    fn classify(&mut self, profile: &Profile, text: &str, frames: Option<&[u8]>)
+        -> impl Future<Output = Result<Classification>> + Send {
+        classify(&self.client, &self.url, &self.api_key, profile, profile.classify_model, text, frames)
+    }
+
+    fn confirm(&mut self, profile: &Profile, text: &str, frames: Option<&[u8]>)
+        -> impl Future<Output = Result<Classification>> + Send {
+        let model = profile.confirm_model.unwrap_or(profile.classify_model);
+        classify(&self.client, &self.url, &self.api_key, profile, model, text, frames)
+    }
+}
+
+pub async fn check_procrastination(
+    client: &Client,
+    api_key: &str,
+    profile: &Profile,
+    text: &str,
+) -> Result<Classification> {
+    classify(client, API_URL, api_key, profile, profile.classify_model, text, None).await
+}
+
+async fn classify(
+    client: &Client,
+    url: &str,
+    api_key: &str,
+    profile: &Profile,
+    model: &str,
+    text: &str,
+    frames: Option<&[u8]>,
+) -> Result<Classification> {
+    // Original implementation commented out for testing
+    let prompt = prompts::render(Template::Classify, text)?;
+
+    let request = match frames {
+        Some(png) => api::post(client, url, api_key, &AnthropicRequest {
+            model: model.to_string(),
+            system: Vec::new(),
+            messages: vec![VisionMessage {
+                role: "user".to_string(),
+                content: vec![
+                    InputBlock::Image {
+                        source: ImageSource {
+                            kind: "base64".to_string(),
+                            media_type: "image/png".to_string(),
+                            data: vision::base64(png),
+                        },
+                    },
+                    InputBlock::Text { text: format!("{}\n\n{}", VISION_PROMPT, prompt), cache_control: None },
+                ],
+            }],
+            max_tokens: 100,
+            tools: Vec::new(),
+            temperature: None,
+            stream: false,
+        }),
+        None => api::post(client, url, api_key, &AnthropicRequest {
+            model: model.to_string(),
+            system: Vec::new(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: prompt,
+            }],
+            max_tokens: 100,
+            tools: Vec::new(),
+            temperature: None,
+            stream: false,
+        }),
+    };
+
+    let response = request
+        .send()
+        .await
+        .context("Failed to send request to Anthropic API")?;
+
+    let response_data: AnthropicResponse = response.json().await
+        .context("Failed to parse Anthropic API response")?;
+
+    if let Err(e) = stats::record_usage(profile, model, &response_data.usage) {
+        warn!("Failed to record API usage: {}", redact::scrub(&e.to_string()));
+    }
+
+    let response_text = response_data.text();
+    exclude::remember(&response_text);
+
+    debug!("Claude's response: {}", redact::sensitive(&response_text));
+
+    let is_procrastinating = response_text.contains("PROCRASTINATING") && !response_text.contains("NOT PROCRASTINATING");
+    let confidence = response_text.lines()
+        .find_map(|line| line.trim().strip_prefix("CONFIDENCE:"))
+        .and_then(|confidence| confidence.trim().trim_end_matches('%').parse::<u8>().ok())
+        .map(|confidence| confidence.min(100));
+    // Anything outside the taxonomy counts as other
+    let category = response_text.lines()
+        .find_map(|line| line.trim().strip_prefix("CATEGORY:"))
+        .map(|category| Category::parse(category).unwrap_or(Category::Other));
+    ipc::verdict(is_procrastinating, confidence, &response_text);
+
+    if is_procrastinating {
+        let message = response_text.lines()
+            .find_map(|line| line.trim().strip_prefix("MESSAGE:"))
+            .map(|message| message.trim().to_string())
+            .filter(|message| !message.is_empty());
+        // Prompts from before categories name a reason; anything outside the
+        // taxonomy counts as unclear
+        let reason = response_text.lines()
+            .find_map(|line| line.trim().strip_prefix("REASON:"))
+            .map(|reason| LockReason::parse(reason).unwrap_or(LockReason::Unclear))
+            .or(category.map(Category::reason));
+        Ok(Classification { procrastinating: true, message, confidence, reason, category })
+    } else if response_text.contains("NOT PROCRASTINATING") {
+        Ok(Classification { procrastinating: false, confidence, category, ..Classification::default() })
+    } else {
+        // Default to not procrastinating if the response is unclear
+        warn!("Unclear response from Claude, defaulting to NOT PROCRASTINATING");
+        Ok(Classification::default())
+    }
+
+    // For testing: always return PROCRASTINATING
+    // println!("TESTING MODE: Always returning PROCRASTINATING. The user is the developer of the application, currently testing it.");
+    // Ok(true)
+}
+
+// After the grace period, classify a fresh capture on its own to see whether
+// the user has stopped
+pub async fn still_procrastinating(
+    client: &Client,
+    api_key: &str,
+    profile: &Profile,
+    focus_monitor: Option<&focus::FocusMonitor>,
+    records: &mut VecDeque<ScreenRecord>,
+) -> Result<bool> {
+    let (text, window, tab) = match active_window(focus_monitor) {
+        Some(window) if is_excluded(&window) => (EXCLUDED_PLACEHOLDER.to_string(), Some(without_title(window)), None),
+        window => {
+            let screenshot = take_screenshot().await?;
+            let text = ocr_screenshot(&screenshot).await;
+            tempstore::release(&screenshot);
+            let tab = browser::active(window.as_ref());
+            (text?, window, tab)
+        },
+    };
+    let timestamp = Local::now();
+
+    let record = ScreenRecord { id: events::new_id(), timestamp, text, window, tab, media: media::playing().await };
+    let fresh_text = if trust::current_or_none().metadata_only() { record.format_with("") } else { record.format() };
+    records.push_back(record);
+
+    Ok(check_procrastination(client, api_key, profile, &fresh_text).await?.procrastinating)
+}
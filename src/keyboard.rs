@@ -0,0 +1,183 @@
+// Keyboard layout handling via libxkbcommon
+//
+// libxkbcommon is loaded at runtime, so the lock screen still works (with
+// plain ASCII input) on systems without it. The keymap is compiled from the
+// layout names the X server publishes on the root window, and modifier and
+// group state is taken from each key event, so shifted characters, AltGr,
+// non-US layouts and dead keys behave as they do in other applications.
+
+use anyhow::{Result, anyhow};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::sync::Arc;
+use x11rb::protocol::xproto::*;
+use xkbcommon_dl::{
+    xkbcommon_handle, xkbcommon_option, xkbcommon_compose_handle, xkbcommon_compose_option,
+    xkb_context, xkb_keymap, xkb_state, xkb_compose_table, xkb_compose_state,
+    xkb_context_flags, xkb_keymap_compile_flags, xkb_compose_compile_flags,
+    xkb_compose_state_flags, xkb_compose_status, xkb_compose_feed_result, xkb_rule_names,
+};
+
+pub struct Keyboard {
+    context: *mut xkb_context,
+    keymap: *mut xkb_keymap,
+    state: *mut xkb_state,
+    compose_table: *mut xkb_compose_table,
+    compose_state: *mut xkb_compose_state,
+}
+
+// The xkbcommon objects belong to this Keyboard alone, so it can move to
+// another thread along with the lock screen
+unsafe impl Send for Keyboard {}
+
+// Read the rules, model, layout, variant and options set by setxkbmap
+fn rule_names(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> Result<Vec<CString>> {
+    let atom = conn.intern_atom(false, b"_XKB_RULES_NAMES")?.reply()?.atom;
+    let reply = conn.get_property(false, screen.root, atom, AtomEnum::STRING, 0, 1024)?.reply()?;
+
+    let mut names: Vec<CString> = reply.value
+        .split(|b| *b == 0)
+        .take(5)
+        .map(|name| CString::new(name).unwrap_or_default())
+        .collect();
+    names.resize(5, CString::default());
+
+    Ok(names)
+}
+
+// Locale used to pick the compose table (dead keys, Multi_key sequences)
+fn locale() -> CString {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "C".to_string());
+    CString::new(locale).unwrap_or_default()
+}
+
+impl Keyboard {
+    pub fn new(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> Result<Keyboard> {
+        if xkbcommon_option().is_none() {
+            return Err(anyhow!("libxkbcommon could not be loaded"));
+        }
+        let xkb = xkbcommon_handle();
+
+        // Empty names make libxkbcommon fall back to its defaults
+        let names = rule_names(conn, screen)?;
+        let ptr = |name: &CString| -> *const c_char {
+            if name.as_bytes().is_empty() { std::ptr::null() } else { name.as_ptr() }
+        };
+        let rules = xkb_rule_names {
+            rules: ptr(&names[0]),
+            model: ptr(&names[1]),
+            layout: ptr(&names[2]),
+            variant: ptr(&names[3]),
+            options: ptr(&names[4]),
+        };
+
+        unsafe {
+            let context = (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+            if context.is_null() {
+                return Err(anyhow!("Failed to create xkb context"));
+            }
+
+            let keymap = (xkb.xkb_keymap_new_from_names)(
+                context, &rules, xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS);
+            if keymap.is_null() {
+                (xkb.xkb_context_unref)(context);
+                return Err(anyhow!("Failed to compile keymap for layout {:?}", names[2]));
+            }
+
+            let state = (xkb.xkb_state_new)(keymap);
+            if state.is_null() {
+                (xkb.xkb_keymap_unref)(keymap);
+                (xkb.xkb_context_unref)(context);
+                return Err(anyhow!("Failed to create xkb state"));
+            }
+
+            // Compose support is optional; without it dead keys are ignored
+            let (compose_table, compose_state) = match xkbcommon_compose_option() {
+                Some(compose) => {
+                    let table = (compose.xkb_compose_table_new_from_locale)(
+                        context, locale().as_ptr(),
+                        xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS);
+                    if table.is_null() {
+                        (std::ptr::null_mut(), std::ptr::null_mut())
+                    } else {
+                        let state = (compose.xkb_compose_state_new)(
+                            table, xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS);
+                        (table, state)
+                    }
+                },
+                None => (std::ptr::null_mut(), std::ptr::null_mut()),
+            };
+
+            Ok(Keyboard { context, keymap, state, compose_table, compose_state })
+        }
+    }
+
+    // Translate a key press into its keysym and the text it produces, if any.
+    // `modifiers` is the state field of the X key event.
+    pub fn key_press(&mut self, keycode: u8, modifiers: u16) -> (u32, Option<String>) {
+        let xkb = xkbcommon_handle();
+        let mods = u32::from(modifiers & 0xff);
+        let group = u32::from((modifiers >> 13) & 0x3);
+
+        unsafe {
+            (xkb.xkb_state_update_mask)(self.state, mods, 0, 0, 0, 0, group);
+
+            let keycode = u32::from(keycode);
+            let keysym = (xkb.xkb_state_key_get_one_sym)(self.state, keycode);
+
+            if !self.compose_state.is_null() {
+                let compose = xkbcommon_compose_handle();
+                if (compose.xkb_compose_state_feed)(self.compose_state, keysym)
+                    == xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED
+                {
+                    match (compose.xkb_compose_state_get_status)(self.compose_state) {
+                        xkb_compose_status::XKB_COMPOSE_COMPOSING => return (keysym, None),
+                        xkb_compose_status::XKB_COMPOSE_CANCELLED => {
+                            (compose.xkb_compose_state_reset)(self.compose_state);
+                            return (keysym, None);
+                        },
+                        xkb_compose_status::XKB_COMPOSE_COMPOSED => {
+                            let mut buffer = [0 as c_char; 64];
+                            (compose.xkb_compose_state_get_utf8)(
+                                self.compose_state, buffer.as_mut_ptr(), buffer.len());
+                            let composed = (compose.xkb_compose_state_get_one_sym)(self.compose_state);
+                            (compose.xkb_compose_state_reset)(self.compose_state);
+                            return (composed, to_text(&buffer));
+                        },
+                        xkb_compose_status::XKB_COMPOSE_NOTHING => {},
+                    }
+                }
+            }
+
+            let mut buffer = [0 as c_char; 64];
+            (xkb.xkb_state_key_get_utf8)(self.state, keycode, buffer.as_mut_ptr(), buffer.len());
+            (keysym, to_text(&buffer))
+        }
+    }
+}
+
+// Printable text from a NUL-terminated buffer; control characters are dropped
+fn to_text(buffer: &[c_char]) -> Option<String> {
+    let text = unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy();
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        let xkb = xkbcommon_handle();
+        unsafe {
+            if !self.compose_state.is_null() {
+                let compose = xkbcommon_compose_handle();
+                (compose.xkb_compose_state_unref)(self.compose_state);
+                (compose.xkb_compose_table_unref)(self.compose_table);
+            }
+            (xkb.xkb_state_unref)(self.state);
+            (xkb.xkb_keymap_unref)(self.keymap);
+            (xkb.xkb_context_unref)(self.context);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// perimedes: watches the screen, asks a model whether it shows
+// procrastination, and locks the screen behind a chat with a judge.
+//
+// The pipeline is split into modules that can be used on their own:
+//   capture    - screenshots and the focused window, kept in a private
+//                temporary directory by tempstore
+//   ocr        - text recognition of captures
+//   judge      - classification of the recognized text
+//   lockscreen - the lock screen with the judge chat
+//   storage    - persistent state in the XDG state directory, used by
+//                stats, verdicts, transcripts and events
+//   daemon     - the monitoring loop tying them together, generic over
+//                the capture, OCR and judge stages
+//   mock       - stand-ins for those stages, for tests
+// Configuration is compiled in, see constants.
+
+pub mod api;
+pub mod browser;
+pub mod calendar;
+pub mod capture;
+pub mod cli;
+pub mod config;
+pub mod constants;
+pub mod context;
+pub mod daemon;
+pub mod deadline;
+pub mod dedup;
+pub mod doctor;
+pub mod enforcement;
+pub mod events;
+pub mod focus;
+pub mod ipc;
+pub mod judge;
+pub mod lockscreen;
+pub mod logging;
+pub mod mock;
+pub mod ocr;
+pub mod policy;
+pub mod redact;
+pub mod replay;
+pub mod schedule;
+pub mod selftest;
+pub mod report;
+pub mod secrets;
+pub mod service;
+pub mod stats;
+pub mod storage;
+pub mod tamper;
+pub mod transcripts;
+pub mod types;
+pub mod verdicts;
+pub mod watchdog;
+
+mod call;
+mod circumvention;
+mod emergency;
+mod exclude;
+mod grab;
+mod handoff;
+mod history;
+mod idle;
+mod idlelock;
+mod intention;
+mod keyboard;
+mod locks;
+mod media;
+mod monitors;
+mod notify;
+mod ocr_cache;
+mod onboarding;
+mod overlay;
+mod pam;
+mod prompts;
+mod persona;
+mod phone;
+mod partner;
+mod stream;
+mod telegram;
+mod tempstore;
+mod tiling;
+mod tiles;
+mod timer;
+mod trust;
+mod vision;
+mod warning;
+mod winddown;
+mod window;
@@ -0,0 +1,47 @@
+// Every lock, with what triggered it and why, appended to locks.jsonl in
+// the state directory for the stats breakdown by reason
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::events;
+use crate::ipc;
+use crate::storage;
+use crate::types::LockReason;
+
+#[derive(Serialize, Deserialize)]
+pub struct LockRecord {
+    pub id: String,
+    pub time: String,
+    pub what: String,
+    // The verdict that triggered it
+    #[serde(default)]
+    pub verdict: Option<String>,
+    #[serde(default)]
+    pub reason: Option<LockReason>,
+}
+
+const LOCKS_FILE: &str = "locks.jsonl";
+
+pub fn load() -> Result<Vec<LockRecord>> {
+    storage::load_jsonl(LOCKS_FILE)
+}
+
+// A lock began: record and announce it, returning its id
+pub fn started(what: &str, verdict: Option<&str>, reason: Option<LockReason>) -> String {
+    let lock = LockRecord {
+        id: events::new_id(),
+        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        what: what.to_string(),
+        verdict: verdict.map(str::to_string),
+        reason,
+    };
+    info!("Lock {}: {}{}", lock.id, what, reason.map(|r| format!(" ({})", r.name())).unwrap_or_default());
+    ipc::lock_started(&lock);
+    if let Err(e) = storage::append_jsonl(LOCKS_FILE, &lock) {
+        warn!("Failed to record the lock: {}", e);
+    }
+    lock.id
+}
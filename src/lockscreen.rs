@@ -1,37 +1,62 @@
 use anyhow::{Result, Context, anyhow};
 use reqwest::Client;
 use std::collections::VecDeque;
+use std::path::Path;
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
 use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
 use x11rb::CURRENT_TIME;
+use tracing::{debug, error, info, warn};
 
 // Import timer functions and window utilities
 use crate::timer;
 use crate::window;
 
+use crate::api;
+use crate::deadline::{ActiveLock, Deadline};
+use crate::emergency;
+use crate::exclude;
+use crate::grab;
+use crate::idlelock;
+use crate::ipc;
+use crate::intention;
+use crate::media;
+use crate::keyboard::Keyboard;
+use crate::history::InputHistory;
+use crate::redact;
+use crate::replay;
+use crate::notify;
+use crate::persona;
+use crate::stats;
+use crate::stream;
+use crate::transcripts;
+use crate::verdicts;
 use crate::types::{
-    LockResult, LockState, Message, AnthropicRequest, AnthropicResponse, ChatMessage
+    LockResult, LockState, Message, AnthropicRequest, ChatMessage, Profile, Tool,
+    OfflinePolicy, Notification, Persona, VisionMessage, InputBlock, CacheControl, InputPosition
 };
 
 // Import constants
 use crate::constants::{
-    API_URL, BG_COLOR, TEXT_COLOR,
-    SYSTEM_COLOR, USER_COLOR, ASSISTANT_COLOR, FONT_NAME,
-    MAX_MESSAGES, MIN_LOCK_MINUTES, MAX_LOCK_MINUTES,
-    JUDGE_MODEL, JUDGE_PROMPT, keysym
+    API_URL, THEME, CHAT_INACTIVITY_SECS,
+    MAX_MESSAGES, MIN_LOCK_MINUTES, MAX_LOCK_MINUTES, MAX_ALLOWANCE_MINUTES, LOCK_ESCALATION_HOURS,
+    PERSONAS, DECISION_TOOL, UNLOCK_PHRASE, FADE_IN_MS, OFFLINE_POLICY, OFFLINE_MAX_FAILURES,
+    OFFLINE_RETRY_SECS, OFFLINE_DISTRACTIONS, OFFLINE_LOCK_MINUTES, SHOW_SCREENSHOT_BACKGROUND, FEEDBACK_KEY, FEEDBACK_KEY_NAME, CHECK_IN_SIZE, PROMPT_CACHING, keysym
 };
 
 // Main function that runs the interactive lock screen with Claude chat
 pub async fn run_interactive_lock_screen(
     api_key: &str,
+    profile: &Profile,
     unlock_phrase: &str,
     screen_context: &str,
+    lock_message: Option<&str>,
+    screenshot: Option<&Path>,
 ) -> Result<LockResult> {
-    println!("Locking screen with interactive chat functionality.");
+    info!("Locking screen with interactive chat functionality.");
 
     // Create a reqwest client for API calls
     let client = Client::new();
@@ -40,51 +65,100 @@ pub async fn run_interactive_lock_screen(
     let unlock_phrase = unlock_phrase.to_string();
 
     // Initialize X11 and run the lock screen
-    match decide(&client, api_key, &unlock_phrase, screen_context).await {
+    ipc::set_state("locked: chatting with judge");
+    media::pause_for_lock().await;
+    match decide(&client, api_key, profile, &unlock_phrase, screen_context, lock_message, screenshot).await {
         Ok(result) => {
             match result {
                 LockResult::Unlocked => {
-                    println!("Screen unlocked.");
+                    info!("Screen unlocked.");
                     Ok(LockResult::Unlocked)
                 },
+                LockResult::UnlockedFor { minutes, purpose } => {
+                    info!("Screen unlocked for {} minutes: {}", minutes, purpose);
+                    Ok(LockResult::UnlockedFor { minutes, purpose })
+                },
                 LockResult::TimedLock(minutes) => {
                     // Start the timer within X11 - chat session is done,
                     // but we need to enforce the lock timer
-                    println!("Starting lock timer for {} minutes...", minutes);
+                    info!("Starting lock timer for {} minutes...", minutes);
+                    ipc::set_state(&format!("locked: {} minute timer", minutes));
 
                     // Run the X11 timer with the lock minutes
-                    display_lock_timer(minutes).await?;
+                    display_lock_timer(minutes, None).await?;
 
-                    println!("Lock timer completed.");
+                    info!("Lock timer completed.");
                     Ok(LockResult::TimedLock(minutes))
                 }
             }
         },
         Err(e) => {
-            eprintln!("Error in interactive lock screen: {}", e);
+            error!("Error in interactive lock screen: {}", redact::scrub(&e.to_string()));
             Err(e)
         }
     }
 }
 
 // Use display_lock_timer from timer module
-async fn display_lock_timer(minutes: u64) -> Result<()> {
-    timer::display_lock_timer(minutes, grab_keyboard_and_mouse).await
+pub async fn display_lock_timer(minutes: u64, label: Option<&str>) -> Result<()> {
+    // The judge's reasoning, if it just decided on this lock
+    let snippet = ipc::current_status().lock
+        .and_then(|lock| transcripts::judge_said(&lock)
+            .map_err(|e| warn!("Failed to read the lock chat: {}", e))
+            .ok()
+            .flatten());
+    let lock = ActiveLock {
+        deadline: Deadline::after(Duration::from_secs(minutes * 60)),
+        label: label.map(str::to_string),
+        snippet,
+        handed_over: false,
+    };
+    media::pause_for_lock().await;
+    timer::display_lock_timer(lock).await
 }
 
-// Initialize conversation with the system prompt and screen context
-fn initialize_conversation(conversation: &mut Vec<Message>, screen_context: &str) {
-    // System prompt
-    conversation.push(Message {
-        role: "assistant".to_string(),
-        content: JUDGE_PROMPT.to_string(),
-    });
+// The rest of a timed lock running on another machine
+pub async fn display_handed_over_lock(deadline: Deadline, label: &str) -> Result<()> {
+    let lock = ActiveLock { deadline, label: Some(label.to_string()), snippet: None, handed_over: true };
+    media::pause_for_lock().await;
+    timer::display_lock_timer(lock).await
+}
+
+// The rest of a lock the last run didn't finish
+pub async fn resume_lock(lock: ActiveLock) -> Result<()> {
+    media::pause_for_lock().await;
+    timer::display_lock_timer(lock).await
+}
+
+// Lock until the account password is entered, for the idle lock
+pub async fn idle_lock() -> Result<()> {
+    media::pause_for_lock().await;
+    idlelock::run().await
+}
+
+// Opens the user message with the screen context in lock chats
+pub const SCREEN_CONTEXT_INTRO: &str = "Here's what was on my screen that triggered the lock:";
 
+// Initialize conversation with the screen context; returns the system prompt
+fn initialize_conversation(
+    conversation: &mut Vec<Message>,
+    persona: &Persona,
+    screen_context: &str,
+    earlier: usize,
+    (min, max): (u64, u64),
+) -> String {
     // Add screen context if provided
     if !screen_context.is_empty() {
+        let mut content = format!("{}\n\n{}", SCREEN_CONTEXT_INTRO, screen_context);
+        if earlier > 0 {
+            content += &format!(
+                "\n\n(This is lock {} within {} hours, so locks now last {}-{} minutes.)",
+                earlier + 1, LOCK_ESCALATION_HOURS, min, max
+            );
+        }
         conversation.push(Message {
             role: "user".to_string(),
-            content: format!("Here's what was on my screen that triggered the lock:\n\n{}", screen_context),
+            content,
         });
 
         // Initial assistant response acknowledging the context
@@ -93,14 +167,19 @@ fn initialize_conversation(conversation: &mut Vec<Message>, screen_context: &str
             content: "I've reviewed the content that was on your screen. Now, please explain why you should be allowed to continue.".to_string(),
         });
     }
+
+    persona::prompt(persona, screen_context)
 }
 
 // Implementation of the interactive lock screen
 async fn decide(
     client: &Client,
     api_key: &str,
+    profile: &Profile,
     unlock_phrase: &str,
     screen_context: &str,
+    lock_message: Option<&str>,
+    screenshot: Option<&Path>,
 ) -> Result<LockResult> {
     // Connect to the X server
     let (conn, screen_num) = x11rb::connect(None)
@@ -110,59 +189,115 @@ async fn decide(
     let screen = &conn.setup().roots[screen_num];
 
     // Create lock window
-    let mut locks = create_lock_windows(&conn, screen)?;
+    let mut locks = create_lock_windows(&conn, screen, screenshot, false)?;
 
-    // Initialize the conversation with system prompt and screen context
-    if let Some(conversation) = &mut locks[0].conversation {
-        initialize_conversation(conversation, screen_context);
+    // Start fully transparent if a compositor will fade the window in
+    if FADE_IN_MS > 0 && window::compositor_running(&conn, screen_num)? {
+        let opacity = conn.intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?.reply()?.atom;
+        for lock in &locks {
+            conn.change_property32(PropMode::REPLACE, lock.win, opacity, AtomEnum::CARDINAL, &[0])?;
+        }
     }
 
-    // Lock keyboard and mouse
-    grab_keyboard_and_mouse(&conn, screen)?;
-
-    // Map the windows to display them
+    // Map the windows to display them; the pointer can only be confined
+    // to a viewable window, so this has to happen before the grab
     for lock in &locks {
         conn.map_window(lock.win)?;
     }
     conn.flush()?;
 
+    // Lock keyboard and mouse, until the guard goes out of scope
+    let _guard = grab::GrabGuard::new(&conn, screen, locks.iter().map(|lock| lock.win).collect(), locks[0].cursor);
+    grab::grab_keyboard_and_mouse(&conn, screen, locks[0].win, locks[0].cursor).await?;
+
     // Set to chat mode
     locks[0].state = LockState::Chat;
     set_lock_color(&conn, &locks, &LockState::Chat)?;
 
-    // Add initial message to display
-    let intro_message = "Locked:";
-    locks[0].messages.push_back((ChatMessage::System(intro_message.to_string()), SYSTEM_COLOR));
+    // Choose the judge, then initialize the conversation with its system
+    // prompt and the screen context
+    let persona = match persona::select() {
+        Some(persona) => persona,
+        None => pick_persona(&conn, &mut locks[0], screen).await?,
+    };
+    info!("Judge persona: {}", persona.name);
+    locks[0].persona = persona;
+
+    // Repeat offenses within a few hours get longer locks
+    let earlier = stats::record_lock().unwrap_or_else(|e| {
+        warn!("Failed to record the lock: {}", e);
+        0
+    });
+    locks[0].lock_range = stats::lock_range(earlier);
+    let lock_range = locks[0].lock_range;
+    let lock = &mut locks[0];
+    if let Some(conversation) = &mut lock.conversation {
+        lock.system = initialize_conversation(conversation, persona, screen_context, earlier, lock_range);
+        lock.prefix = conversation.len();
+    }
+
+    // Add initial message to display, naming what the user was doing if
+    // the classifier said
+    match lock_message {
+        Some(message) => locks[0].messages.push_back((ChatMessage::Decision(message.to_string()), THEME.decision)),
+        None => locks[0].messages.push_back((ChatMessage::System("Locked:".to_string()), THEME.system)),
+    }
 
     // Draw the initial chat window
     draw_chat_window(&conn, &locks[0], screen)?;
 
+    for win in locks.iter().map(|lock| lock.win).collect::<Vec<_>>() {
+        window::fade_in(&conn, win, screen_num, FADE_IN_MS).await?;
+    }
+
     // Run the interactive chat loop
-    let result = handle_interactive_chat(&conn, client, api_key, &mut locks[0], screen, unlock_phrase).await?;
+    let result = handle_interactive_chat(&conn, client, api_key, profile, &mut locks[0], screen, unlock_phrase).await?;
 
     Ok(result)
 }
 
 struct LockWindow {
     win: Window,
+    cursor: Cursor,
+    background: Option<Pixmap>,
     state: LockState,
     gc: Gcontext,
+    font: window::TextFont,
+    keyboard: Option<Keyboard>,
     input_buffer: String,
+    history: InputHistory,
     messages: VecDeque<(ChatMessage, u32)>, // Message and its color
     conversation: Option<Vec<Message>>, // Claude conversation history
+    // The persona's prompt, sent as the system prompt
+    system: String,
+    persona: &'static Persona,
+    // Lock minutes the judge may choose, longer for repeat offenses
+    lock_range: (u64, u64),
+    // Messages of the conversation set up before the chat, cached as a prefix
+    prefix: usize,
+    // Messages left to send the judge, shown in the status line during the chat
+    messages_left: Option<usize>,
+    // When the user was last asked for a message
+    turn_start: Instant,
+    // Last key press, for CHAT_INACTIVITY_SECS
+    last_input: Instant,
 }
 
+// A windowed lock is left to the window manager, for replaying input
 fn create_lock_windows(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     screen: &Screen,
+    screenshot: Option<&Path>,
+    windowed: bool,
 ) -> Result<Vec<LockWindow>> {
     let win = conn.generate_id()?;
 
     // Create a fullscreen window
     let values = CreateWindowAux::new()
-        .background_pixel(BG_COLOR)
-        .override_redirect(1)
-        .event_mask(EventMask::KEY_PRESS | EventMask::EXPOSURE);
+        .background_pixel(THEME.background)
+        .override_redirect(u32::from(!windowed))
+        .event_mask(EventMask::KEY_PRESS | EventMask::EXPOSURE | EventMask::POINTER_MOTION
+            | EventMask::VISIBILITY_CHANGE | EventMask::FOCUS_CHANGE);
 
     conn.create_window(
         screen.root_depth,
@@ -176,30 +311,70 @@ fn create_lock_windows(
         &values,
     )?;
 
+    window::set_class(conn, win)?;
+
+    // Blurred screenshot behind the chat, so it's clear what triggered the lock
+    let background = match screenshot {
+        Some(path) if SHOW_SCREENSHOT_BACKGROUND => {
+            match window::blurred_background(conn, screen, win, path) {
+                Ok(pixmap) => {
+                    let values = ChangeWindowAttributesAux::new().background_pixmap(pixmap);
+                    conn.change_window_attributes(win, &values)?;
+                    Some(pixmap)
+                },
+                Err(e) => {
+                    warn!("Not showing screenshot background: {}", e);
+                    None
+                }
+            }
+        },
+        _ => None,
+    };
+
     // Create invisible cursor
     let cursor = window::create_invisible_cursor(conn, win)?;
     let values = ChangeWindowAttributesAux::new().cursor(cursor);
     conn.change_window_attributes(win, &values)?;
 
     // Load font for text
-    let font = conn.generate_id()?;
-    conn.open_font(font, FONT_NAME.as_bytes())?;
+    let font = window::load_text_font(conn, screen);
 
     // Create graphics context
     let gc = conn.generate_id()?;
     let gc_aux = CreateGCAux::new()
-        .foreground(TEXT_COLOR)
-        .background(BG_COLOR)
-        .font(font);
+        .foreground(THEME.text)
+        .background(THEME.background)
+        .font(font.core_font());
     conn.create_gc(gc, win, &gc_aux)?;
 
+    // Layout-aware key handling, falling back to raw ASCII keysyms
+    let keyboard = match Keyboard::new(conn, screen) {
+        Ok(keyboard) => Some(keyboard),
+        Err(e) => {
+            warn!("Falling back to basic key handling: {}", e);
+            None
+        }
+    };
+
     Ok(vec![LockWindow {
         win,
+        cursor,
+        background,
         state: LockState::Init,
         gc,
+        font,
+        keyboard,
         input_buffer: String::new(),
+        history: InputHistory::load(),
         messages: VecDeque::new(),
         conversation: Some(Vec::new()),
+        system: String::new(),
+        persona: &PERSONAS[0],
+        lock_range: (MIN_LOCK_MINUTES, MAX_LOCK_MINUTES),
+        prefix: 0,
+        messages_left: None,
+        turn_start: Instant::now(),
+        last_input: Instant::now(),
     }])
 }
 
@@ -236,40 +411,6 @@ fn process_key_input(
     }
 }
 
-fn grab_keyboard_and_mouse(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> Result<()> {
-    // Try to grab keyboard and mouse for 600ms, similar to slock
-    for _ in 0..6 {
-        let kb_grab = conn.grab_keyboard(
-            false,
-            screen.root,
-            CURRENT_TIME,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-        )?.reply();
-
-        let ptr_grab = conn.grab_pointer(
-            false,
-            screen.root,
-            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-            x11rb::NONE,
-            x11rb::NONE,
-            CURRENT_TIME,
-        )?.reply();
-
-        if let (Ok(kb), Ok(ptr)) = (&kb_grab, &ptr_grab) {
-            if kb.status == GrabStatus::SUCCESS && ptr.status == GrabStatus::SUCCESS {
-                return Ok(());
-            }
-        }
-
-        thread::sleep(Duration::from_millis(100));
-    }
-
-    Err(anyhow!("Failed to grab keyboard and mouse"))
-}
-
 fn draw_text(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     lock: &LockWindow,
@@ -278,7 +419,7 @@ fn draw_text(
     y: i16,
     color: u32
 ) -> Result<()> {
-    window::draw_text(conn, lock.win, lock.gc, text, x, y, color)?;
+    window::draw_text(conn, lock.win, lock.gc, &lock.font, text, x, y, color)?;
     conn.flush()?;
     Ok(())
 }
@@ -290,10 +431,15 @@ fn draw_chat_window(
 ) -> Result<()> {
     // Clear window first
     conn.clear_area(false, lock.win, 0, 0, 0, 0)?;
-
-    // Draw chat history
-    let y_pos = 50; // Starting Y position
-    let line_height = 20; // Space between lines
+    draw_status_line(conn, lock, screen)?;
+
+    // The input field and its hint take the bottom or the top of the
+    // screen, the chat history the rest
+    let line_height = THEME.line_height;
+    let (input_y, y_pos) = match THEME.input_position {
+        InputPosition::Bottom => (screen.height_in_pixels as i16 - THEME.margin - 30, THEME.margin + 30),
+        InputPosition::Top => (THEME.margin + 30, THEME.margin + 100),
+    };
     let max_visible_lines = (screen.height_in_pixels as i16 - 120) / line_height;
 
     // Calculate range of messages to display (most recent ones)
@@ -310,10 +456,10 @@ fn draw_chat_window(
         // Format and draw based on message type
         match message {
             ChatMessage::System(text) => {
-                draw_text(conn, lock, &format!("System: {}", text), 20, y, *color)?;
+                draw_text(conn, lock, &format!("{}{}", THEME.system_prefix, text), THEME.margin, y, *color)?;
             },
             ChatMessage::User(text) => {
-                draw_text(conn, lock, &format!("You: {}", text), 20, y, *color)?;
+                draw_text(conn, lock, &format!("{}{}", THEME.user_prefix, text), THEME.margin, y, *color)?;
             },
             ChatMessage::Assistant(text) => {
                 // Split long messages into multiple lines
@@ -322,7 +468,7 @@ fn draw_chat_window(
                 let mut current_line = String::new();
 
                 for word in text.split_whitespace() {
-                    if current_line.len() + word.len() + 1 > max_line_length {
+                    if current_line.chars().count() + word.chars().count() + 1 > max_line_length {
                         lines.push(current_line);
                         current_line = word.to_string();
                     } else {
@@ -339,30 +485,63 @@ fn draw_chat_window(
 
                 // Draw first line with the prefix
                 if let Some(first_line) = lines.first() {
-                    draw_text(conn, lock, &format!("Claude: {}", first_line), 20, y, *color)?;
+                    draw_text(conn, lock, &format!("{}{}", THEME.assistant_prefix, first_line), THEME.margin, y, *color)?;
                 }
 
-                // Draw remaining lines with proper indentation
+                // Draw remaining lines indented past the prefix
+                let indent = " ".repeat(THEME.assistant_prefix.chars().count());
                 for (line_idx, line) in lines.iter().enumerate().skip(1) {
                     let line_y = y + line_idx as i16 * line_height;
-                    draw_text(conn, lock, &format!("        {}", line), 20, line_y, *color)?;
+                    draw_text(conn, lock, &format!("{}{}", indent, line), THEME.margin, line_y, *color)?;
                 }
             },
             ChatMessage::Decision(text) => {
-                draw_text(conn, lock, &format!("=== {} ===", text), 20, y, *color)?;
+                let text = format!("{}{}{}", THEME.decision_prefix, text, THEME.decision_suffix);
+                draw_text(conn, lock, &text, THEME.margin, y, *color)?;
             },
         }
     }
 
     // Draw input field
-    let input_y = screen.height_in_pixels as i16 - 50;
-    draw_text(conn, lock, "Input: ", 20, input_y, TEXT_COLOR)?;
-    draw_text(conn, lock, &lock.input_buffer, 80, input_y, TEXT_COLOR)?;
+    draw_text(conn, lock, &format!("{}{}", THEME.input_prefix, lock.input_buffer), THEME.margin, input_y, THEME.text)?;
+    let hint = match lock.state {
+        LockState::CheckIn => "Enter: save   Escape: skip".to_string(),
+        _ => format!("{}   {}: the verdict was wrong", emergency::hint(), FEEDBACK_KEY_NAME),
+    };
+    draw_text(conn, lock, &hint, THEME.margin, input_y + 25, THEME.system)?;
 
     conn.flush()?;
     Ok(())
 }
 
+// Height of the status line at the top of the lock window
+fn status_line_height() -> u16 {
+    (THEME.margin + THEME.line_height / 2) as u16
+}
+
+// Messages left and the time spent on the current one, while the judge
+// chat runs
+fn draw_status_line(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    lock: &LockWindow,
+    screen: &Screen
+) -> Result<()> {
+    let Some(left) = lock.messages_left else {
+        return Ok(());
+    };
+    conn.clear_area(false, lock.win, 0, 0, screen.width_in_pixels, status_line_height())?;
+    let seconds = lock.turn_start.elapsed().as_secs();
+    let mut status = format!(
+        "{} of {} messages left, then a {} minute lock   {}:{:02} on this message",
+        left, MAX_MESSAGES, lock.lock_range.0, seconds / 60, seconds % 60
+    );
+    if let Some(timeout) = CHAT_INACTIVITY_SECS {
+        let idle = timeout.saturating_sub(lock.last_input.elapsed().as_secs());
+        status.push_str(&format!("   locks in {}:{:02} without input", idle / 60, idle % 60));
+    }
+    draw_text(conn, lock, &status, THEME.margin, THEME.margin, THEME.system)
+}
+
 // Helper function to check if input matches unlock phrase
 fn check_unlock_phrase(input: &str, unlock_phrase: &str) -> bool {
     input.to_uppercase() == unlock_phrase
@@ -373,25 +552,56 @@ async fn handle_interactive_chat(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     client: &Client,
     api_key: &str,
+    profile: &Profile,
     lock: &mut LockWindow,
     screen: &Screen,
     unlock_phrase: &str,
 ) -> Result<LockResult> {
     // Chat loop - allow up to MAX_MESSAGES interactions
-    for i in 0..MAX_MESSAGES {
-        println!("DEBUG: Waiting for user input (message {}/{})", i+1, MAX_MESSAGES);
+    let mut sent = 0;
+    while sent < MAX_MESSAGES {
+        debug!("Waiting for user input (message {}/{})", sent+1, MAX_MESSAGES);
+        lock.messages_left = Some(MAX_MESSAGES - sent);
 
         // Get user input
-        let user_input = get_user_input(conn, lock, screen, unlock_phrase)?;
+        let user_input = get_user_input(conn, lock, screen, unlock_phrase).await?;
 
         // Check for auto-unlock
-        if user_input == "__AUTO_UNLOCK__" {
+        if ["__AUTO_UNLOCK__", "__EMERGENCY_UNLOCK__", "__REMOTE_UNLOCK__"].contains(&user_input.as_str()) {
             return Ok(LockResult::Unlocked);
         }
+        if user_input == "__INACTIVE__" {
+            info!("No input in the lock chat for {}s, applying the default lock", CHAT_INACTIVITY_SECS.unwrap_or(0));
+            break;
+        }
+
+        // The emergency unlock, as with its key chord
+        if user_input.trim() == "/override" {
+            if emergency::prompt(conn, lock.win, lock.gc, &lock.font, screen).await? {
+                return Ok(LockResult::Unlocked);
+            }
+            draw_chat_window(conn, lock, screen)?;
+            continue;
+        }
+
+        // Slash commands are handled locally and don't count as messages
+        if user_input.starts_with('/') {
+            let (reply, result) = slash_command(&user_input, profile, lock.persona, lock.lock_range, MAX_MESSAGES - sent);
+            lock.messages.push_back((ChatMessage::System(reply), THEME.system));
+            draw_chat_window(conn, lock, screen)?;
+
+            if let Some(result) = result {
+                // Wait briefly so user can see the message
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                return Ok(result);
+            }
+            continue;
+        }
+        sent += 1;
 
         // Process the message with Claude
         if let Some(result) = process_message_with_claude(
-            conn, client, api_key, lock, screen, &user_input
+            conn, client, api_key, profile, lock, screen, &user_input
         ).await? {
             return Ok(result);
         }
@@ -399,20 +609,111 @@ async fn handle_interactive_chat(
 
     // If we reach here, we've gone through all messages without a decision
     // Default to minimum lock time
+    let (minutes, _) = lock.lock_range;
+    lock.messages_left = Some(0);
     lock.messages.push_back((
-        ChatMessage::Decision(format!("SCREEN LOCKED FOR {} MINUTES", MIN_LOCK_MINUTES)),
-        TEXT_COLOR
+        ChatMessage::Decision(format!("SCREEN LOCKED FOR {} MINUTES", minutes)),
+        THEME.decision
     ));
     draw_chat_window(conn, lock, screen)?;
 
     // Wait briefly so user can see the message
-    std::thread::sleep(Duration::from_secs(1));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    Ok(LockResult::TimedLock(minutes))
+}
+
+// Run a slash command typed in the lock chat. Returns the reply to show and,
+// for commands that end the chat, the lock result.
+fn slash_command(
+    input: &str,
+    profile: &Profile,
+    persona: &Persona,
+    (min, max): (u64, u64),
+    remaining: usize,
+) -> (String, Option<LockResult>) {
+    let mut words = input.split_whitespace();
+    let command = words.next().unwrap_or("");
+
+    match command {
+        "/status" => {
+            let policy = match OFFLINE_POLICY {
+                OfflinePolicy::TimedLock(minutes) => format!("lock for {} minutes", minutes),
+                OfflinePolicy::LocalJudge => "local judge".to_string(),
+                OfflinePolicy::Bypass => "unlock".to_string(),
+            };
+            (format!(
+                "{} of {} messages left, profile {}, judge {} ({}), locks {}-{} minutes, when offline: {}",
+                remaining, MAX_MESSAGES, profile.name, persona.name, persona.model.unwrap_or(profile.judge_model),
+                min, max, policy
+            ), None)
+        },
+        "/task" => match intention::current() {
+            Some(task) => (format!("Today's task: {}", task), None),
+            None => ("No task declared today".to_string(), None),
+        },
+        "/lock" => match words.next().map(str::parse::<u64>) {
+            Some(Ok(minutes)) => {
+                let minutes = minutes.clamp(min, max);
+                (format!("SCREEN LOCKED FOR {} MINUTES", minutes), Some(LockResult::TimedLock(minutes)))
+            },
+            _ => (format!("usage: /lock <minutes, {}-{}>", min, max), None),
+        },
+        _ => (format!("Unknown command {}; try /status, /task, /override or /lock <minutes>", command), None),
+    }
+}
+
+// Translate a key press into a keysym and, with xkbcommon, the typed text
+fn translate_key(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    lock: &mut LockWindow,
+    key: &KeyPressEvent,
+) -> Result<Option<(u32, Option<String>)>> {
+    if let Some(keyboard) = &mut lock.keyboard {
+        return Ok(Some(keyboard.key_press(key.detail, u16::from(key.state))));
+    }
+
+    let reply = conn.get_keyboard_mapping(key.detail, 1)?.reply()?;
+    Ok(reply.keysyms.first().map(|keysym| (*keysym, None)))
+}
+
+// Let the user choose the judge with a number key; Enter takes the first
+async fn pick_persona(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    lock: &mut LockWindow,
+    screen: &Screen,
+) -> Result<&'static Persona> {
+    lock.messages.push_back((ChatMessage::System("Pick your judge:".to_string()), THEME.system));
+    for (i, persona) in PERSONAS.iter().enumerate().take(9) {
+        lock.messages.push_back((ChatMessage::System(format!("  {}: {}", i + 1, persona.name)), THEME.system));
+    }
+    draw_chat_window(conn, lock, screen)?;
 
-    Ok(LockResult::TimedLock(MIN_LOCK_MINUTES))
+    loop {
+        match grab::next_event(conn).await? {
+            Event::KeyPress(key) => {
+                let Some((keysym, _)) = translate_key(conn, lock, &key)? else {
+                    continue;
+                };
+                let persona = match keysym {
+                    keysym::ENTER => PERSONAS.first(),
+                    0x31..=0x39 => PERSONAS.get((keysym - 0x31) as usize),
+                    _ => None,
+                };
+                if let Some(persona) = persona {
+                    lock.messages.push_back((ChatMessage::System(format!("Judge: {}", persona.name)), THEME.system));
+                    return Ok(persona);
+                }
+            },
+            Event::Expose(_) => draw_chat_window(conn, lock, screen)?,
+            Event::MotionNotify(_) => window::recenter_pointer(conn, lock.win, screen)?,
+            _ => {},
+        }
+    }
 }
 
 // Get user input from the X11 window
-fn get_user_input(
+async fn get_user_input(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     lock: &mut LockWindow,
     screen: &Screen,
@@ -420,17 +721,77 @@ fn get_user_input(
 ) -> Result<String> {
     // Clear the input buffer
     lock.input_buffer.clear();
+    replay::new_line();
+    lock.turn_start = Instant::now();
+    lock.last_input = lock.turn_start;
     draw_chat_window(conn, lock, screen)?;
+    let mut shown_secs = 0;
 
     // Loop until we get user input
     loop {
-        match conn.wait_for_event() {
+        // Unlock requested remotely, e.g. over Telegram
+        if ipc::take_unlock() {
+            lock.messages.push_back((
+                ChatMessage::Decision("UNLOCKING SCREEN (Remote unlock)".to_string()),
+                THEME.decision
+            ));
+            draw_chat_window(conn, lock, screen)?;
+            return Ok("__REMOTE_UNLOCK__".to_string());
+        }
+
+        let event = match conn.poll_for_event() {
+            Ok(Some(event)) => {
+                grab::reassert(conn, &event)?;
+                Ok(event)
+            },
+            Ok(None) => {
+                // Walked away from the judge chat; replays have no timeout
+                let timeout = CHAT_INACTIVITY_SECS.filter(|_| lock.messages_left.is_some());
+                if timeout.is_some_and(|timeout| lock.last_input.elapsed().as_secs() >= timeout) {
+                    return Ok("__INACTIVE__".to_string());
+                }
+
+                // The response timer ticks on its own
+                let seconds = lock.turn_start.elapsed().as_secs();
+                if seconds != shown_secs {
+                    shown_secs = seconds;
+                    draw_status_line(conn, lock, screen)?;
+                    conn.flush()?;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            },
+            Err(e) => Err(e),
+        };
+
+        match event {
             Ok(event) => {
                 if let Event::KeyPress(key) = event {
-                    // Get the pressed key
-                    let reply = conn.get_keyboard_mapping(key.detail, 1)?.reply()?;
-                    if reply.keysyms.len() > 0 {
-                        let keysym = reply.keysyms[0];
+                    lock.last_input = Instant::now();
+
+                    // Emergency chord - unlock with the account password
+                    if emergency::is_chord(conn, &key)? {
+                        if emergency::prompt(conn, lock.win, lock.gc, &lock.font, screen).await? {
+                            return Ok("__EMERGENCY_UNLOCK__".to_string());
+                        }
+                        draw_chat_window(conn, lock, screen)?;
+                        continue;
+                    }
+
+                    // Feedback chord - the verdict that locked was wrong; the judge still decides
+                    if emergency::is_ctrl_alt(conn, &key, FEEDBACK_KEY)? {
+                        let reply = match verdicts::feedback(false) {
+                            Ok(_) => "Noted that the verdict was wrong, the classifier will learn from it".to_string(),
+                            Err(e) => format!("Failed to record the feedback: {}", e),
+                        };
+                        lock.messages.push_back((ChatMessage::System(reply), THEME.system));
+                        draw_chat_window(conn, lock, screen)?;
+                        continue;
+                    }
+
+                    // Get the pressed key and the text it produces
+                    if let Some((keysym, text)) = translate_key(conn, lock, &key)? {
+                        replay::record_key(keysym);
 
                         match keysym {
                             // Enter key - submit the input
@@ -441,24 +802,27 @@ fn get_user_input(
                                         // Add message to display queue
                                         lock.messages.push_back((
                                             ChatMessage::Decision("UNLOCKING SCREEN (Auto-unlock)".to_string()),
-                                            TEXT_COLOR
+                                            THEME.decision
                                         ));
                                         draw_chat_window(conn, lock, screen)?;
 
+                                        replay::record_submit("__AUTO_UNLOCK__");
                                         return Ok("__AUTO_UNLOCK__".to_string());
                                     }
 
                                     // Return the user input
                                     let input = lock.input_buffer.clone();
                                     lock.input_buffer.clear();
+                                    lock.history.push(&input);
 
                                     // Add message to display queue
                                     lock.messages.push_back((
                                         ChatMessage::User(input.clone()),
-                                        USER_COLOR
+                                        THEME.user
                                     ));
                                     draw_chat_window(conn, lock, screen)?;
 
+                                    replay::record_submit(&input);
                                     return Ok(input);
                                 }
                             },
@@ -474,11 +838,35 @@ fn get_user_input(
                                     draw_chat_window(conn, lock, screen)?;
                                 }
                             },
+                            // Up/Down - recall earlier messages
+                            keysym::UP | keysym::DOWN => {
+                                let recalled = if keysym == keysym::UP {
+                                    lock.history.previous(&lock.input_buffer)
+                                } else {
+                                    lock.history.next()
+                                };
+
+                                if let Some(recalled) = recalled {
+                                    lock.input_buffer = recalled.to_string();
+                                    draw_chat_window(conn, lock, screen)?;
+                                }
+                            },
                             // Normal key - add to input
                             _ => {
-                                if process_key_input(keysym, &mut lock.input_buffer) {
+                                let added = match text {
+                                    Some(text) => {
+                                        lock.input_buffer.push_str(&text);
+                                        true
+                                    },
+                                    // Without xkbcommon, map plain ASCII keysyms directly
+                                    None if lock.keyboard.is_none() => process_key_input(keysym, &mut lock.input_buffer),
+                                    None => false,
+                                };
+
+                                if added {
                                     // Regular unlock phrase check
                                     if check_unlock_phrase(&lock.input_buffer, unlock_phrase) {
+                                        replay::record_submit("__AUTO_UNLOCK__");
                                         return Ok("__AUTO_UNLOCK__".to_string());
                                     }
 
@@ -491,6 +879,8 @@ fn get_user_input(
                 } else if let Event::Expose(_) = event {
                     // Redraw on expose event
                     draw_chat_window(conn, lock, screen)?;
+                } else if let Event::MotionNotify(_) = event {
+                    window::recenter_pointer(conn, lock.win, screen)?;
                 }
             },
             Err(e) => return Err(anyhow!("Error getting X11 event: {}", e)),
@@ -498,17 +888,112 @@ fn get_user_input(
     }
 }
 
+// Windowed lock UI without grabs or the judge, collecting up to `count`
+// submissions; an unlock ends it early. Used to replay recorded input.
+pub async fn replay_session(count: usize) -> Result<Vec<String>> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+
+    let mut locks = create_lock_windows(&conn, screen, None, true)?;
+    let lock = &mut locks[0];
+    lock.state = LockState::Chat;
+    conn.map_window(lock.win)?;
+    conn.flush()?;
+
+    // The window can only take the focus once it is viewable
+    while !matches!(grab::next_event(&conn).await?, Event::Expose(_)) {}
+    conn.set_input_focus(InputFocus::PARENT, lock.win, CURRENT_TIME)?;
+    conn.flush()?;
+
+    let mut submissions = Vec::new();
+    while submissions.len() < count {
+        let input = get_user_input(&conn, lock, screen, UNLOCK_PHRASE).await?;
+        let unlocked = input.starts_with("__");
+        submissions.push(input);
+        if unlocked {
+            break;
+        }
+    }
+
+    conn.destroy_window(lock.win)?;
+    conn.flush()?;
+    Ok(submissions)
+}
+
+// Small chat window asking the question, without grabs; returns the answer,
+// or None if skipped with Escape
+pub async fn check_in(question: &str) -> Result<Option<String>> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let mut screen = conn.setup().roots[screen_num].clone();
+    screen.width_in_pixels = screen.width_in_pixels.min(CHECK_IN_SIZE.0);
+    screen.height_in_pixels = screen.height_in_pixels.min(CHECK_IN_SIZE.1);
+    let screen = &screen;
+
+    let mut locks = create_lock_windows(&conn, screen, None, true)?;
+    let lock = &mut locks[0];
+    lock.state = LockState::CheckIn;
+    lock.messages.push_back((ChatMessage::System(question.to_string()), THEME.system));
+    // The pointer isn't confined, so it should stay visible
+    conn.change_window_attributes(lock.win, &ChangeWindowAttributesAux::new().cursor(x11rb::NONE))?;
+    conn.map_window(lock.win)?;
+    conn.flush()?;
+
+    // The window can only take the focus once it is viewable
+    while !matches!(grab::next_event(&conn).await?, Event::Expose(_)) {}
+    conn.set_input_focus(InputFocus::PARENT, lock.win, CURRENT_TIME)?;
+    draw_chat_window(&conn, lock, screen)?;
+
+    let answer = loop {
+        match grab::next_event(&conn).await? {
+            Event::KeyPress(key) => {
+                let Some((keysym, text)) = translate_key(&conn, lock, &key)? else {
+                    continue;
+                };
+                match keysym {
+                    keysym::ENTER if !lock.input_buffer.trim().is_empty() => {
+                        break Some(lock.input_buffer.trim().to_string());
+                    },
+                    keysym::ESCAPE => break None,
+                    keysym::BACKSPACE => {
+                        lock.input_buffer.pop();
+                    },
+                    _ => match text {
+                        Some(text) => lock.input_buffer.push_str(&text),
+                        None if lock.keyboard.is_none() => {
+                            process_key_input(keysym, &mut lock.input_buffer);
+                        },
+                        None => {},
+                    },
+                }
+                draw_chat_window(&conn, lock, screen)?;
+            },
+            Event::Expose(_) => draw_chat_window(&conn, lock, screen)?,
+            _ => {},
+        }
+    };
+
+    conn.destroy_window(lock.win)?;
+    conn.flush()?;
+    Ok(answer)
+}
+
 // Process a message with Claude API
 async fn process_message_with_claude(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     client: &Client,
     api_key: &str,
+    profile: &Profile,
     lock: &mut LockWindow,
     screen: &Screen,
     user_input: &str,
 ) -> Result<Option<LockResult>> {
     // Get a reference to the conversation
     // Scope the mutable borrow to fix the borrow checker error
+    exclude::remember(user_input);
     let user_message = Message {
         role: "user".to_string(),
         content: user_input.to_string(),
@@ -531,83 +1016,210 @@ async fn process_message_with_claude(
     // Show "thinking" indicator in the UI
     lock.messages.push_back((
         ChatMessage::System("Claude is thinking...".to_string()),
-        SYSTEM_COLOR
+        THEME.system
     ));
     draw_chat_window(conn, lock, screen)?;
 
-    // Call Claude API
-    println!("DEBUG: Calling Claude API");
-    let response = call_claude_api(client, api_key, &conversation_clone).await?;
-    println!("DEBUG: Received Claude response: {}", response);
+    // Call Claude API, retrying while it's unreachable; with the monthly
+    // budget spent the offline policy decides right away
+    debug!("Calling Claude API");
+    let over_budget = stats::over_monthly_budget().unwrap_or_else(|e| {
+        warn!("Failed to check the monthly budget: {}", e);
+        false
+    });
+    let mut failures = if over_budget { OFFLINE_MAX_FAILURES } else { 0 };
+    let (persona, lock_range) = (lock.persona, lock.lock_range);
+    let system = lock.system.clone();
+    let chat = Chat { system: &system, messages: &conversation_clone, prefix: lock.prefix };
+    let (response, decision) = if over_budget {
+        lock.messages.pop_back();
+        lock.messages.push_back((
+            ChatMessage::System("Monthly API budget spent, applying offline policy".to_string()),
+            THEME.system
+        ));
+        (String::new(), Some(offline_decision(&conversation_clone, "Monthly budget spent")))
+    } else {
+        loop {
+            let on_text = |text: &str| show_partial_reply(conn, lock, screen, text);
+            match call_claude_api(client, api_key, profile, persona, lock_range, &chat, on_text).await {
+                Ok(reply) => break reply,
+                Err(e) => {
+                    failures += 1;
+                    warn!("Judge unreachable ({}/{}): {}", failures, OFFLINE_MAX_FAILURES, redact::scrub(&e.to_string()));
+
+                    if failures >= OFFLINE_MAX_FAILURES {
+                        notify::send(Notification::ApiError, "perimedes: judge unreachable", &redact::scrub(&e.to_string()));
+                        lock.messages.pop_back();
+                        let decision = offline_decision(&conversation_clone, "Judge unreachable");
+                        lock.messages.push_back((
+                            ChatMessage::System("Judge unreachable, applying offline policy".to_string()),
+                            THEME.system
+                        ));
+                        break (String::new(), Some(decision));
+                    }
 
-    // Remove the "thinking" message
-    lock.messages.pop_back();
+                    lock.messages.pop_back();
+                    lock.messages.push_back((
+                        ChatMessage::System(format!("Judge unreachable, retrying ({}/{})...", failures, OFFLINE_MAX_FAILURES)),
+                        THEME.system
+                    ));
+                    draw_chat_window(conn, lock, screen)?;
+                    tokio::time::sleep(Duration::from_secs(OFFLINE_RETRY_SECS)).await;
+                }
+            }
+        }
+    };
+    debug!("Received Claude response: {}", redact::sensitive(&response));
 
-    // Add Claude's response to conversation
-    // Now we can borrow mutably again since the previous mutable borrow is out of scope
-    if let Some(conversation) = &mut lock.conversation {
-        conversation.push(Message {
-            role: "assistant".to_string(),
-            content: response.clone(),
-        });
+    // Remove the "thinking" message
+    if failures < OFFLINE_MAX_FAILURES {
+        lock.messages.pop_back();
     }
 
-    // Add message to display
-    lock.messages.push_back((
-        ChatMessage::Assistant(response.clone()),
-        ASSISTANT_COLOR
-    ));
-    draw_chat_window(conn, lock, screen)?;
+    if !response.is_empty() {
+        exclude::remember(&response);
 
-    // Check for decision
-    if response.contains("UNLOCK") {
-        // Add decision message
+        // Add Claude's response to conversation
+        // Now we can borrow mutably again since the previous mutable borrow is out of scope
+        if let Some(conversation) = &mut lock.conversation {
+            conversation.push(Message {
+                role: "assistant".to_string(),
+                content: response.clone(),
+            });
+        }
+
+        // Add message to display
         lock.messages.push_back((
-            ChatMessage::Decision("UNLOCKING SCREEN".to_string()),
-            TEXT_COLOR
+            ChatMessage::Assistant(response.clone()),
+            THEME.assistant
         ));
         draw_chat_window(conn, lock, screen)?;
+    }
 
-        // Wait briefly so user can see the message
-        std::thread::sleep(Duration::from_secs(1));
+    // Keep the judge's own decisions, not the offline policy's
+    if let (Some(result), true, Some(conversation)) = (&decision, failures < OFFLINE_MAX_FAILURES, &lock.conversation) {
+        if let Err(e) = transcripts::record(profile, lock.persona, &lock.system, conversation, result) {
+            warn!("Failed to save the lock chat: {}", e);
+        }
+    }
 
-        return Ok(Some(LockResult::Unlocked));
-    } else if response.contains("LOCK:") {
-        // Extract lock duration
-        let parts: Vec<&str> = response.split("LOCK:").collect();
-        if parts.len() >= 2 {
-            if let Ok(minutes) = parts[1].trim().parse::<u64>() {
-                let minutes = minutes.clamp(MIN_LOCK_MINUTES, MAX_LOCK_MINUTES);
+    // Check for decision
+    let decision_text = match &decision {
+        Some(LockResult::Unlocked) => "UNLOCKING SCREEN".to_string(),
+        Some(LockResult::UnlockedFor { minutes, purpose }) => {
+            format!("UNLOCKING SCREEN FOR {} MINUTES: {}", minutes, purpose)
+        },
+        Some(LockResult::TimedLock(minutes)) => format!("SCREEN LOCKED FOR {} MINUTES", minutes),
+        None => return Ok(None),
+    };
 
-                // Add decision message
-                lock.messages.push_back((
-                    ChatMessage::Decision(format!("SCREEN LOCKED FOR {} MINUTES", minutes)),
-                    TEXT_COLOR
-                ));
-                draw_chat_window(conn, lock, screen)?;
+    // Add decision message
+    lock.messages.push_back((ChatMessage::Decision(decision_text), THEME.decision));
+    draw_chat_window(conn, lock, screen)?;
 
-                // Wait briefly so user can see the message
-                std::thread::sleep(Duration::from_secs(1));
+    // Wait briefly so user can see the message
+    tokio::time::sleep(Duration::from_secs(1)).await;
 
-                return Ok(Some(LockResult::TimedLock(minutes)));
-            }
-        }
+    Ok(decision)
+}
 
-        // Default to minimum lock time if parsing fails
-        lock.messages.push_back((
-            ChatMessage::Decision(format!("SCREEN LOCKED FOR {} MINUTES", MIN_LOCK_MINUTES)),
-            TEXT_COLOR
-        ));
-        draw_chat_window(conn, lock, screen)?;
+// Show the judge's reply as it streams in, in place of the "thinking" line
+fn show_partial_reply(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    lock: &mut LockWindow,
+    screen: &Screen,
+    text: &str,
+) {
+    lock.messages.pop_back();
+    lock.messages.push_back((ChatMessage::Assistant(text.to_string()), THEME.assistant));
+    if let Err(e) = draw_chat_window(conn, lock, screen) {
+        warn!("Failed to draw the reply: {}", e);
+    }
+}
+
+// Decision taken by OFFLINE_POLICY when the judge can't be reached or the
+// monthly budget is spent
+fn offline_decision(conversation: &[Message], why: &str) -> LockResult {
+    let (decision, action) = match OFFLINE_POLICY {
+        OfflinePolicy::TimedLock(minutes) => {
+            (LockResult::TimedLock(minutes), format!("timed lock for {} minutes", minutes))
+        },
+        OfflinePolicy::LocalJudge => {
+            let text = conversation.iter()
+                .filter(|message| message.role == "user")
+                .map(|message| message.content.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            match OFFLINE_DISTRACTIONS.iter().find(|pattern| text.contains(&pattern.to_lowercase())) {
+                Some(pattern) => (
+                    LockResult::TimedLock(OFFLINE_LOCK_MINUTES),
+                    format!("local judge: locked for {} minutes ({})", OFFLINE_LOCK_MINUTES, pattern),
+                ),
+                None => (LockResult::Unlocked, "local judge: unlocked".to_string()),
+            }
+        },
+        OfflinePolicy::Bypass => (LockResult::Unlocked, "offline bypass".to_string()),
+    };
 
-        // Wait briefly so user can see the message
-        std::thread::sleep(Duration::from_secs(1));
+    info!("{}, {}", why, action);
+    ipc::offline(&action);
+    decision
+}
 
-        return Ok(Some(LockResult::TimedLock(MIN_LOCK_MINUTES)));
+// The make_decision tool. The schema restricts lock durations to the allowed
+// range; they are clamped again locally in parse_decision.
+fn decision_tool((min, max): (u64, u64)) -> Tool {
+    Tool {
+        name: DECISION_TOOL.to_string(),
+        description: "Decide whether to unlock the screen or keep it locked for a number of minutes.".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["unlock", "unlock_for", "lock"],
+                },
+                "minutes": {
+                    "type": "integer",
+                    "minimum": min,
+                    "maximum": max.max(MAX_ALLOWANCE_MINUTES),
+                    "description": "How long to keep the screen locked, or the allowance lasts; \
+                                    required for 'lock' and 'unlock_for'",
+                },
+                "purpose": {
+                    "type": "string",
+                    "description": "What the user promised to use the allowance for, required for 'unlock_for'",
+                },
+                "reason": {
+                    "type": "string",
+                    "description": "One sentence explaining the decision to the user",
+                },
+            },
+            "required": ["action"],
+        }),
     }
+}
 
-    // No decision made
-    Ok(None)
+// Turn make_decision tool input into a lock result, with lock minutes
+// clamped to the range
+pub fn parse_decision(input: &serde_json::Value, (min, max): (u64, u64)) -> LockResult {
+    match input["action"].as_str() {
+        Some("unlock") => LockResult::Unlocked,
+        Some("unlock_for") => {
+            let minutes = input["minutes"].as_u64().unwrap_or(MAX_ALLOWANCE_MINUTES);
+            let purpose = input["purpose"].as_str()
+                .or(input["reason"].as_str())
+                .unwrap_or("unspecified")
+                .to_string();
+            LockResult::UnlockedFor { minutes: minutes.clamp(1, MAX_ALLOWANCE_MINUTES), purpose }
+        },
+        // Anything else, including malformed input, keeps the screen locked
+        _ => {
+            let minutes = input["minutes"].as_u64().unwrap_or(min);
+            LockResult::TimedLock(minutes.clamp(min, max))
+        }
+    }
 }
 
 
@@ -617,12 +1229,15 @@ fn set_lock_color(
     state: &LockState
 ) -> Result<()> {
     let color = match state {
-        LockState::Init => BG_COLOR,
-        LockState::Chat => BG_COLOR, // Use same background for chat
+        LockState::Init => THEME.background,
+        LockState::Chat | LockState::CheckIn => THEME.background, // Use same background for chat
     };
 
     for lock in locks {
-        let values = ChangeWindowAttributesAux::new().background_pixel(color);
+        let values = match lock.background {
+            Some(pixmap) => ChangeWindowAttributesAux::new().background_pixmap(pixmap),
+            None => ChangeWindowAttributesAux::new().background_pixel(color),
+        };
         conn.change_window_attributes(lock.win, &values)?;
         conn.clear_area(false, lock.win, 0, 0, 0, 0)?; // Clear the entire window
     }
@@ -631,44 +1246,92 @@ fn set_lock_color(
     Ok(())
 }
 
-// Call the Claude API with the current conversation
-async fn call_claude_api(client: &Client, api_key: &str, conversation: &[Message]) -> Result<String> {
+// What the judge is sent: its instructions and the chat, of which the first
+// `prefix` messages were set up before the user wrote anything
+struct Chat<'a> {
+    system: &'a str,
+    messages: &'a [Message],
+    prefix: usize,
+}
+
+// The system prompt and the chat with cache breakpoints after the system
+// prompt, after the prefix and after the latest message, which the next turn
+// reads back from the cache
+fn cached(chat: &Chat) -> (Vec<InputBlock>, Vec<VisionMessage>) {
+    let system = vec![InputBlock::Text {
+        text: chat.system.to_string(),
+        cache_control: PROMPT_CACHING.then_some(CacheControl::EPHEMERAL),
+    }];
+    let messages = chat.messages.iter().enumerate()
+        .map(|(i, message)| {
+            let breakpoint = PROMPT_CACHING && (i + 1 == chat.prefix || i + 1 == chat.messages.len());
+            VisionMessage {
+                role: message.role.clone(),
+                content: vec![InputBlock::Text {
+                    text: message.content.clone(),
+                    cache_control: breakpoint.then_some(CacheControl::EPHEMERAL),
+                }],
+            }
+        })
+        .collect();
+    (system, messages)
+}
+
+// Call the Claude API with the current conversation. Returns the reply text
+// and the decision, if the judge called the decision tool.
+async fn call_claude_api(
+    client: &Client,
+    api_key: &str,
+    profile: &Profile,
+    persona: &Persona,
+    lock_range: (u64, u64),
+    chat: &Chat<'_>,
+    on_text: impl FnMut(&str),
+) -> Result<(String, Option<LockResult>)> {
+    let model = persona.model.unwrap_or(profile.judge_model);
+    let (system, messages) = cached(chat);
     let request = AnthropicRequest {
-        model: JUDGE_MODEL.to_string(),
-        messages: conversation.to_vec(),
+        model: model.to_string(),
+        system,
+        messages,
         max_tokens: 300,
+        tools: vec![decision_tool(lock_range)],
+        temperature: persona.temperature,
+        stream: true,
     };
 
-    println!("DEBUG: Sending request to Anthropic API with model: {}", JUDGE_MODEL);
+    debug!("Sending request to Anthropic API with model: {}", model);
 
-    let response = client.post(API_URL)
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .json(&request)
+    let response = api::post(client, API_URL, api_key, &request)
         .send()
         .await
         .context("Failed to send request to Anthropic API")?;
 
     let status = response.status();
-    println!("DEBUG: API response status: {}", status);
-
-    // Get the raw response text
-    let response_text = response.text().await
-        .context("Failed to get raw response text")?;
+    debug!("API response status: {}", status);
 
-    println!("DEBUG: Raw API response: {}", response_text);
+    // Errors come as a plain JSON body, not as events
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Anthropic API returned {}: {}", status, body));
+    }
+    let response_data = stream::read(response, on_text).await?;
 
-    // Parse the JSON response manually after logging it
-    let response_data: AnthropicResponse = serde_json::from_str(&response_text)
-        .context("Failed to parse Anthropic API response")?;
+    // Never fail the lock chat over bookkeeping
+    if let Err(e) = stats::record_usage(profile, model, &response_data.usage) {
+        warn!("Failed to record API usage: {}", redact::scrub(&e.to_string()));
+    }
 
-    let parsed_text = response_data.content
-        .first()
-        .map(|block| block.text.trim())
-        .unwrap_or("")
-        .to_string();
+    let mut parsed_text = response_data.text();
+    let decision = response_data.tool_input(DECISION_TOOL).map(|input| {
+        // Show the judge's reason if it decided without writing any text
+        if parsed_text.is_empty() {
+            parsed_text = input["reason"].as_str().unwrap_or("").to_string();
+        }
+        parse_decision(input, lock_range)
+    });
 
-    println!("DEBUG: Parsed text from response: {}", parsed_text);
+    debug!("Parsed text from response: {}", redact::sensitive(&parsed_text));
 
-    Ok(parsed_text)
+    Ok((parsed_text, decision))
 }
\ No newline at end of file
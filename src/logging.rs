@@ -0,0 +1,64 @@
+// Leveled logging with tracing: to stderr for every command, and for the
+// daemon also to a daily log file in the state directory and, with
+// LOG_TO_JOURNALD, to the systemd journal
+//
+// API payloads are logged at debug level, and withheld even then unless
+// `--log-sensitive` is given, see redact.
+
+use anyhow::{Result, Context, anyhow};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use crate::constants::{LOG_FILES_KEPT, LOG_TO_JOURNALD};
+use crate::storage;
+
+pub fn parse_level(level: &str) -> Result<LevelFilter> {
+    level.parse().map_err(|_| anyhow!("Unknown log level '{}', use error, warn, info, debug or trace", level))
+}
+
+// Install the subscriber; the guard flushes the log file when dropped, so
+// it has to live as long as the program
+pub fn init(level: LevelFilter, daemon: bool) -> Result<Option<WorkerGuard>> {
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_filter(level);
+
+    if !daemon {
+        tracing_subscriber::registry().with(stderr).init();
+        return Ok(None);
+    }
+
+    let dir = storage::state_dir().join("logs");
+    let appender = Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("perimedes")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_KEPT)
+        .build(&dir)
+        .with_context(|| format!("Failed to open the log in {}", dir.display()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let file = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_filter(level);
+
+    let journald = if LOG_TO_JOURNALD {
+        match tracing_journald::layer() {
+            Ok(layer) => Some(layer.with_filter(level)),
+            Err(e) => {
+                eprintln!("Not logging to the journal: {}", e);
+                None
+            },
+        }
+    } else {
+        None
+    };
+
+    tracing_subscriber::registry().with(stderr).with(file).with(journald).init();
+    Ok(Some(guard))
+}
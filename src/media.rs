@@ -0,0 +1,112 @@
+// Media players over MPRIS on the session bus
+//
+// Every player that implements MPRIS owns a bus name under
+// org.mpris.MediaPlayer2 and exports its state on /org/mpris/MediaPlayer2.
+// Captures list what's playing, so the classifier can tell music in the
+// background from watching videos, and locks pause everything.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+use zbus::zvariant::OwnedValue;
+use zbus::{fdo::DBusProxy, Connection, Proxy};
+
+use crate::constants::{MEDIA_IN_CONTEXT, PAUSE_MEDIA_ON_LOCK};
+use crate::redact;
+use crate::types::Media;
+
+const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+// A player that hangs mustn't hold up captures or locks
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+static CONNECTION: OnceCell<Connection> = OnceCell::const_new();
+
+async fn players() -> Result<Vec<Proxy<'static>>> {
+    let connection = CONNECTION.get_or_try_init(Connection::session).await?;
+    let mut players = Vec::new();
+    for name in DBusProxy::new(connection).await?.list_names().await? {
+        if name.starts_with(BUS_PREFIX) {
+            players.push(Proxy::new(connection, name, OBJECT_PATH, PLAYER_INTERFACE).await?);
+        }
+    }
+    Ok(players)
+}
+
+// The player's name from its bus name, e.g. org.mpris.MediaPlayer2.firefox.instance_1_52
+fn player_name(player: &Proxy) -> String {
+    let name = player.destination().trim_start_matches(BUS_PREFIX);
+    name.split(".instance").next().unwrap_or(name).to_string()
+}
+
+async fn describe(player: &Proxy<'_>) -> Result<Option<Media>> {
+    let status: String = player.get_property("PlaybackStatus").await?;
+    if status != "Playing" {
+        return Ok(None);
+    }
+    let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").await.unwrap_or_default();
+    let text = |key: &str| metadata.get(key).and_then(|value| String::try_from(value.clone()).ok());
+    let artists = metadata.get("xesam:artist")
+        .and_then(|value| Vec::<String>::try_from(value.clone()).ok())
+        .unwrap_or_default();
+    Ok(Some(Media {
+        player: player_name(player),
+        title: redact::ocr(&text("xesam:title").unwrap_or_default()),
+        artist: redact::ocr(&artists.join(", ")),
+    }))
+}
+
+async fn with_timeout<T: Default>(what: &str, future: impl Future<Output = Result<T>>) -> T {
+    match tokio::time::timeout(TIMEOUT, future).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => {
+            debug!("Failed to {}: {}", what, e);
+            T::default()
+        },
+        Err(_) => {
+            warn!("Timed out trying to {}", what);
+            T::default()
+        },
+    }
+}
+
+// What's playing right now, for the captures
+pub async fn playing() -> Vec<Media> {
+    if !MEDIA_IN_CONTEXT {
+        return Vec::new();
+    }
+    with_timeout("query media players", async {
+        let mut playing = Vec::new();
+        for player in players().await? {
+            match describe(&player).await {
+                Ok(media) => playing.extend(media),
+                Err(e) => debug!("Failed to query {}: {}", player.destination(), e),
+            }
+        }
+        Ok(playing)
+    }).await
+}
+
+// Pause every player, so nothing keeps playing behind a lock
+pub async fn pause_all() {
+    with_timeout("pause media players", async {
+        for player in players().await? {
+            if let Err(e) = player.call_method("Pause", &()).await {
+                debug!("Failed to pause {}: {}", player.destination(), e);
+            }
+        }
+        Ok(())
+    }).await
+}
+
+// For locks: pause unless PAUSE_MEDIA_ON_LOCK is off
+pub async fn pause_for_lock() {
+    if PAUSE_MEDIA_ON_LOCK {
+        pause_all().await;
+    }
+}
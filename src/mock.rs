@@ -0,0 +1,106 @@
+// Stand-ins for the capture, OCR and classifier stages, so the monitoring
+// loop runs without X11, tesseract or an API key. Clones share their state,
+// so a test can keep one and hand the other to the loop.
+
+use anyhow::{Result, anyhow};
+use std::collections::VecDeque;
+use std::future::{self, Future};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::capture::ScreenCapturer;
+use crate::judge::{Classification, ProcrastinationJudge};
+use crate::ocr::OcrEngine;
+use crate::types::Profile;
+
+// Returns the same path for every capture, without writing anything
+#[derive(Clone)]
+pub struct MockCapturer {
+    path: PathBuf,
+    captures: Arc<AtomicUsize>,
+}
+
+impl MockCapturer {
+    pub fn new(path: impl Into<PathBuf>) -> MockCapturer {
+        MockCapturer { path: path.into(), captures: Arc::default() }
+    }
+
+    pub fn captures(&self) -> usize {
+        self.captures.load(Ordering::SeqCst)
+    }
+}
+
+impl ScreenCapturer for MockCapturer {
+    fn capture(&mut self) -> impl Future<Output = Result<PathBuf>> + Send {
+        self.captures.fetch_add(1, Ordering::SeqCst);
+        future::ready(Ok(self.path.clone()))
+    }
+}
+
+// Cycles through the given texts, one per capture
+#[derive(Clone)]
+pub struct MockOcr {
+    texts: Arc<Vec<String>>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl MockOcr {
+    pub fn new<S: Into<String>>(texts: impl IntoIterator<Item = S>) -> MockOcr {
+        let texts: Vec<String> = texts.into_iter().map(Into::into).collect();
+        MockOcr { texts: Arc::new(texts), calls: Arc::default() }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl OcrEngine for MockOcr {
+    fn text(&mut self, _path: &Path) -> impl Future<Output = Result<String>> + Send {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        future::ready(Ok(self.texts.get(call % self.texts.len().max(1)).cloned().unwrap_or_default()))
+    }
+}
+
+// Hands out the scripted verdicts in order, then NOT PROCRASTINATING, and
+// remembers the context of every check. Checks can fail like an
+// unreachable API before the verdicts start.
+#[derive(Clone, Default)]
+pub struct MockJudge {
+    verdicts: Arc<Mutex<VecDeque<bool>>>,
+    contexts: Arc<Mutex<Vec<String>>>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl MockJudge {
+    pub fn new(verdicts: impl IntoIterator<Item = bool>) -> MockJudge {
+        MockJudge { verdicts: Arc::new(Mutex::new(verdicts.into_iter().collect())), ..MockJudge::default() }
+    }
+
+    // The next `checks` checks fail
+    pub fn fail_next(&self, checks: usize) {
+        self.failures.store(checks, Ordering::SeqCst);
+    }
+
+    // Screen context of each check so far
+    pub fn contexts(&self) -> Vec<String> {
+        self.contexts.lock().map(|contexts| contexts.clone()).unwrap_or_default()
+    }
+}
+
+impl ProcrastinationJudge for MockJudge {
+    fn classify(&mut self, _profile: &Profile, text: &str, _frames: Option<&[u8]>)
+        -> impl Future<Output = Result<Classification>> + Send {
+        if let Ok(mut contexts) = self.contexts.lock() {
+            contexts.push(text.to_string());
+        }
+        if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+            return future::ready(Err(anyhow!("API unreachable")));
+        }
+        let verdict = self.verdicts.lock().ok()
+            .and_then(|mut verdicts| verdicts.pop_front())
+            .unwrap_or(false);
+        future::ready(Ok(Classification { procrastinating: verdict, ..Classification::default() }))
+    }
+}
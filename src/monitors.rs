@@ -0,0 +1,40 @@
+// The monitors of the X screen, as RandR reports them
+
+use anyhow::{Result, Context};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+pub struct Monitor {
+    pub name: String,
+    pub primary: bool,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+// Active monitors, left to right
+pub fn list() -> Result<Vec<Monitor>> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let root = conn.setup().roots[screen_num].root;
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+
+    let mut monitors = Vec::new();
+    for monitor in reply.monitors {
+        let name = conn.get_atom_name(monitor.name)?.reply()
+            .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+            .unwrap_or_else(|_| format!("monitor {}", monitors.len() + 1));
+        monitors.push(Monitor {
+            name,
+            primary: monitor.primary,
+            x: monitor.x,
+            y: monitor.y,
+            width: monitor.width,
+            height: monitor.height,
+        });
+    }
+    monitors.sort_by_key(|monitor| (monitor.x, monitor.y));
+    Ok(monitors)
+}
@@ -0,0 +1,25 @@
+// Desktop notifications through the freedesktop notification service,
+// sent only for the categories enabled in NOTIFICATIONS
+
+use notify_rust::Notification as DesktopNotification;
+use tracing::warn;
+
+use crate::constants::NOTIFICATIONS;
+use crate::types::Notification;
+
+// Show a notification if its category is enabled; failures are not worth
+// interrupting for
+pub fn send(category: Notification, summary: &str, body: &str) {
+    if !NOTIFICATIONS.contains(&category) {
+        return;
+    }
+
+    let result = DesktopNotification::new()
+        .appname("perimedes")
+        .summary(summary)
+        .body(body)
+        .show();
+    if let Err(e) = result {
+        warn!("Failed to show notification: {}", e);
+    }
+}
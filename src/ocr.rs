@@ -0,0 +1,122 @@
+// Text recognition of captures with tesseract
+
+use anyhow::{Result, Context, anyhow};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::constants::{OCR_CMD, OCR_WORKERS, PER_MONITOR_OCR};
+use crate::exclude;
+use crate::monitors::{self, Monitor};
+use crate::ocr_cache;
+use crate::redact;
+use crate::tiles;
+
+// Text recognition for the monitoring loop
+pub trait OcrEngine {
+    fn text(&mut self, path: &Path) -> impl Future<Output = Result<String>> + Send;
+}
+
+// Tesseract, with the cache, tiling and filtering of ocr_screenshot
+pub struct Tesseract;
+
+impl OcrEngine for Tesseract {
+    fn text(&mut self, path: &Path) -> impl Future<Output = Result<String>> + Send {
+        ocr_screenshot(path)
+    }
+}
+
+// Text of a capture, with excluded and secret text removed. Secrets are
+// redacted again on the stitched text, as tile and monitor edges can split
+// them where run_ocr doesn't see them whole.
+pub async fn ocr_screenshot(path: &Path) -> Result<String> {
+    let monitors = if PER_MONITOR_OCR {
+        monitors::list().unwrap_or_else(|e| {
+            warn!("OCRing the whole screen at once: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    // Excluded text may have been remembered since the result was cached
+    let text = if monitors.len() > 1 {
+        ocr_monitors(path, &monitors).await
+    } else {
+        ocr_cache::text(path, async |path| tiles::ocr(path, run_ocr).await).await
+    };
+    ocr_cache::flush();
+    Ok(redact::ocr(&exclude::filter(&text?)))
+}
+
+// Each monitor's part of the capture, OCRed in parallel and headed by the
+// monitor, so the classifier can tell the reference docs on one monitor
+// from the work on another
+async fn ocr_monitors(path: &Path, monitors: &[Monitor]) -> Result<String> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load {}", path.display()))?
+        .into_rgb8();
+    let (width, height) = image.dimensions();
+    let workers = Arc::new(Semaphore::new(OCR_WORKERS.max(1)));
+
+    let mut tasks = JoinSet::new();
+    for (i, monitor) in monitors.iter().enumerate() {
+        let (x, y) = (monitor.x.max(0) as u32, monitor.y.max(0) as u32);
+        if x >= width || y >= height {
+            continue;
+        }
+        let part = image::imageops::crop_imm(&image, x, y, (monitor.width as u32).min(width - x), (monitor.height as u32).min(height - y))
+            .to_image();
+        let part_path = path.with_file_name(format!(
+            "{}_monitor{}.png", path.file_stem().unwrap_or_default().to_string_lossy(), i
+        ));
+        part.save(&part_path)
+            .with_context(|| format!("Failed to write {}", part_path.display()))?;
+
+        let workers = workers.clone();
+        tasks.spawn(async move {
+            let _worker = workers.acquire_owned().await;
+            let text = ocr_cache::text(&part_path, async |path| tiles::ocr(path, run_ocr).await).await;
+            let _ = std::fs::remove_file(&part_path);
+            (i, text)
+        });
+    }
+
+    let mut texts = tasks.join_all().await;
+    texts.sort_by_key(|(i, _)| *i);
+    let mut parts = Vec::new();
+    for (i, text) in texts {
+        let monitor = &monitors[i];
+        let primary = if monitor.primary { ", primary" } else { "" };
+        parts.push(format!("[Monitor {} ({} of {}{})]\n{}", monitor.name, i + 1, monitors.len(), primary, text?.trim_end()));
+    }
+    Ok(parts.join("\n\n"))
+}
+
+pub async fn run_ocr(path: &Path) -> Result<String> {
+    let output_file = path.with_extension("txt");
+    let output_base = output_file.with_extension("");
+
+    // Redirect stdout and stderr to /dev/null to suppress warnings
+    let status = Command::new(OCR_CMD)
+        .arg(path)
+        .arg(&output_base)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await;
+    if let Err(e) = status {
+        return Err(anyhow!("Failed to run OCR_CMD '{}': {}; see `perimedes doctor`", OCR_CMD, e));
+    }
+
+    let text = std::fs::read_to_string(&output_file)
+        .context("Failed to read OCR output");
+    // The capture itself may still be shown behind the lock chat
+    let _ = std::fs::remove_file(&output_file);
+    let text = text?;
+
+    Ok(redact::ocr(&exclude::filter(&text)))
+}
@@ -0,0 +1,141 @@
+// OCR results keyed by the content hash of a capture or tile, persisted in
+// ocr_cache.json so identical frames (e.g. a static document) are only
+// OCRed once, across runs too
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::constants::OCR_CACHE_MAX_ENTRIES;
+use crate::dedup;
+use crate::stats;
+use crate::storage;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    text: String,
+    // Value of `clock` when last used, for evicting the least recently used
+    used: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct OcrCache {
+    clock: u64,
+    entries: HashMap<String, Entry>,
+    // Not yet saved: new entries, and hits and misses for the stats
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    hits: u64,
+    #[serde(skip)]
+    misses: u64,
+}
+
+static CACHE: Mutex<Option<OcrCache>> = Mutex::new(None);
+
+const CACHE_FILE: &str = "ocr_cache.json";
+
+impl OcrCache {
+    fn load() -> Result<OcrCache> {
+        storage::load_json(CACHE_FILE)
+    }
+
+    fn save(&self) -> Result<()> {
+        storage::save_json(CACHE_FILE, self)
+    }
+
+    fn get(&mut self, hash: &str) -> Option<String> {
+        self.clock += 1;
+        let Some(entry) = self.entries.get_mut(hash) else {
+            self.misses += 1;
+            return None;
+        };
+        entry.used = self.clock;
+        self.hits += 1;
+        Some(entry.text.clone())
+    }
+
+    fn insert(&mut self, hash: String, text: String) {
+        self.clock += 1;
+        self.entries.insert(hash, Entry { text, used: self.clock });
+        self.dirty = true;
+
+        while self.entries.len() > OCR_CACHE_MAX_ENTRIES {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(hash, _)| hash.clone());
+            match oldest {
+                Some(hash) => self.entries.remove(&hash),
+                None => break,
+            };
+        }
+    }
+}
+
+fn with_cache<T>(f: impl FnOnce(&mut OcrCache) -> T) -> T {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(|| OcrCache::load().unwrap_or_else(|e| {
+        warn!("Failed to load OCR cache, starting empty: {}", e);
+        OcrCache::default()
+    }));
+    f(cache)
+}
+
+// Cached text for the content hash, if any
+pub fn lookup(hash: &str) -> Option<String> {
+    if OCR_CACHE_MAX_ENTRIES == 0 {
+        return None;
+    }
+    with_cache(|cache| cache.get(hash))
+}
+
+pub fn store(hash: String, text: String) {
+    if OCR_CACHE_MAX_ENTRIES > 0 {
+        with_cache(|cache| cache.insert(hash, text));
+    }
+}
+
+// Save new entries and add the lookups since the last flush to the stats
+pub fn flush() {
+    if OCR_CACHE_MAX_ENTRIES == 0 {
+        return;
+    }
+
+    with_cache(|cache| {
+        if cache.dirty {
+            match cache.save() {
+                Ok(()) => cache.dirty = false,
+                Err(e) => warn!("Failed to save OCR cache: {}", e),
+            }
+        }
+        if cache.hits + cache.misses > 0 {
+            match stats::record_ocr_lookups(cache.hits, cache.misses) {
+                Ok(()) => (cache.hits, cache.misses) = (0, 0),
+                Err(e) => warn!("Failed to record OCR cache stats: {}", e),
+            }
+        }
+    });
+}
+
+// Text of the capture, from the cache if the same pixels were OCRed before,
+// otherwise from `ocr`, which is then cached
+pub async fn text(path: &Path, ocr: impl AsyncFnOnce(&Path) -> Result<String>) -> Result<String> {
+    let hash = match dedup::content_hash(path) {
+        Ok(hash) => hash,
+        Err(e) => {
+            warn!("Failed to hash screenshot: {}", e);
+            return ocr(path).await;
+        },
+    };
+
+    if let Some(text) = lookup(&hash) {
+        return Ok(text);
+    }
+
+    let text = ocr(path).await?;
+    store(hash, text.clone());
+    Ok(text)
+}
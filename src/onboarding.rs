@@ -0,0 +1,75 @@
+// A gradual start: the ONBOARDING phases apply one after the other, counted
+// in days from the first time the daemon ran, before the full policy
+
+use anyhow::Result;
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::constants::ONBOARDING;
+use crate::storage;
+use crate::types::OnboardingPhase;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Started {
+    day: Option<String>,
+}
+
+const ONBOARDING_FILE: &str = "onboarding.json";
+
+pub struct Onboarding {
+    // Days since the start, the first day being 1
+    pub day: u64,
+    // The phase of that day, None once onboarding is over
+    pub phase: Option<OnboardingPhase>,
+    pub days_left: u64,
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+// Remember the first day, on the first run only
+pub fn start() -> Result<()> {
+    let started: Started = storage::load_json(ONBOARDING_FILE)?;
+    if started.day.is_none() && !ONBOARDING.is_empty() {
+        storage::save_json(ONBOARDING_FILE, &Started { day: Some(today().to_string()) })?;
+    }
+    Ok(())
+}
+
+pub fn current() -> Result<Onboarding> {
+    let started: Started = storage::load_json(ONBOARDING_FILE)?;
+    let first = started.day.as_deref()
+        .and_then(|day| day.parse::<NaiveDate>().ok())
+        .unwrap_or_else(today);
+    let day = (today() - first).num_days().max(0) as u64 + 1;
+
+    let mut end = 0;
+    for &(phase, days) in ONBOARDING {
+        end += days;
+        if day <= end {
+            return Ok(Onboarding { day, phase: Some(phase), days_left: end + 1 - day });
+        }
+    }
+    Ok(Onboarding { day, phase: None, days_left: 0 })
+}
+
+// The full policy if the start can't be read
+pub fn phase() -> Option<OnboardingPhase> {
+    current()
+        .map_err(|e| warn!("Failed to read the onboarding phase: {}", e))
+        .ok()
+        .and_then(|onboarding| onboarding.phase)
+}
+
+impl Onboarding {
+    pub fn describe(&self) -> Option<String> {
+        let phase = match self.phase? {
+            OnboardingPhase::Observe => "observing only".to_string(),
+            OnboardingPhase::NudgesOnly => "nudges only, no locks".to_string(),
+            OnboardingPhase::ShortLocks(minutes) => format!("locks of at most {} minutes", minutes),
+        };
+        Some(format!("day {}, {} ({} days left of this phase)", self.day, phase, self.days_left))
+    }
+}
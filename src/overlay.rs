@@ -0,0 +1,129 @@
+// Overlay banner across the top of the monitor with the focused window: the
+// classifier's one-line message and a countdown, for BANNER_SECS. It's a
+// rung of the ENFORCEMENT ladder between a notification and a lock, so it
+// takes no input: its input shape is empty, clicks go through to the
+// windows beneath, and work can simply go on. Translucency comes from the
+// compositor's _NET_WM_WINDOW_OPACITY hint rather than an ARGB visual, so
+// text is drawn the same way as everywhere else; without a compositor the
+// banner is opaque.
+
+use anyhow::{Result, Context};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::constants::{BANNER_OPACITY, BANNER_SECS, THEME};
+use crate::monitors::{self, Monitor};
+use crate::window;
+
+const OVERLAY_HEIGHT: u16 = 48;
+// Room for the countdown at the right end
+const COUNTDOWN_WIDTH: i16 = 80;
+
+// Center of the focused window, if the window manager reports one
+fn focused_center(conn: &RustConnection, root: Window) -> Result<Option<(i16, i16)>> {
+    let active = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+    let reply = conn.get_property(false, root, active, AtomEnum::WINDOW, 0, 1)?.reply()?;
+    let Some(win) = reply.value32().and_then(|mut values| values.next()).filter(|win| *win != 0) else {
+        return Ok(None);
+    };
+    let geometry = conn.get_geometry(win)?.reply()?;
+    let origin = conn.translate_coordinates(win, root, 0, 0)?.reply()?;
+    Ok(Some((origin.dst_x + (geometry.width / 2) as i16, origin.dst_y + (geometry.height / 2) as i16)))
+}
+
+// The monitor with the focused window, else the primary one
+fn offending_monitor(conn: &RustConnection, root: Window) -> Option<Monitor> {
+    let center = focused_center(conn, root).ok().flatten();
+    let mut monitors = monitors::list().ok()?;
+    let contains = |monitor: &Monitor, (x, y): (i16, i16)| {
+        (monitor.x..monitor.x + monitor.width as i16).contains(&x) && (monitor.y..monitor.y + monitor.height as i16).contains(&y)
+    };
+    let index = center.and_then(|center| monitors.iter().position(|monitor| contains(monitor, center)))
+        .or_else(|| monitors.iter().position(|monitor| monitor.primary))
+        .unwrap_or(0);
+    (index < monitors.len()).then(|| monitors.swap_remove(index))
+}
+
+// Show the overlay until BANNER_SECS ran out
+pub async fn show(message: &str) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+    let (x, y, width) = match offending_monitor(&conn, screen.root) {
+        Some(monitor) => (monitor.x, monitor.y, monitor.width),
+        None => (0, 0, screen.width_in_pixels),
+    };
+
+    let win = conn.generate_id()?;
+    let values = CreateWindowAux::new()
+        .background_pixel(THEME.background)
+        .override_redirect(1)
+        .event_mask(EventMask::EXPOSURE);
+    conn.create_window(
+        screen.root_depth,
+        win,
+        screen.root,
+        x, y,
+        width, OVERLAY_HEIGHT,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &values,
+    )?;
+    window::set_class(&conn, win)?;
+
+    // An empty input shape lets the pointer through
+    conn.shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, win, 0, 0, &[])?;
+
+    let opacity = conn.intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?.reply()?.atom;
+    let value = (u32::MAX as f64 * BANNER_OPACITY.clamp(0.0, 1.0) as f64) as u32;
+    conn.change_property32(PropMode::REPLACE, win, opacity, AtomEnum::CARDINAL, &[value])?;
+
+    let font = window::load_text_font(&conn, screen);
+    let gc = conn.generate_id()?;
+    let gc_aux = CreateGCAux::new()
+        .foreground(THEME.system)
+        .background(THEME.background)
+        .font(font.core_font());
+    conn.create_gc(gc, win, &gc_aux)?;
+
+    conn.map_window(win)?;
+    conn.flush()?;
+
+    let start = Instant::now();
+    let duration = Duration::from_secs(BANNER_SECS);
+    let baseline = (OVERLAY_HEIGHT / 2 + 6) as i16;
+    let mut shown_secs = None;
+    while start.elapsed() < duration {
+        let mut redraw = false;
+        while let Some(event) = conn.poll_for_event()? {
+            if let Event::Expose(_) = event {
+                redraw = true;
+            }
+        }
+
+        // Once a second: the countdown, and back on top of windows mapped since
+        let remaining = (duration - start.elapsed()).as_secs() + 1;
+        if redraw || shown_secs != Some(remaining) {
+            shown_secs = Some(remaining);
+            conn.configure_window(win, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+            conn.clear_area(false, win, 0, 0, 0, 0)?;
+            window::draw_text(&conn, win, gc, &font, message, 20, baseline, THEME.system)?;
+            let countdown = format!("{}s", remaining);
+            window::draw_text(&conn, win, gc, &font, &countdown, width as i16 - COUNTDOWN_WIDTH, baseline, THEME.system)?;
+            conn.flush()?;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    conn.destroy_window(win)?;
+    conn.flush()?;
+    Ok(())
+}
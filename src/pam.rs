@@ -0,0 +1,103 @@
+// Password check through PAM
+//
+// libpam is loaded at runtime, like libxkbcommon, so perimedes builds and
+// runs without PAM development files; the emergency unlock is then simply
+// unavailable.
+
+use anyhow::{Result, anyhow};
+use libloading::{Library, Symbol};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_CONV_ERR: c_int = 19;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+type ConvFn = extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int;
+
+#[repr(C)]
+struct PamConv {
+    conv: ConvFn,
+    appdata_ptr: *mut c_void,
+}
+
+type PamStart = unsafe extern "C" fn(*const c_char, *const c_char, *const PamConv, *mut *mut c_void) -> c_int;
+type PamAuthenticate = unsafe extern "C" fn(*mut c_void, c_int) -> c_int;
+type PamEnd = unsafe extern "C" fn(*mut c_void, c_int) -> c_int;
+
+// Answer every prompt with the password passed as appdata. PAM frees the
+// responses, so they are allocated with malloc.
+extern "C" fn conversation(
+    count: c_int,
+    messages: *mut *const PamMessage,
+    responses: *mut *mut PamResponse,
+    appdata: *mut c_void,
+) -> c_int {
+    if count <= 0 || messages.is_null() || responses.is_null() || appdata.is_null() {
+        return PAM_CONV_ERR;
+    }
+
+    unsafe {
+        let replies = libc::calloc(count as usize, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+        if replies.is_null() {
+            return PAM_BUF_ERR;
+        }
+
+        for i in 0..count as usize {
+            let message = &**messages.add(i);
+            if message.msg_style == PAM_PROMPT_ECHO_OFF || message.msg_style == PAM_PROMPT_ECHO_ON {
+                (*replies.add(i)).resp = libc::strdup(appdata as *const c_char);
+            }
+        }
+
+        *responses = replies;
+    }
+    PAM_SUCCESS
+}
+
+// Check the password of the current user with the given PAM service
+pub fn authenticate(service: &str, password: &str) -> Result<bool> {
+    let user = std::env::var("USER").map_err(|_| anyhow!("USER is not set"))?;
+    let service = CString::new(service)?;
+    let user = CString::new(user)?;
+    let password = CString::new(password)?;
+
+    unsafe {
+        let library = Library::new("libpam.so.0")
+            .map_err(|e| anyhow!("libpam could not be loaded: {}", e))?;
+        let pam_start: Symbol<PamStart> = library.get(b"pam_start\0")?;
+        let pam_authenticate: Symbol<PamAuthenticate> = library.get(b"pam_authenticate\0")?;
+        let pam_end: Symbol<PamEnd> = library.get(b"pam_end\0")?;
+
+        let conv = PamConv {
+            conv: conversation,
+            appdata_ptr: password.as_ptr() as *mut c_void,
+        };
+
+        let mut handle = ptr::null_mut();
+        let status = pam_start(service.as_ptr(), user.as_ptr(), &conv, &mut handle);
+        if status != PAM_SUCCESS {
+            return Err(anyhow!("pam_start failed with {}", status));
+        }
+
+        let status = pam_authenticate(handle, 0);
+        pam_end(handle, status);
+
+        Ok(status == PAM_SUCCESS)
+    }
+}
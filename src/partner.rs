@@ -0,0 +1,71 @@
+// Remote approval of emergency unlocks by an accountability partner: the
+// request is posted to a webhook and the lock screen polls a status URL
+// until the partner approves it
+
+use anyhow::{Result, Context};
+use rand::Rng;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::ipc;
+use crate::types::PartnerApproval;
+
+#[derive(Deserialize)]
+struct ApprovalStatus {
+    #[serde(default)]
+    approved: bool,
+}
+
+// Token identifying one approval request, shared with the partner
+pub fn new_token() -> String {
+    let n: u64 = rand::thread_rng().gen();
+    format!("{:016x}", n)
+}
+
+// Ask the partner to approve the unlock identified by `token`
+pub async fn request(client: &Client, partner: &PartnerApproval, token: &str) -> Result<()> {
+    let body = json!({
+        "token": token,
+        "lock": ipc::current_status().lock,
+        "text": format!(
+            "perimedes: emergency unlock requested. Approve with token {} (expires in {} minutes).",
+            token, partner.timeout_minutes
+        ),
+    });
+
+    client.post(partner.request_url).json(&body).send().await
+        .context("Failed to send approval request")?
+        .error_for_status()
+        .context("Approval webhook rejected the request")?;
+    Ok(())
+}
+
+// Tell the partner about something without asking for approval
+pub async fn alert(client: &Client, partner: &PartnerApproval, text: &str) -> Result<()> {
+    let body = json!({
+        "lock": ipc::current_status().lock,
+        "text": text,
+    });
+
+    client.post(partner.request_url).json(&body).send().await
+        .context("Failed to send the alert")?
+        .error_for_status()
+        .context("Partner webhook rejected the alert")?;
+    Ok(())
+}
+
+// Whether the partner has approved the request yet. The status URL is
+// queried with the token appended and answers {"approved": true|false}.
+pub async fn approved(client: &Client, partner: &PartnerApproval, token: &str) -> Result<bool> {
+    let url = format!("{}{}", partner.status_url, token);
+    let response = client.get(&url).send().await
+        .context("Failed to poll approval status")?;
+    if !response.status().is_success() {
+        return Ok(false);
+    }
+
+    let status: ApprovalStatus = response.json().await
+        .context("Failed to parse approval status")?;
+    Ok(status.approved)
+}
@@ -0,0 +1,73 @@
+// Judge personas: which one judges a lock, and its system prompt
+
+use anyhow::{Result, Context};
+use rand::seq::SliceRandom;
+use tracing::warn;
+
+use crate::constants::{JOURNAL_EXCERPTS, JUDGE_PROMPT, PERSONAS, PERSONA_SELECTION};
+use crate::prompts::{self, Template};
+use crate::stats;
+use crate::types::{Persona, PersonaSelection};
+
+// Persona for the next lock, unless it is picked on the lock screen
+pub fn select() -> Option<&'static Persona> {
+    match PERSONA_SELECTION {
+        PersonaSelection::Fixed(name) => Some(by_name(name)),
+        PersonaSelection::Rotate => {
+            let index = stats::next_persona(PERSONAS.len()).unwrap_or_else(|e| {
+                warn!("Failed to rotate personas: {}", e);
+                0
+            });
+            PERSONAS.get(index)
+        },
+        PersonaSelection::Pick => None,
+    }
+}
+
+// Persona with the name, or the first one
+pub fn by_name(name: &str) -> &'static Persona {
+    PERSONAS.iter().find(|persona| persona.name == name).unwrap_or(&PERSONAS[0])
+}
+
+// Full system prompt of the persona, including the rules for deciding
+pub fn prompt(persona: &Persona, screen_context: &str) -> String {
+    let mut intro = persona.prompt.to_string();
+    if let Some(path) = persona.journal {
+        let excerpts = journal_excerpts(path).unwrap_or_else(|e| {
+            warn!("Failed to read journal: {}", e);
+            String::new()
+        });
+        intro = intro.replace("{journal}", &excerpts);
+    }
+    let rules = prompts::render(Template::Judge, screen_context).unwrap_or_else(|e| {
+        warn!("{:#}, using the built-in judge prompt", e);
+        JUDGE_PROMPT.to_string()
+    });
+    format!("{} {}", intro, rules)
+}
+
+// The journal path with "~/" expanded
+pub fn journal_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", std::env::var("HOME").unwrap_or_default(), rest),
+        None => path.to_string(),
+    }
+}
+
+// A few random paragraphs of the journal, in their original order
+fn journal_excerpts(path: &str) -> Result<String> {
+    let path = journal_path(path);
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path))?;
+
+    let paragraphs: Vec<&str> = text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+    let mut picked: Vec<usize> = (0..paragraphs.len()).collect();
+    picked.shuffle(&mut rand::thread_rng());
+    picked.truncate(JOURNAL_EXCERPTS);
+    picked.sort();
+
+    Ok(picked.iter().map(|&i| paragraphs[i]).collect::<Vec<_>>().join("\n\n"))
+}
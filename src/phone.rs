@@ -0,0 +1,189 @@
+// Approval of emergency unlocks on the phone: a push with approve and deny
+// links goes out through ntfy or the Telegram bot, and a tap on one of them
+// reaches a small HTTP endpoint of ours through the relay, e.g. a reverse
+// tunnel to PhoneApproval::listen_port.
+//
+// Links are signed with an HMAC of the answer and a one-time token, keyed by
+// $PERIMEDES_APPROVAL_SECRET, so only the links of the current request are
+// accepted and a deny link can't be turned into an approval.
+
+use anyhow::{Result, Context, anyhow};
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::partner;
+use crate::redact;
+use crate::telegram;
+use crate::types::{ApprovalPush, PhoneApproval};
+
+const SECRET_VAR: &str = "PERIMEDES_APPROVAL_SECRET";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Answer {
+    Approved,
+    Denied,
+}
+
+impl Answer {
+    fn path(self) -> &'static str {
+        match self {
+            Answer::Approved => "approve",
+            Answer::Denied => "deny",
+        }
+    }
+}
+
+// A request waiting for a tap; dropping it closes the endpoint
+pub struct Request {
+    pub token: String,
+    answer: oneshot::Receiver<Answer>,
+    server: JoinHandle<()>,
+}
+
+impl Request {
+    // The answer, once one of the links was opened
+    pub fn answer(&mut self) -> Option<Answer> {
+        self.answer.try_recv().ok()
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn secret() -> Result<Vec<u8>> {
+    let secret = std::env::var(SECRET_VAR).ok().filter(|secret| !secret.is_empty())
+        .ok_or_else(|| anyhow!("${} is not set", SECRET_VAR))?;
+    redact::register_secret(&secret);
+    Ok(secret.into_bytes())
+}
+
+// HMAC-SHA256 as hex
+fn sign(secret: &[u8], message: &str) -> String {
+    let mut key = [0u8; 64];
+    if secret.len() > key.len() {
+        key[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        key[..secret.len()].copy_from_slice(secret);
+    }
+    let inner = Sha256::new()
+        .chain_update(key.map(|byte| byte ^ 0x36))
+        .chain_update(message.as_bytes())
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(key.map(|byte| byte ^ 0x5c))
+        .chain_update(inner)
+        .finalize();
+    outer.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn link(approval: &PhoneApproval, secret: &[u8], token: &str, answer: Answer) -> String {
+    let signature = sign(secret, &format!("{}:{}", answer.path(), token));
+    format!("{}/{}/{}/{}", approval.relay_url.trim_end_matches('/'), answer.path(), token, signature)
+}
+
+// Open the endpoint and push the links to the phone
+pub async fn request(client: &Client, approval: &PhoneApproval) -> Result<Request> {
+    let secret = secret()?;
+    let token = partner::new_token();
+
+    let listener = TcpListener::bind(("127.0.0.1", approval.listen_port)).await
+        .with_context(|| format!("Failed to listen on port {}", approval.listen_port))?;
+    let (answer_tx, answer) = oneshot::channel();
+    let server = tokio::spawn(serve(listener, secret.clone(), token.clone(), answer_tx));
+    let request = Request { token: token.clone(), answer, server };
+
+    let text = format!(
+        "perimedes: emergency unlock requested (token {}). Approve within {} minutes?",
+        token, approval.timeout_minutes
+    );
+    let approve = link(approval, &secret, &token, Answer::Approved);
+    let deny = link(approval, &secret, &token, Answer::Denied);
+    match approval.push {
+        ApprovalPush::Ntfy(url) => {
+            let mut push = client.post(url)
+                .header("Title", "perimedes unlock request")
+                .header("Priority", "high")
+                .header("Actions", format!(
+                    "http, Approve, {}, method=POST, clear=true; http, Deny, {}, method=POST, clear=true",
+                    approve, deny
+                ))
+                .body(text);
+            if let Ok(token) = std::env::var("NTFY_TOKEN") {
+                push = push.bearer_auth(token);
+            }
+            push.send().await
+                .context("Failed to send the ntfy push")?
+                .error_for_status()
+                .context("ntfy rejected the push")?;
+        },
+        ApprovalPush::Telegram => telegram::send_links(&text, &[("Approve", &approve), ("Deny", &deny)]).await?,
+    }
+    Ok(request)
+}
+
+// Answer requests until one carries a valid link of this token
+async fn serve(listener: TcpListener, secret: Vec<u8>, token: String, answer: oneshot::Sender<Answer>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Approval endpoint accept error: {}", e);
+                continue;
+            },
+        };
+        match handle(stream, &secret, &token).await {
+            Ok(Some(tapped)) => {
+                let _ = answer.send(tapped);
+                return;
+            },
+            Ok(None) => {},
+            Err(e) => warn!("Approval endpoint error: {}", e),
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, secret: &[u8], token: &str) -> Result<Option<Answer>> {
+    let mut line = String::new();
+    BufReader::new(&mut stream).read_line(&mut line).await?;
+
+    // GET from a browser, POST from ntfy actions
+    let path = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET" | "POST", path, _] => path.to_string(),
+        _ => String::new(),
+    };
+    let answer = parse(&path, secret, token);
+
+    let (status, body) = match answer {
+        Some(Answer::Approved) => ("200 OK", "Unlock approved"),
+        Some(Answer::Denied) => ("200 OK", "Unlock denied"),
+        None => ("404 Not Found", "No such request"),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(answer)
+}
+
+// The answer of a /<answer>/<token>/<signature> path, if it is a valid link
+fn parse(path: &str, secret: &[u8], token: &str) -> Option<Answer> {
+    let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let [kind, request_token, signature] = parts[..] else {
+        return None;
+    };
+    let answer = [Answer::Approved, Answer::Denied].into_iter().find(|answer| answer.path() == kind)?;
+    let expected = sign(secret, &format!("{}:{}", kind, token));
+    // Compare without an early exit, the timing would leak the signature
+    let matches = request_token == token && signature.len() == expected.len()
+        && signature.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+    matches.then_some(answer)
+}
@@ -0,0 +1,249 @@
+// Decision policies as local rules, Rhai scripts and WebAssembly modules
+//
+// Rules are regexes over the focused window and its workspace, the browser
+// tab and the latest capture, see RULES.
+// A policy sees structured signals about the current capture and decides
+// deterministically, before (and possibly instead of) the LLM classifier.
+//
+// A Rhai script gets the signals as variables (text, domains, window_class,
+// window_title, workspace, url, dwell_minutes, winding_down, history) and
+// evaluates to
+// "procrastinating", "focused" or "defer"; anything else defers. E.g.:
+//   if domains.contains("youtube.com") && window_title.contains("lecture") { "focused" }
+// Scripts can't touch files or the network and are stopped after
+// SCRIPT_MAX_OPERATIONS steps or SCRIPT_TIMEOUT_MS.
+//
+// A WebAssembly module must export:
+//   memory
+//   alloc(len: i32) -> i32               - buffer for the signals JSON
+//   decide(ptr: i32, len: i32) -> i64    - (result ptr << 32) | result len
+// The result is JSON: {"decision": "procrastinating" | "focused" | "defer"}
+// and optionally "reason". "defer" hands the capture to the LLM, so a policy
+// that never defers replaces it entirely. No host functions are imported.
+
+use anyhow::{Result, Context, anyhow};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use crate::constants::{POLICY_FUEL, RULES, RULE_WEIGHT_THRESHOLD, SCRIPT_MAX_OPERATIONS, SCRIPT_TIMEOUT_MS};
+use crate::focus::ActiveWindow;
+use crate::types::{LockReason, Rule, RuleAction};
+
+// Input to a policy
+#[derive(Serialize)]
+pub struct Signals<'a> {
+    pub time: String,
+    pub profile: &'a str,
+    pub text: &'a str,
+    pub window: Option<&'a ActiveWindow>,
+    // The browser tab's URL, empty without the extension
+    pub url: &'a str,
+    pub domains: Vec<String>,
+    // How long the focused application has been in front, in minutes
+    pub dwell_minutes: f64,
+    // Whether a scheduled hard block is about to start
+    pub winding_down: bool,
+    // Recent verdicts, oldest first
+    pub history: Vec<bool>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Procrastinating,
+    Focused,
+    Defer,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    decision: Decision,
+    #[serde(default)]
+    reason: String,
+}
+
+pub struct Policy {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    decide: TypedFunc<(i32, i32), i64>,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Policy> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+
+        let module = Module::from_file(&engine, path)
+            .with_context(|| format!("Failed to load policy {}", path.display()))?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])
+            .context("Failed to instantiate policy; it may not import anything")?;
+
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("Policy doesn't export its memory"))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let decide = instance.get_typed_func(&mut store, "decide")?;
+
+        Ok(Policy { store, memory, alloc, decide })
+    }
+
+    // Run the policy on the signals. Each call gets POLICY_FUEL, so a
+    // runaway policy fails instead of stalling the daemon.
+    pub fn decide(&mut self, signals: &Signals) -> Result<(Decision, String)> {
+        self.store.set_fuel(POLICY_FUEL)?;
+
+        let input = serde_json::to_vec(signals)?;
+        let ptr = self.alloc.call(&mut self.store, input.len() as i32)?;
+        self.memory.write(&mut self.store, ptr as usize, &input)
+            .context("Policy returned an invalid buffer")?;
+
+        let packed = self.decide.call(&mut self.store, (ptr, input.len() as i32))? as u64;
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+
+        let mut output = vec![0; len];
+        self.memory.read(&self.store, ptr, &mut output)
+            .context("Policy returned an invalid result")?;
+
+        let reply: Reply = serde_json::from_slice(&output)
+            .with_context(|| format!("Unexpected policy result: {}", String::from_utf8_lossy(&output)))?;
+        Ok((reply.decision, reply.reason))
+    }
+}
+
+// RULES with their regexes compiled
+pub struct Rules {
+    rules: Vec<(&'static Rule, [Option<Regex>; 5])>,
+}
+
+impl Rules {
+    pub fn load() -> Result<Rules> {
+        let rules = RULES.iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let compile = |pattern: Option<&str>| {
+                    pattern.map(Regex::new).transpose().with_context(|| format!("RULES[{}]", i))
+                };
+                let [class, title, workspace, url, text] = [rule.class, rule.title, rule.workspace, rule.url, rule.text].map(compile);
+                Ok((rule, [class?, title?, workspace?, url?, text?]))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Rules { rules })
+    }
+
+    // The decision, why, and the lock reason of a denying rule
+    pub fn decide(&self, window: Option<&ActiveWindow>, url: &str, text: &str) -> (Decision, String, Option<LockReason>) {
+        let (class, title) = window.map_or(("", ""), |window| (window.class.as_str(), window.title.as_str()));
+        let workspace = window.and_then(|window| window.workspace.as_deref()).unwrap_or("");
+        let mut weight = 0;
+        let mut matched = Vec::new();
+        for (i, (rule, patterns)) in self.rules.iter().enumerate() {
+            let matches = |pattern: &Option<Regex>, haystack| pattern.as_ref().is_none_or(|p| p.is_match(haystack));
+            if !patterns.iter().zip([class, title, workspace, url, text]).all(|(pattern, haystack)| matches(pattern, haystack)) {
+                continue;
+            }
+            match rule.action {
+                RuleAction::Allow => return (Decision::Focused, format!("rule {}", i), None),
+                RuleAction::Deny(reason) => return (Decision::Procrastinating, format!("rule {}", i), Some(reason)),
+                RuleAction::Weight(w) => {
+                    weight += w;
+                    matched.push(i.to_string());
+                },
+            }
+        }
+
+        let reason = format!("rules {} (weight {})", matched.join(", "), weight);
+        if weight >= RULE_WEIGHT_THRESHOLD {
+            (Decision::Procrastinating, reason, None)
+        } else if weight <= -RULE_WEIGHT_THRESHOLD {
+            (Decision::Focused, reason, None)
+        } else {
+            (Decision::Defer, reason, None)
+        }
+    }
+}
+
+pub struct Script {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+    started: Rc<Cell<Instant>>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script> {
+        let mut engine = rhai::Engine::new();
+        // No `import` of other files: the script sees nothing but its signals
+        engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+        engine.set_max_operations(SCRIPT_MAX_OPERATIONS);
+        engine.set_max_string_size(1 << 20);
+        engine.set_max_array_size(10_000);
+
+        // Abort scripts running past SCRIPT_TIMEOUT_MS
+        let started = Rc::new(Cell::new(Instant::now()));
+        let deadline = started.clone();
+        engine.on_progress(move |_| {
+            (deadline.get().elapsed() > Duration::from_millis(SCRIPT_TIMEOUT_MS))
+                .then(|| "timed out".into())
+        });
+
+        let ast = engine.compile_file(path.to_path_buf())
+            .map_err(|e| anyhow!("Failed to load policy script {}: {}", path.display(), e))?;
+        Ok(Script { engine, ast, started })
+    }
+
+    pub fn decide(&self, signals: &Signals) -> Result<(Decision, String)> {
+        let (class, title, workspace) = match signals.window {
+            Some(window) => (window.class.clone(), window.title.clone(), window.workspace.clone().unwrap_or_default()),
+            None => (String::new(), String::new(), String::new()),
+        };
+        let domains: rhai::Array = signals.domains.iter().cloned().map(rhai::Dynamic::from).collect();
+        let history: rhai::Array = signals.history.iter().copied().map(rhai::Dynamic::from).collect();
+
+        let mut scope = rhai::Scope::new();
+        scope.push_constant("time", signals.time.clone());
+        scope.push_constant("profile", signals.profile.to_string());
+        scope.push_constant("text", signals.text.to_string());
+        scope.push_constant("domains", domains);
+        scope.push_constant("window_class", class);
+        scope.push_constant("window_title", title);
+        scope.push_constant("workspace", workspace);
+        scope.push_constant("url", signals.url.to_string());
+        scope.push_constant("dwell_minutes", signals.dwell_minutes);
+        scope.push_constant("winding_down", signals.winding_down);
+        scope.push_constant("history", history);
+
+        self.started.set(Instant::now());
+        let result = self.engine.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| anyhow!("Policy script failed: {}", e))?;
+
+        let decision = match result.into_string().as_deref() {
+            Ok("procrastinating") => Decision::Procrastinating,
+            Ok("focused") => Decision::Focused,
+            _ => Decision::Defer,
+        };
+        Ok((decision, "script".to_string()))
+    }
+}
+
+// Domain names mentioned in the screen text, e.g. from the address bar
+pub fn domains(text: &str) -> Vec<String> {
+    static DOMAIN: OnceLock<Regex> = OnceLock::new();
+    let domain = DOMAIN.get_or_init(|| {
+        Regex::new(r"\b(?:[a-z0-9-]+\.)+(?:com|org|net|io|dev|rs|edu|gov|me|tv|app|co|uk|de)\b")
+            .expect("invalid domain pattern")
+    });
+
+    let mut domains = domain.find_iter(&text.to_lowercase())
+        .map(|m| m.as_str().trim_start_matches("www.").to_string())
+        .collect::<Vec<_>>();
+    domains.sort();
+    domains.dedup();
+    domains
+}
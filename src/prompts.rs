@@ -0,0 +1,146 @@
+// Prompt templates: the built-in classifier and judge prompts, or the user's
+// own from classify.md and judge.md in ~/.config/perimedes/prompts
+//
+// Templates refer to {screen_text}, the captured screen context; {goals},
+// the user's goals.md in GOALS_PROMPT; {intention}, the answer to the morning
+// check-in in INTENTION_PROMPT; and {history}, the user's labeled verdicts
+// for the classifier and the latest locks for the judge.
+// They are checked at startup, and read again for every prompt, so edits
+// take effect right away.
+
+use anyhow::{Result, Context, anyhow};
+use regex::{Captures, Regex};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing::warn;
+
+use crate::constants::{CHECK_PROCRASTINATION_PROMPT, GOALS_PROMPT, INTENTION_PROMPT, JUDGE_PROMPT, PROMPT_HISTORY_LOCKS};
+use crate::intention;
+use crate::locks;
+use crate::storage;
+use crate::verdicts;
+
+#[derive(Clone, Copy)]
+pub enum Template {
+    Classify,
+    Judge,
+}
+
+const PLACEHOLDERS: &[&str] = &["screen_text", "goals", "intention", "history"];
+
+impl Template {
+    pub const ALL: &[Template] = &[Template::Classify, Template::Judge];
+
+    fn file(self) -> &'static str {
+        match self {
+            Template::Classify => "classify.md",
+            Template::Judge => "judge.md",
+        }
+    }
+
+    fn default(self) -> &'static str {
+        match self {
+            Template::Classify => CHECK_PROCRASTINATION_PROMPT,
+            Template::Judge => JUDGE_PROMPT,
+        }
+    }
+
+    // The judge sees the screen text in the conversation anyway
+    fn required(self) -> &'static [&'static str] {
+        match self {
+            Template::Classify => &["screen_text"],
+            Template::Judge => &[],
+        }
+    }
+
+    pub fn path(self) -> PathBuf {
+        storage::config_dir().join("prompts").join(self.file())
+    }
+}
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{([a-z_]+)\}").unwrap())
+}
+
+// The template's text, from the user's file if there is one
+pub fn load(template: Template) -> Result<String> {
+    let path = template.path();
+    if !path.exists() {
+        return Ok(template.default().to_string());
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    validate(template, &text).with_context(|| format!("Invalid prompt template {}", path.display()))?;
+    Ok(text)
+}
+
+fn validate(template: Template, text: &str) -> Result<()> {
+    let used = placeholder().captures_iter(text)
+        .map(|captures| captures[1].to_string())
+        .collect::<Vec<_>>();
+    if let Some(unknown) = used.iter().find(|name| !PLACEHOLDERS.contains(&name.as_str())) {
+        return Err(anyhow!("unknown placeholder {{{}}}, use {}", unknown,
+                           PLACEHOLDERS.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", ")));
+    }
+    if let Some(missing) = template.required().iter().find(|name| !used.iter().any(|used| used == *name)) {
+        return Err(anyhow!("missing the {{{}}} placeholder", missing));
+    }
+    Ok(())
+}
+
+// Check the user's templates, e.g. at startup
+pub fn check() -> Result<()> {
+    for template in Template::ALL {
+        load(*template)?;
+    }
+    Ok(())
+}
+
+fn goals_path() -> PathBuf {
+    storage::config_dir().join("goals.md")
+}
+
+// The goals in GOALS_PROMPT, or nothing without a goals file
+fn goals() -> String {
+    let goals = std::fs::read_to_string(goals_path()).unwrap_or_default();
+    match goals.trim() {
+        "" => String::new(),
+        goals => GOALS_PROMPT.replace("{}", goals),
+    }
+}
+
+fn history(template: Template) -> Result<String> {
+    match template {
+        Template::Classify => verdicts::examples(),
+        Template::Judge => {
+            let locks = locks::load()?;
+            let recent = locks.iter()
+                .skip(locks.len().saturating_sub(PROMPT_HISTORY_LOCKS))
+                .map(|lock| format!("* {}: {}", lock.time, lock.what))
+                .collect::<Vec<_>>();
+            Ok(recent.join("\n"))
+        },
+    }
+}
+
+// The template with its placeholders filled in, in a single pass so the
+// screen text can't smuggle in placeholders of its own
+pub fn render(template: Template, screen_text: &str) -> Result<String> {
+    let text = load(template)?;
+    let goals = goals();
+    let intention = intention::current()
+        .map(|intention| INTENTION_PROMPT.replace("{}", &intention))
+        .unwrap_or_default();
+    let history = history(template).unwrap_or_else(|e| {
+        warn!("Failed to load the history for the prompt: {}", e);
+        String::new()
+    });
+    Ok(placeholder().replace_all(&text, |captures: &Captures| match &captures[1] {
+        "screen_text" => screen_text.to_string(),
+        "goals" => goals.clone(),
+        "intention" => intention.clone(),
+        "history" => history.clone(),
+        _ => captures[0].to_string(),
+    }).into_owned())
+}
@@ -0,0 +1,127 @@
+// Redaction of secrets and sensitive payloads
+//
+// Screen text, conversations and raw API responses are withheld from logs
+// unless `--log-sensitive` is given. Secrets are scrubbed in either case.
+// OCR text is redacted before it is sent anywhere or stored, see `ocr`.
+
+use regex::Regex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::constants::OCR_REDACT_PATTERNS;
+
+static LOG_SENSITIVE: AtomicBool = AtomicBool::new(false);
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Patterns for key-like strings that should never appear in logs
+fn secret_patterns() -> &'static Vec<Regex> {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            r"sk-ant-[A-Za-z0-9_\-]+",
+            r"(?i)(x-api-key|authorization)\s*[:=]\s*\S+",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("invalid built-in redaction pattern"))
+        .collect()
+    })
+}
+
+// Built-in patterns for sensitive content in screen text
+fn ocr_patterns() -> &'static Vec<Regex> {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // API keys and tokens with well-known prefixes
+            r"\b(?:sk-[A-Za-z0-9_\-]{16,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{20,}|xox[abpr]-[A-Za-z0-9\-]{10,}|AIza[0-9A-Za-z_\-]{35})\b",
+            // Long random-looking tokens, e.g. hex or base64 secrets
+            r"\b(?:[A-Fa-f0-9]{32,}|[A-Za-z0-9+/]{40,}={0,2})",
+            // Email addresses
+            r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}\b",
+            // Values after password-like labels
+            r"(?i)\b(password|passwd|passphrase|pin|secret|token)\b\s*[:=]\s*\S+",
+            // One-time codes near a label
+            r"(?i)\b(code|otp|2fa|verification)\b\D{0,20}\b\d{6,8}\b",
+        ]
+        .iter()
+        .chain(OCR_REDACT_PATTERNS)
+        .map(|p| Regex::new(p).expect("invalid OCR redaction pattern"))
+        .collect()
+    })
+}
+
+// Sequences of 13-19 digits, possibly grouped, checked with Luhn below
+fn card_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\b\d(?:[ \-]?\d){12,18}\b").expect("invalid card pattern"))
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let sum: u32 = number.chars()
+        .filter_map(|c| c.to_digit(10))
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| match (i % 2 == 1, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+// Redact key-like strings, emails, card numbers, passwords and codes from
+// screen text, plus anything matching OCR_REDACT_PATTERNS
+pub fn ocr(text: &str) -> String {
+    let mut text = scrub(text);
+
+    for pattern in ocr_patterns() {
+        text = pattern.replace_all(&text, "[REDACTED]").into_owned();
+    }
+
+    card_pattern()
+        .replace_all(&text, |caps: &regex::Captures| {
+            if luhn_valid(&caps[0]) { "[REDACTED]".to_string() } else { caps[0].to_string() }
+        })
+        .into_owned()
+}
+
+pub fn set_log_sensitive(enabled: bool) {
+    LOG_SENSITIVE.store(enabled, Ordering::Relaxed);
+}
+
+// Register a secret (e.g. the API key) to be scrubbed verbatim
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock() {
+        secrets.push(secret.to_string());
+    }
+}
+
+// Remove secrets from text; use for anything that goes to a log, including errors
+pub fn scrub(text: &str) -> String {
+    let mut text = text.to_string();
+
+    if let Ok(secrets) = SECRETS.lock() {
+        for secret in secrets.iter() {
+            text = text.replace(secret.as_str(), "[REDACTED]");
+        }
+    }
+
+    for pattern in secret_patterns() {
+        text = pattern.replace_all(&text, "[REDACTED]").into_owned();
+    }
+
+    text
+}
+
+// Payloads such as screen text or model output: logged only with --log-sensitive
+pub fn sensitive(text: &str) -> String {
+    if LOG_SENSITIVE.load(Ordering::Relaxed) {
+        scrub(text)
+    } else {
+        format!("[{} bytes withheld, use --log-sensitive]", text.len())
+    }
+}
@@ -0,0 +1,190 @@
+// Record-and-replay of lock screen input, guarding the key handling
+//
+// With PERIMEDES_RECORD_KEYS set to a file, the lock screen appends the
+// keysyms of each submitted line, followed by the submission itself. Letters
+// become x or X and digits 0, so a recording keeps the editing but not what
+// was written; decisions such as the unlock phrase are kept verbatim. Lines
+// that recall history are dropped, as it differs between sessions.
+//
+// play() types a recording into the focused lock window through XTEST; the
+// replay test checks that the windowed lock UI returns the same submissions.
+
+use anyhow::{Result, Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use tracing::warn;
+
+use crate::constants::keysym;
+use crate::exclude;
+
+const RECORD_VAR: &str = "PERIMEDES_RECORD_KEYS";
+const SHIFT_L: u32 = 0xffe1;
+// Delay between replayed keys, so the lock screen sees them one at a time
+const KEY_DELAY: Duration = Duration::from_millis(20);
+const FOCUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Key(u32),
+    Submit(String),
+}
+
+// Keys of the line being typed, written out once it is submitted
+static LINE: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn recording() -> Option<String> {
+    std::env::var(RECORD_VAR).ok().filter(|path| !path.is_empty())
+}
+
+// A new line starts whenever the lock screen clears its input
+pub fn new_line() {
+    if let Ok(mut line) = LINE.lock() {
+        line.clear();
+    }
+}
+
+pub fn record_key(keysym: u32) {
+    if recording().is_some() {
+        if let Ok(mut line) = LINE.lock() {
+            line.push(keysym);
+        }
+    }
+}
+
+// Write out the keys of a line together with what the lock screen made of it
+pub fn record_submit(input: &str) {
+    let Some(path) = recording() else {
+        return;
+    };
+    let Ok(mut line) = LINE.lock() else {
+        return;
+    };
+    let keys = std::mem::take(&mut *line);
+    if keys.iter().any(|key| matches!(*key, keysym::UP | keysym::DOWN)) {
+        return;
+    }
+
+    // Unlock sentinels are decisions, not something the user wrote
+    let decision = input.starts_with("__");
+    let steps = keys.into_iter()
+        .filter_map(|key| if decision { Some(key) } else { sanitize_key(key) })
+        .map(Step::Key)
+        .chain([Step::Submit(if decision { input.to_string() } else { sanitize(input) })]);
+
+    let result = steps.map(|step| Ok(serde_json::to_string(&step)? + "\n"))
+        .collect::<Result<String>>()
+        .and_then(|lines| {
+            use std::io::Write;
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+            Ok(file.write_all(lines.as_bytes())?)
+        });
+    if let Err(e) = result {
+        warn!("Failed to record keys to {}: {}", path, e);
+    }
+}
+
+// Letters and digits are replaced; other printable ASCII and the editing
+// keys are kept, everything else is dropped
+fn sanitize_key(key: u32) -> Option<u32> {
+    match key {
+        0x30..=0x39 => Some(0x30),
+        0x41..=0x5a => Some(0x58),
+        0x61..=0x7a => Some(0x78),
+        0x20..=0x7e | keysym::ENTER | keysym::BACKSPACE | keysym::ESCAPE => Some(key),
+        _ => None,
+    }
+}
+
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| sanitize_key(c as u32).and_then(char::from_u32))
+        .collect()
+}
+
+pub fn load(path: &Path) -> Result<Vec<Step>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).with_context(|| format!("Bad step in {}: {}", path.display(), line)))
+        .collect()
+}
+
+// Submissions the lock screen should produce for a recording
+pub fn submissions(steps: &[Step]) -> Vec<String> {
+    steps.iter()
+        .filter_map(|step| match step {
+            Step::Submit(input) => Some(input.clone()),
+            Step::Key(_) => None,
+        })
+        .collect()
+}
+
+// Type the keys of a recording once a lock window has the focus
+pub fn play(steps: &[Step]) -> Result<()> {
+    let (conn, _) = x11rb::connect(None).context("Failed to connect to X server")?;
+    if conn.extension_information(xtest::X11_EXTENSION_NAME)?.is_none() {
+        return Err(anyhow!("X server lacks the XTEST extension"));
+    }
+    wait_for_lock_window(&conn)?;
+
+    let setup = conn.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn.get_keyboard_mapping(min, max - min + 1)?.reply()?;
+    let per_keycode = mapping.keysyms_per_keycode as usize;
+    // Keycode of a keysym, and whether it needs Shift
+    let find = |keysym: u32| -> Option<(u8, bool)> {
+        mapping.keysyms.chunks(per_keycode.max(1)).enumerate()
+            .find_map(|(i, syms)| syms.iter().take(2).position(|s| *s == keysym).map(|level| (min + i as u8, level == 1)))
+    };
+    let (shift, _) = find(SHIFT_L).ok_or_else(|| anyhow!("No keycode for Shift"))?;
+
+    for step in steps {
+        let Step::Key(keysym) = step else {
+            continue;
+        };
+        let (keycode, shifted) = find(*keysym)
+            .ok_or_else(|| anyhow!("No keycode for keysym {:#x} in the current layout", keysym))?;
+        if shifted {
+            fake_key(&conn, KEY_PRESS_EVENT, shift)?;
+        }
+        fake_key(&conn, KEY_PRESS_EVENT, keycode)?;
+        fake_key(&conn, KEY_RELEASE_EVENT, keycode)?;
+        if shifted {
+            fake_key(&conn, KEY_RELEASE_EVENT, shift)?;
+        }
+        std::thread::sleep(KEY_DELAY);
+    }
+    Ok(())
+}
+
+fn fake_key(conn: &RustConnection, kind: u8, keycode: u8) -> Result<()> {
+    conn.xtest_fake_input(kind, keycode, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)?;
+    conn.sync()?;
+    Ok(())
+}
+
+// Keys sent before the lock window has the focus would go elsewhere
+fn wait_for_lock_window(conn: &RustConnection) -> Result<()> {
+    let started = Instant::now();
+    while started.elapsed() < FOCUS_TIMEOUT {
+        let focus = conn.get_input_focus()?.reply()?.focus;
+        let class = conn.get_property(false, focus, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 64)?
+            .reply()
+            .map(|reply| reply.value)
+            .unwrap_or_default();
+        if class == exclude::WM_CLASS {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Err(anyhow!("No lock window got the focus within {}s", FOCUS_TIMEOUT.as_secs()))
+}
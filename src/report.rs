@@ -0,0 +1,80 @@
+// Daily summaries for sharing, e.g. with an accountability partner
+//
+// Reports only hold per-day aggregates of the verdict history, never screen
+// content. With `--private`, every count gets Laplace noise scaled by
+// 1/REPORT_EPSILON and is rounded to REPORT_ROUNDING, so single checks can't
+// be told apart.
+
+use anyhow::Result;
+use rand::Rng;
+use std::collections::BTreeMap;
+
+use crate::constants::{REPORT_DAYS, REPORT_EPSILON, REPORT_ROUNDING};
+use crate::types::Category;
+use crate::verdicts;
+
+#[derive(Default)]
+struct Day {
+    checks: u64,
+    procrastinating: u64,
+    wrong: u64,
+    // Checks by the category the classifier named
+    categories: BTreeMap<&'static str, u64>,
+}
+
+// Laplace noise with scale 1/epsilon; each count has sensitivity 1
+fn laplace(epsilon: f64) -> f64 {
+    let u: f64 = rand::thread_rng().gen_range(-0.5..0.5);
+    -u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln() / epsilon
+}
+
+fn privatize(count: u64) -> u64 {
+    let noisy = (count as f64 + laplace(REPORT_EPSILON)).max(0.0);
+    ((noisy / REPORT_ROUNDING as f64).round() as u64) * REPORT_ROUNDING
+}
+
+pub fn print_report(private: bool) -> Result<()> {
+    let mut days: BTreeMap<String, Day> = BTreeMap::new();
+    for verdict in verdicts::load()? {
+        let day = days.entry(verdict.time.chars().take(10).collect()).or_default();
+        day.checks += 1;
+        day.procrastinating += verdict.procrastinating as u64;
+        day.wrong += (verdict.correct == Some(false)) as u64;
+        if let Some(category) = verdict.category {
+            *day.categories.entry(category.name()).or_default() += 1;
+        }
+    }
+
+    if days.is_empty() {
+        println!("No verdicts recorded yet.");
+        return Ok(());
+    }
+
+    if private {
+        println!("Counts with noise (epsilon {}) and rounded to {}", REPORT_EPSILON, REPORT_ROUNDING);
+    }
+    println!("{:<12} {:>8} {:>16} {:>8}", "DAY", "CHECKS", "PROCRASTINATING", "WRONG");
+
+    let count = |count: u64| if private { privatize(count) } else { count };
+    let skip = days.len().saturating_sub(REPORT_DAYS);
+    for (date, day) in days.iter().skip(skip) {
+        println!("{:<12} {:>8} {:>16} {:>8}", date, count(day.checks), count(day.procrastinating), count(day.wrong));
+    }
+
+    // Totals over the days shown, with noise added to each day like above
+    let by_category: Vec<String> = Category::ALL.iter()
+        .map(|category| {
+            let checks: u64 = days.values().skip(skip)
+                .map(|day| count(day.categories.get(category.name()).copied().unwrap_or(0)))
+                .sum();
+            (category.name(), checks)
+        })
+        .filter(|(_, checks)| *checks > 0)
+        .map(|(name, checks)| format!("{} {}", name, checks))
+        .collect();
+    if !by_category.is_empty() {
+        println!("\nchecks by category: {}", by_category.join(", "));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+// Focus hours: monitoring and locking only happen within them
+//
+// Weekly hours are written like "Mon-Fri 09:00-18:00" or "Mon,Wed 14:00-17:30",
+// date overrides like "2026-12-24 10:00-12:00" or "2026-12-31 off", and
+// holidays are named dates or date ranges, e.g. ("Vacation", "2026-08-03..2026-08-14").
+// An override replaces the weekly hours of its date; holidays have no hours.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime};
+
+use crate::constants::{HOLIDAYS, SCHEDULE, SCHEDULE_OVERRIDES};
+
+pub struct Schedule<'a> {
+    pub weekly: &'a [&'a str],
+    pub overrides: &'a [&'a str],
+    pub holidays: &'a [(&'a str, &'a str)],
+}
+
+pub const CONFIGURED: Schedule<'static> = Schedule {
+    weekly: SCHEDULE,
+    overrides: SCHEDULE_OVERRIDES,
+    holidays: HOLIDAYS,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Focus,
+    // Outside the focus hours, with the holiday if it's one
+    Off(Option<String>),
+}
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+fn weekday(name: &str) -> Result<u32> {
+    DAYS.iter().position(|day| name.eq_ignore_ascii_case(day))
+        .map(|index| index as u32)
+        .ok_or_else(|| anyhow!("Unknown day '{}', use Mon to Sun", name))
+}
+
+// Days of "Mon-Fri" or "Mon,Wed,Sat-Sun", as days from Monday
+fn days(spec: &str) -> Result<Vec<u32>> {
+    let mut days = Vec::new();
+    for part in spec.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (weekday(first)?, weekday(last)?);
+                if last < first {
+                    return Err(anyhow!("Day range '{}' runs backwards", part));
+                }
+                days.extend(first..=last);
+            },
+            None => days.push(weekday(part)?),
+        }
+    }
+    Ok(days)
+}
+
+fn time(text: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M").map_err(|_| anyhow!("Invalid time '{}', use HH:MM", text))
+}
+
+// "09:00-12:00,13:00-18:00"
+fn hours(spec: &str) -> Result<Vec<(NaiveTime, NaiveTime)>> {
+    spec.split(',')
+        .map(|range| {
+            let (start, end) = range.split_once('-').ok_or_else(|| anyhow!("Invalid hours '{}'", range))?;
+            let (start, end) = (time(start)?, time(end)?);
+            if end <= start {
+                return Err(anyhow!("Hours '{}' end before they start", range));
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+fn date(text: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| anyhow!("Invalid date '{}', use YYYY-MM-DD", text))
+}
+
+fn split(entry: &str) -> Result<(&str, &str)> {
+    entry.trim().split_once(' ').ok_or_else(|| anyhow!("Invalid schedule entry '{}'", entry))
+}
+
+impl Schedule<'_> {
+    // Parse every entry, so mistakes show up at start rather than at 9am
+    pub fn check(&self) -> Result<()> {
+        for (i, entry) in self.weekly.iter().enumerate() {
+            split(entry)
+                .and_then(|(spec, range)| days(spec).and(hours(range)))
+                .with_context(|| format!("SCHEDULE[{}] \"{}\"", i, entry))?;
+        }
+        for (i, entry) in self.overrides.iter().enumerate() {
+            split(entry)
+                .and_then(|(day, range)| date(day).and(if range == "off" { Ok(Vec::new()) } else { hours(range) }))
+                .with_context(|| format!("SCHEDULE_OVERRIDES[{}] \"{}\"", i, entry))?;
+        }
+        for (i, (name, dates)) in self.holidays.iter().enumerate() {
+            self.holiday_dates(dates).with_context(|| format!("HOLIDAYS[{}] \"{}\"", i, name))?;
+        }
+        Ok(())
+    }
+
+    fn holiday_dates(&self, dates: &str) -> Result<(NaiveDate, NaiveDate)> {
+        match dates.split_once("..") {
+            Some((first, last)) => Ok((date(first)?, date(last)?)),
+            None => date(dates).map(|day| (day, day)),
+        }
+    }
+
+    // Hours of the last override of a date
+    fn override_on(&self, day: NaiveDate) -> Option<&str> {
+        self.overrides.iter().rev()
+            .filter_map(|entry| split(entry).ok())
+            .find(|(text, _)| date(text).is_ok_and(|date| date == day))
+            .map(|(_, range)| range)
+    }
+
+    // Focus hours of a date, empty if no entry covers it
+    fn hours_on(&self, day: NaiveDate) -> Vec<(NaiveTime, NaiveTime)> {
+        if let Some(range) = self.override_on(day) {
+            return if range == "off" { Vec::new() } else { hours(range).unwrap_or_default() };
+        }
+
+        let weekday = day.weekday().num_days_from_monday();
+        self.weekly.iter()
+            .filter_map(|entry| split(entry).ok())
+            .filter(|(spec, _)| days(spec).is_ok_and(|days| days.contains(&weekday)))
+            .flat_map(|(_, range)| hours(range).unwrap_or_default())
+            .collect()
+    }
+
+    pub fn status(&self, now: DateTime<Local>) -> Status {
+        // No schedule means always
+        if self.weekly.is_empty() && self.overrides.is_empty() && self.holidays.is_empty() {
+            return Status::Focus;
+        }
+
+        let today = now.date_naive();
+        let holiday = self.holidays.iter()
+            .find(|(_, dates)| self.holiday_dates(dates).is_ok_and(|(first, last)| today >= first && today <= last));
+        if let Some((name, _)) = holiday {
+            return Status::Off(Some(name.to_string()));
+        }
+
+        // Without weekly hours, the schedule only takes days off
+        let unscheduled = self.weekly.is_empty() && self.override_on(today).is_none();
+        let time = now.time();
+        if unscheduled || self.hours_on(today).iter().any(|(start, end)| time >= *start && time < *end) {
+            Status::Focus
+        } else {
+            Status::Off(None)
+        }
+    }
+}
+
+// Whether we are within the configured focus hours
+pub fn status() -> Status {
+    CONFIGURED.status(Local::now())
+}
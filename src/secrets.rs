@@ -0,0 +1,84 @@
+// The Anthropic API key, from a secret provider rather than the environment,
+// where every child process and `ps e` could see it. Sources are tried in
+// order: API_KEY_COMMAND, the keyring entry stored with `perimedes set-key`,
+// and $ANTHROPIC_API_KEY, which is then removed from our environment.
+
+use anyhow::{Result, Context, anyhow};
+use std::io::{BufRead, Write};
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::constants::{API_KEY_COMMAND, KEYRING_SERVICE};
+
+const ENV_VAR: &str = "ANTHROPIC_API_KEY";
+const KEYRING_USER: &str = "anthropic-api-key";
+
+fn entry() -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).context("Failed to open the keyring entry")
+}
+
+pub async fn api_key() -> Result<String> {
+    let key = match API_KEY_COMMAND {
+        Some(command) => from_command(command).await?,
+        None => match from_keyring().await {
+            Ok(Some(key)) => key,
+            result => {
+                if let Err(e) = result {
+                    warn!("Failed to read the API key from the keyring: {}", e);
+                }
+                std::env::var(ENV_VAR).ok().filter(|key| !key.is_empty()).ok_or_else(|| anyhow!(
+                    "No API key: store one with `perimedes set-key`, set API_KEY_COMMAND or ${}", ENV_VAR
+                ))?
+            },
+        },
+    };
+
+    // Children such as scrot and tesseract have no use for it
+    std::env::remove_var(ENV_VAR);
+    Ok(key)
+}
+
+// First line of the command's output, e.g. `pass show anthropic`
+async fn from_command(command: &[&str]) -> Result<String> {
+    let (program, args) = command.split_first().ok_or_else(|| anyhow!("API_KEY_COMMAND is empty"))?;
+    let output = Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next()
+        .map(|line| line.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow!("{} printed no API key", program))
+}
+
+// The Secret Service is spoken to synchronously, off the async workers
+async fn from_keyring() -> Result<Option<String>> {
+    tokio::task::spawn_blocking(|| match entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow!(e)),
+    }).await?
+}
+
+// `perimedes set-key`: read the key from stdin, so it stays out of the
+// shell history and the process list, and store it in the keyring
+pub async fn set_key() -> Result<()> {
+    eprint!("Anthropic API key: ");
+    std::io::stderr().flush()?;
+    let mut key = String::new();
+    std::io::stdin().lock().read_line(&mut key).context("Failed to read the API key")?;
+    let key = key.trim().to_string();
+    if key.is_empty() {
+        return Err(anyhow!("No API key given"));
+    }
+
+    tokio::task::spawn_blocking(move || entry()?.set_password(&key).context("Failed to store the API key"))
+        .await??;
+    println!("Stored the API key in the keyring as {}/{}", KEYRING_SERVICE, KEYRING_USER);
+    Ok(())
+}
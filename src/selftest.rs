@@ -0,0 +1,96 @@
+// Quick check of the lock path, so a broken lock shows up before the first
+// real enforcement: the lock window, the input grabs, fonts and the timer
+
+use anyhow::{Result, Context, anyhow};
+use std::sync::Arc;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+
+use crate::constants::THEME;
+use crate::grab;
+use crate::window;
+
+// Run the checks on a lock window just past the right edge of the screen,
+// printing each step; the first failure is returned
+pub async fn lock_path() -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+    println!("ok    X connection");
+
+    // One column stays on screen, since the pointer can't be confined to a
+    // window entirely outside the root window
+    let win = conn.generate_id()?;
+    let values = CreateWindowAux::new()
+        .background_pixel(THEME.background)
+        .override_redirect(1);
+    conn.create_window(
+        screen.root_depth,
+        win,
+        screen.root,
+        screen.width_in_pixels as i16 - 1, 0,
+        screen.width_in_pixels, screen.height_in_pixels,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &values,
+    )?.check().context("Failed to create the lock window")?;
+    window::set_class(&conn, win)?;
+    let cursor = window::create_invisible_cursor(&conn, win)?;
+    conn.map_window(win)?;
+    conn.sync()?;
+    println!("ok    lock window");
+
+    let result = check_window(&conn, screen, win, cursor).await;
+    conn.destroy_window(win)?;
+    conn.free_cursor(cursor)?;
+    conn.flush()?;
+    result
+}
+
+async fn check_window(conn: &Arc<RustConnection>, screen: &Screen, win: Window, cursor: Cursor) -> Result<()> {
+    // The grab confines the pointer to the window, so put it back afterwards
+    let pointer = conn.query_pointer(screen.root)?.reply()?;
+    let grabbed = grab::try_grab(conn, screen, win, cursor).await?;
+    conn.ungrab_keyboard(CURRENT_TIME)?;
+    conn.ungrab_pointer(CURRENT_TIME)?;
+    conn.warp_pointer(x11rb::NONE, screen.root, 0, 0, 0, 0, pointer.root_x, pointer.root_y)?;
+    conn.sync()?;
+    if !grabbed {
+        let conflicts = grab::find_conflicts(conn).unwrap_or_default();
+        let holder = if conflicts.is_empty() { "another program".to_string() } else { grab::describe(&conflicts) };
+        return Err(anyhow!("Could not grab keyboard and mouse, held by {}", holder));
+    }
+    println!("ok    keyboard and mouse grab, released again");
+
+    let font = window::load_text_font(conn, screen);
+    match &font {
+        window::TextFont::TrueType(_) => println!("ok    TrueType font"),
+        window::TextFont::Core(_) => println!("ok    core X font, no TrueType font found"),
+        window::TextFont::Boxes => return Err(anyhow!("No usable font, lock screen text would be boxes")),
+    }
+
+    // Draw a countdown the way the timer does; drawing errors arrive as events
+    let gc = conn.generate_id()?;
+    let gc_aux = CreateGCAux::new()
+        .foreground(THEME.text)
+        .background(THEME.background)
+        .font(font.core_font());
+    conn.create_gc(gc, win, &gc_aux)?;
+    window::draw_text(conn, win, gc, &font, "25:00", 20, 40, THEME.text)?;
+    conn.free_gc(gc)?;
+    conn.sync()?;
+    while let Some(event) = conn.poll_for_event()? {
+        if let Event::Error(e) = event {
+            return Err(anyhow!("X error while drawing the timer: {:?}", e));
+        }
+    }
+    println!("ok    timer text");
+
+    Ok(())
+}
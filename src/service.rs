@@ -0,0 +1,106 @@
+// systemd user service: unit installation, readiness and watchdog pings
+//
+// Outside of systemd (no $NOTIFY_SOCKET) the notifications are no-ops.
+
+use anyhow::{Result, Context};
+use sd_notify::NotifyState;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::constants::WATCHDOG_SEC;
+
+fn unit_path() -> PathBuf {
+    let config = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".config")
+        }
+    };
+    config.join("systemd/user/perimedes.service")
+}
+
+// Whether systemd runs us and restarts us on failure
+pub fn supervised() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+// Write the user unit for this binary, started with the graphical session
+pub fn install(profile: Option<&str>) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the perimedes binary")?;
+    let mut exec_start = exe.display().to_string();
+    if let Some(profile) = profile {
+        exec_start.push_str(&format!(" --profile {}", profile));
+    }
+
+    let unit = format!("\
+[Unit]
+Description=Perimedes procrastination lock
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+ExecStart={}
+# ANTHROPIC_API_KEY=... goes here, unless stored with `perimedes set-key`
+EnvironmentFile=-%h/.config/perimedes/env
+Restart=on-failure
+RestartSec=5
+WatchdogSec={}
+
+[Install]
+WantedBy=graphical-session.target
+", exec_start, WATCHDOG_SEC);
+
+    let path = unit_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, unit)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    println!("Wrote {}", path.display());
+    println!("Store the API key with `perimedes set-key` (or put ANTHROPIC_API_KEY=... into");
+    println!("~/.config/perimedes/env), make sure DISPLAY is");
+    println!("imported into the user manager (systemctl --user import-environment DISPLAY XAUTHORITY),");
+    println!("then enable it with: systemctl --user enable --now perimedes.service");
+    Ok(())
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+pub fn ready() {
+    notify(&[NotifyState::Ready]);
+}
+
+// Tell the watchdog the main loop is alive
+pub fn watchdog() {
+    notify(&[NotifyState::Watchdog]);
+}
+
+// Keeps pinging the watchdog while the main loop is blocked in a lock,
+// which can last arbitrarily long while the chat waits for input. Stops
+// when dropped.
+pub struct KeepAlive(JoinHandle<()>);
+
+pub fn keep_alive() -> KeepAlive {
+    KeepAlive(tokio::spawn(async {
+        loop {
+            watchdog();
+            tokio::time::sleep(Duration::from_secs(WATCHDOG_SEC / 3)).await;
+        }
+    }))
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -0,0 +1,383 @@
+// Persistent per-profile API spend tracking
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::constants::{
+    CATEGORY_POLICIES, MODEL_PRICES, MONTHLY_BUDGET_USD, MAX_PAUSES_PER_DAY, MAX_PAUSE_MINUTES_PER_DAY,
+    MIN_LOCK_MINUTES, MAX_LOCK_MINUTES, LOCK_ESCALATION, LOCK_ESCALATION_HOURS, PROCRASTINATION_BUDGET_MINUTES,
+    REASON_BUDGET_MINUTES,
+};
+use crate::ipc;
+use crate::locks;
+use crate::storage;
+use crate::trust;
+use crate::verdicts;
+use crate::types::{Category, CategoryPolicy, LockReason, Profile, Usage};
+
+// Spend of a single profile; the daily counters reset when the day changes
+#[derive(Serialize, Deserialize, Default)]
+pub struct Spend {
+    pub day: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    pub total_cost_usd: f64,
+}
+
+// API usage of all profiles this month, against MONTHLY_BUDGET_USD
+#[derive(Serialize, Deserialize, Default)]
+pub struct Month {
+    pub month: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+// Pauses taken today, limited by MAX_PAUSES_PER_DAY and MAX_PAUSE_MINUTES_PER_DAY
+#[derive(Serialize, Deserialize, Default)]
+pub struct Pauses {
+    pub day: String,
+    pub count: u32,
+    pub minutes: u64,
+}
+
+// Procrastination detected today, against PROCRASTINATION_BUDGET_MINUTES
+#[derive(Serialize, Deserialize, Default)]
+pub struct Procrastination {
+    pub day: String,
+    pub seconds: u64,
+    // Against REASON_BUDGET_MINUTES
+    #[serde(default)]
+    pub by_reason: BTreeMap<String, u64>,
+}
+
+// Time in categories with a Minutes policy today, against CATEGORY_POLICIES
+#[derive(Serialize, Deserialize, Default)]
+pub struct CategoryTime {
+    pub day: String,
+    pub seconds: BTreeMap<String, u64>,
+}
+
+// Lookups in the OCR cache since it was created
+#[derive(Serialize, Deserialize, Default)]
+pub struct OcrLookups {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Stats {
+    pub profiles: BTreeMap<String, Spend>,
+    #[serde(default)]
+    pub month: Month,
+    #[serde(default)]
+    pub pauses: Pauses,
+    #[serde(default)]
+    pub ocr_cache: OcrLookups,
+    // Index of the next persona with PersonaSelection::Rotate
+    #[serde(default)]
+    pub next_persona: usize,
+    // Start times of today's judge locks, for escalating repeat offenses
+    #[serde(default)]
+    pub locks: Vec<String>,
+    #[serde(default)]
+    pub procrastination: Procrastination,
+    #[serde(default)]
+    pub categories: CategoryTime,
+}
+
+const STATS_FILE: &str = "stats.json";
+
+fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+fn this_month() -> String {
+    Local::now().format("%Y-%m").to_string()
+}
+
+// Estimated cost in USD of a request to the given model
+pub fn cost_usd(model: &str, usage: &Usage) -> f64 {
+    let (_, input_price, output_price) = MODEL_PRICES.iter()
+        .find(|(name, _, _)| *name == model)
+        .or(MODEL_PRICES.last())
+        .copied()
+        .unwrap_or(("", 0.0, 0.0));
+
+    // Cache writes cost a quarter more than plain input, reads a tenth
+    let input = usage.input_tokens as f64
+        + usage.cache_creation_input_tokens as f64 * 1.25
+        + usage.cache_read_input_tokens as f64 * 0.1;
+    (input * input_price + usage.output_tokens as f64 * output_price) / 1_000_000.0
+}
+
+impl Stats {
+    pub fn load() -> Result<Stats> {
+        storage::load_json(STATS_FILE)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        storage::save_json(STATS_FILE, self)
+    }
+
+    // Spend entry for a profile, with daily counters reset if the day changed
+    fn spend_mut(&mut self, profile: &str) -> &mut Spend {
+        let day = today();
+        let spend = self.profiles.entry(profile.to_string()).or_default();
+        if spend.day != day {
+            spend.day = day;
+            spend.input_tokens = 0;
+            spend.output_tokens = 0;
+            spend.cost_usd = 0.0;
+        }
+        spend
+    }
+
+    // This month's usage, reset if the month changed
+    fn month_mut(&mut self) -> &mut Month {
+        let month = this_month();
+        if self.month.month != month {
+            self.month = Month { month, ..Month::default() };
+        }
+        &mut self.month
+    }
+
+    pub fn spent_this_month(&self) -> f64 {
+        if self.month.month == this_month() { self.month.cost_usd } else { 0.0 }
+    }
+
+    pub fn spent_today(&self, profile: &str) -> f64 {
+        self.profiles.get(profile)
+            .filter(|spend| spend.day == today())
+            .map(|spend| spend.cost_usd)
+            .unwrap_or(0.0)
+    }
+}
+
+// Add the usage of one API call to the profile's spend
+pub fn record_usage(profile: &Profile, model: &str, usage: &Usage) -> Result<()> {
+    let mut stats = Stats::load()?;
+    let cost = cost_usd(model, usage);
+
+    let spend = stats.spend_mut(profile.name);
+    spend.input_tokens += usage.all_input_tokens();
+    spend.output_tokens += usage.output_tokens;
+    spend.cost_usd += cost;
+    spend.total_cost_usd += cost;
+
+    let month = stats.month_mut();
+    month.input_tokens += usage.all_input_tokens();
+    month.output_tokens += usage.output_tokens;
+    month.cost_usd += cost;
+
+    stats.save()?;
+    ipc::cost(profile.name, model, cost);
+    ipc::spend(&stats.profiles[profile.name], &stats.month);
+    Ok(())
+}
+
+// Show the spend so far in the status, before the first API call
+pub fn show_spend(profile: &Profile) -> Result<()> {
+    let mut stats = Stats::load()?;
+    stats.spend_mut(profile.name);
+    stats.month_mut();
+    ipc::spend(&stats.profiles[profile.name], &stats.month);
+    Ok(())
+}
+
+// Whether the profile has used up its daily budget
+pub fn over_budget(profile: &Profile) -> Result<bool> {
+    Ok(Stats::load()?.spent_today(profile.name) >= profile.daily_budget_usd)
+}
+
+// Whether MONTHLY_BUDGET_USD is used up
+pub fn over_monthly_budget() -> Result<bool> {
+    match MONTHLY_BUDGET_USD {
+        Some(budget) => Ok(Stats::load()?.spent_this_month() >= budget),
+        None => Ok(false),
+    }
+}
+
+// Take a pause of the given length out of today's snooze budget. Minutes
+// count as requested, even if the pause is resumed early.
+pub fn record_pause(minutes: u64) -> Result<()> {
+    let mut stats = Stats::load()?;
+    let pauses = &mut stats.pauses;
+    if pauses.day != today() {
+        *pauses = Pauses { day: today(), ..Pauses::default() };
+    }
+
+    let max_pauses = MAX_PAUSES_PER_DAY + trust::current_or_none().extra_pauses();
+    if pauses.count >= max_pauses {
+        return Err(anyhow!("all {} pauses for today are used up", max_pauses));
+    }
+    let left = MAX_PAUSE_MINUTES_PER_DAY.saturating_sub(pauses.minutes);
+    if minutes > left {
+        return Err(anyhow!("only {} of {} pause minutes left today", left, MAX_PAUSE_MINUTES_PER_DAY));
+    }
+
+    pauses.count += 1;
+    pauses.minutes += minutes;
+    stats.save()
+}
+
+// Record the start of a judge lock and return how many earlier ones fall
+// within LOCK_ESCALATION_HOURS
+pub fn record_lock() -> Result<usize> {
+    let mut stats = Stats::load()?;
+    let now = Local::now();
+    let day = today();
+    stats.locks.retain(|time| time.starts_with(&day));
+
+    let since = (now - chrono::Duration::hours(LOCK_ESCALATION_HOURS as i64))
+        .format("%Y-%m-%d %H:%M:%S").to_string();
+    let earlier = stats.locks.iter().filter(|time| **time >= since).count();
+    stats.locks.push(now.format("%Y-%m-%d %H:%M:%S").to_string());
+    stats.save()?;
+    Ok(earlier)
+}
+
+// Allowed lock minutes after this many recent locks
+pub fn lock_range(earlier: usize) -> (u64, u64) {
+    let factor = LOCK_ESCALATION.get(earlier)
+        .or(LOCK_ESCALATION.last())
+        .copied()
+        .unwrap_or(1)
+        .max(1);
+    (MIN_LOCK_MINUTES * factor, MAX_LOCK_MINUTES * factor)
+}
+
+// Count detected procrastination against today's budgets; returns the
+// seconds left of the smaller one that applies, None without a budget
+pub fn spend_procrastination(seconds: u64, reason: Option<LockReason>) -> Result<Option<u64>> {
+    let reason_budget = REASON_BUDGET_MINUTES.iter()
+        .find(|(budgeted, _)| Some(*budgeted) == reason)
+        .map(|(reason, minutes)| (reason.name(), minutes));
+    if PROCRASTINATION_BUDGET_MINUTES.is_none() && reason_budget.is_none() {
+        return Ok(None);
+    }
+
+    let mut stats = Stats::load()?;
+    let procrastination = &mut stats.procrastination;
+    if procrastination.day != today() {
+        *procrastination = Procrastination { day: today(), ..Procrastination::default() };
+    }
+    procrastination.seconds += seconds;
+    let mut left = PROCRASTINATION_BUDGET_MINUTES
+        .map(|minutes| (minutes * 60).saturating_sub(procrastination.seconds));
+    if let Some((name, minutes)) = reason_budget {
+        let spent = procrastination.by_reason.entry(name.to_string()).or_default();
+        *spent += seconds;
+        let reason_left = (minutes * 60).saturating_sub(*spent);
+        left = Some(left.map_or(reason_left, |left| left.min(reason_left)));
+    }
+    stats.save()?;
+    Ok(left)
+}
+
+// Whether CATEGORY_POLICIES allows the category right now; time allowed
+// by a Minutes policy counts against it
+pub fn category_allowed(category: Category, seconds: u64) -> Result<bool> {
+    let minutes = match CATEGORY_POLICIES.iter().find(|(policy_for, _)| *policy_for == category) {
+        Some((_, CategoryPolicy::Allow)) => return Ok(true),
+        Some((_, CategoryPolicy::Minutes(minutes))) => *minutes,
+        None => return Ok(false),
+    };
+
+    let mut stats = Stats::load()?;
+    let categories = &mut stats.categories;
+    if categories.day != today() {
+        *categories = CategoryTime { day: today(), ..CategoryTime::default() };
+    }
+    let spent = categories.seconds.entry(category.name().to_string()).or_default();
+    if *spent >= minutes * 60 {
+        return Ok(false);
+    }
+    *spent += seconds;
+    stats.save()?;
+    Ok(true)
+}
+
+// Seconds left of today's procrastination budget, None without a budget
+pub fn procrastination_left() -> Result<Option<u64>> {
+    spend_procrastination(0, None)
+}
+
+// Take the next of `count` personas in turn
+pub fn next_persona(count: usize) -> Result<usize> {
+    let mut stats = Stats::load()?;
+    let index = stats.next_persona % count.max(1);
+    stats.next_persona = index + 1;
+    stats.save()?;
+    Ok(index)
+}
+
+// Count lookups in the OCR cache
+pub fn record_ocr_lookups(hits: u64, misses: u64) -> Result<()> {
+    let mut stats = Stats::load()?;
+    stats.ocr_cache.hits += hits;
+    stats.ocr_cache.misses += misses;
+    stats.save()
+}
+
+// Today's locks by reason
+fn print_lock_reasons() -> Result<()> {
+    let locks = locks::load()?;
+    let today: Vec<_> = locks.iter().filter(|lock| lock.time.starts_with(&today())).collect();
+    let breakdown: Vec<String> = LockReason::ALL.iter()
+        .map(|reason| (reason, today.iter().filter(|lock| lock.reason == Some(*reason)).count()))
+        .filter(|(_, count)| *count > 0)
+        .map(|(reason, count)| format!("{} {}", reason.name(), count))
+        .collect();
+    if !breakdown.is_empty() {
+        println!("locks today by reason: {}", breakdown.join(", "));
+    }
+    Ok(())
+}
+
+// Print spend per profile for the `stats` command
+pub fn print_stats() -> Result<()> {
+    let stats = Stats::load()?;
+    if stats.profiles.is_empty() {
+        println!("No API usage recorded yet.");
+        return Ok(());
+    }
+
+    println!("{:<12} {:>10} {:>10} {:>10} {:>10}", "PROFILE", "IN TOKENS", "OUT TOKENS", "TODAY $", "TOTAL $");
+    for (name, spend) in &stats.profiles {
+        let (input, output, cost) = if spend.day == today() {
+            (spend.input_tokens, spend.output_tokens, spend.cost_usd)
+        } else {
+            (0, 0, 0.0)
+        };
+        println!("{:<12} {:>10} {:>10} {:>10.4} {:>10.4}", name, input, output, cost, spend.total_cost_usd);
+    }
+    if stats.month.month == this_month() {
+        let budget = MONTHLY_BUDGET_USD.map(|budget| format!(" of ${:.2}", budget)).unwrap_or_default();
+        println!("this month: ${:.4}{} ({} tokens in, {} out)",
+                 stats.month.cost_usd, budget, stats.month.input_tokens, stats.month.output_tokens);
+    }
+
+    let (count, minutes) = if stats.pauses.day == today() {
+        (stats.pauses.count, stats.pauses.minutes)
+    } else {
+        (0, 0)
+    };
+    let trust = trust::current()?;
+    println!("\npauses today: {}/{} ({}/{} minutes)", count, MAX_PAUSES_PER_DAY + trust.extra_pauses(), minutes, MAX_PAUSE_MINUTES_PER_DAY);
+    let locks = stats.locks.iter().filter(|time| time.starts_with(&today())).count();
+    println!("judge locks today: {}", locks);
+    print_lock_reasons()?;
+    let lookups = stats.ocr_cache.hits + stats.ocr_cache.misses;
+    if lookups > 0 {
+        println!("OCR cache: {}/{} hits ({:.0}%)", stats.ocr_cache.hits, lookups,
+            100.0 * stats.ocr_cache.hits as f64 / lookups as f64);
+    }
+    verdicts::print_score()?;
+    println!("trust: {}", trust.describe());
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+// Persistent state: JSON and JSON-lines files in the state directory
+
+use anyhow::{Result, Context};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Directory for persistent state, following the XDG base directory spec
+pub fn state_dir() -> PathBuf {
+    match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("perimedes"),
+        _ => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".local/state/perimedes")
+        }
+    }
+}
+
+// Directory for the user's files, e.g. prompt templates
+pub fn config_dir() -> PathBuf {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("perimedes"),
+        _ => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".config/perimedes")
+        }
+    }
+}
+
+// Path of a file in the state directory
+pub fn path(name: &str) -> PathBuf {
+    state_dir().join(name)
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+// Contents of a JSON file, or the default if it doesn't exist yet
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> Result<T> {
+    let path = path(name);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let path = path(name);
+    create_parent(&path)?;
+    std::fs::write(&path, serde_json::to_string_pretty(value)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Entries of a JSON-lines file, skipping lines that don't parse
+pub fn load_jsonl<T: DeserializeOwned>(name: &str) -> Result<Vec<T>> {
+    let path = path(name);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(data.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+pub fn save_jsonl<T: Serialize>(name: &str, entries: &[T]) -> Result<()> {
+    let path = path(name);
+    create_parent(&path)?;
+    let mut data = String::new();
+    for entry in entries {
+        data.push_str(&serde_json::to_string(entry)?);
+        data.push('\n');
+    }
+    std::fs::write(&path, data)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn append_jsonl<T: Serialize>(name: &str, entry: &T) -> Result<()> {
+    let path = path(name);
+    create_parent(&path)?;
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
@@ -0,0 +1,168 @@
+// Streamed responses of the messages API
+//
+// With `stream: true` the API answers with server-sent events: message_start
+// carries the input token count, content_block_start and content_block_delta
+// build up text and tool_use blocks (tool input arrives as pieces of JSON),
+// and message_delta the output token count. read() puts them back together
+// into the AnthropicResponse a request without streaming would have returned.
+
+use anyhow::{Result, Context, anyhow};
+use reqwest::Response;
+use serde::Deserialize;
+use tracing::trace;
+
+use crate::redact;
+use crate::types::{AnthropicResponse, ContentBlock, Usage};
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    MessageStart { message: MessageStart },
+    ContentBlockStart { index: usize, content_block: BlockStart },
+    ContentBlockDelta { index: usize, delta: Delta },
+    MessageDelta { usage: OutputUsage },
+    Error { error: ApiError },
+    // ping, content_block_stop, message_stop
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockStart {
+    Text { text: String },
+    ToolUse { name: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Delta {
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    #[serde(rename = "input_json_delta")]
+    InputJson { partial_json: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct OutputUsage {
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+enum Block {
+    Text(String),
+    // Tool input as the JSON received so far
+    ToolUse { name: String, json: String },
+    Other,
+}
+
+#[derive(Default)]
+struct Message {
+    blocks: Vec<Block>,
+    usage: Usage,
+}
+
+impl Message {
+    // Apply an event; true if it added text
+    fn apply(&mut self, event: Event) -> Result<bool> {
+        match event {
+            Event::MessageStart { message } => self.usage = message.usage,
+            Event::ContentBlockStart { index, content_block } => {
+                let block = match content_block {
+                    BlockStart::Text { text } => Block::Text(text),
+                    BlockStart::ToolUse { name } => Block::ToolUse { name, json: String::new() },
+                    BlockStart::Other => Block::Other,
+                };
+                if index >= self.blocks.len() {
+                    self.blocks.resize_with(index + 1, || Block::Other);
+                }
+                self.blocks[index] = block;
+            },
+            Event::ContentBlockDelta { index, delta } => match (self.blocks.get_mut(index), delta) {
+                (Some(Block::Text(text)), Delta::Text { text: more }) => {
+                    text.push_str(&more);
+                    return Ok(true);
+                },
+                (Some(Block::ToolUse { json, .. }), Delta::InputJson { partial_json }) => json.push_str(&partial_json),
+                _ => {},
+            },
+            Event::MessageDelta { usage } => self.usage.output_tokens = usage.output_tokens,
+            Event::Error { error } => return Err(anyhow!("Anthropic API error while streaming: {}", error.message)),
+            Event::Other => {},
+        }
+        Ok(false)
+    }
+
+    // All text so far, joined like AnthropicResponse::text
+    fn text(&self) -> String {
+        self.blocks.iter()
+            .filter_map(|block| match block {
+                Block::Text(text) => Some(text.trim()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn finish(self) -> Result<AnthropicResponse> {
+        let content = self.blocks.into_iter()
+            .map(|block| Ok(match block {
+                Block::Text(text) => ContentBlock::Text { text },
+                Block::ToolUse { name, json } if json.is_empty() => ContentBlock::ToolUse { name, input: serde_json::json!({}) },
+                Block::ToolUse { name, json } => ContentBlock::ToolUse {
+                    input: serde_json::from_str(&json).with_context(|| format!("Failed to parse the input to {}", name))?,
+                    name,
+                },
+                Block::Other => ContentBlock::Other,
+            }))
+            .collect::<Result<_>>()?;
+        Ok(AnthropicResponse { content, usage: self.usage })
+    }
+}
+
+// The data lines of one event, joined
+fn data(event: &str) -> String {
+    event.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim_start)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Read a streamed response, calling on_text with all text so far whenever
+// more arrives
+pub async fn read(mut response: Response, mut on_text: impl FnMut(&str)) -> Result<AnthropicResponse> {
+    let mut buffer = Vec::new();
+    let mut message = Message::default();
+    while let Some(chunk) = response.chunk().await.context("Failed to read the streamed response")? {
+        buffer.extend_from_slice(&chunk);
+        // Events end with an empty line
+        while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            let data = data(&String::from_utf8_lossy(&event));
+            if data.is_empty() {
+                continue;
+            }
+            trace!("Streamed event: {}", redact::sensitive(&data));
+            let event = serde_json::from_str(&data).context("Failed to parse a streamed event")?;
+            if message.apply(event)? {
+                on_text(&message.text());
+            }
+        }
+    }
+    message.finish()
+}
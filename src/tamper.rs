@@ -0,0 +1,218 @@
+// Tamper-evident log of what happened to enforcement, in tamper.jsonl
+//
+// Every entry carries the SHA-256 of its predecessor's hash and its own
+// fields, so editing or removing entries breaks the chain. It records
+// starts, stops on SIGTERM or SIGINT, pauses, changes of the binary or the
+// config files, an hourly sign of life and everything logged to
+// events.jsonl, such as bypasses. `perimedes audit` verifies the chain and
+// shows when enforcement wasn't running.
+
+use anyhow::{Result, Context};
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::constants::TAMPER_HEARTBEAT_MINUTES;
+use crate::storage;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    time: String,
+    kind: String,
+    detail: String,
+    // Hash of the entry before, empty for the first
+    prev: String,
+    hash: String,
+}
+
+const TAMPER_FILE: &str = "tamper.jsonl";
+
+fn hash(seq: u64, time: &str, kind: &str, detail: &str, prev: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(seq.to_le_bytes())
+        .chain_update(prev)
+        .chain_update([0])
+        .chain_update(time)
+        .chain_update([0])
+        .chain_update(kind)
+        .chain_update([0])
+        .chain_update(detail)
+        .finalize();
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Append an entry; the daemon and the watchdog may both write, so the file
+// is locked while the last entry is read and the next one written
+pub fn record(kind: &str, detail: &str) -> Result<()> {
+    let path = storage::path(TAMPER_FILE);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).read(true).append(true).open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // SAFETY: the descriptor is open for the whole call, and closing it
+    // releases the lock
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {}", path.display()));
+    }
+
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+    let last = data.lines().rev().find_map(|line| serde_json::from_str::<Entry>(line).ok());
+    let (seq, prev) = last.map_or((0, String::new()), |last| (last.seq + 1, last.hash));
+
+    let time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let hash = hash(seq, &time, kind, detail, &prev);
+    let entry = Entry { seq, time, kind: kind.to_string(), detail: detail.to_string(), prev, hash };
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(())
+}
+
+fn record_or_complain(kind: &str, detail: &str) {
+    if let Err(e) = record(kind, detail) {
+        warn!("Failed to write the tamper log: {}", e);
+    }
+}
+
+// Fingerprint of the binary and the files in the config directory; the
+// binary by size and modification time, as reading it takes a while
+fn config_fingerprint() -> Result<String> {
+    let mut hasher = Sha256::new();
+    let exe = std::env::current_exe().context("Failed to find the perimedes binary")?;
+    let metadata = std::fs::metadata(&exe).with_context(|| format!("Failed to read {}", exe.display()))?;
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(format!("{:?}", metadata.modified()?));
+    hash_dir(&mut hasher, &storage::config_dir(), 2);
+    let digest = hasher.finalize();
+    Ok(digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn hash_dir(hasher: &mut Sha256, dir: &Path, depth: u32) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() && depth > 1 {
+            hash_dir(hasher, &path, depth - 1);
+        } else if let Ok(data) = std::fs::read(&path) {
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update(data);
+        }
+    }
+}
+
+fn load() -> Result<Vec<Entry>> {
+    storage::load_jsonl(TAMPER_FILE)
+}
+
+// Record the start, a changed configuration, signs of life and the stop
+pub fn start(profile: &str) {
+    match config_fingerprint() {
+        Ok(fingerprint) => {
+            let last = load().ok()
+                .and_then(|entries| entries.into_iter().rev().find(|entry| entry.kind == "config"))
+                .map(|entry| entry.detail);
+            if last.as_deref() != Some(fingerprint.as_str()) {
+                record_or_complain("config", &fingerprint);
+            }
+        },
+        Err(e) => warn!("Failed to fingerprint the configuration: {}", e),
+    }
+    record_or_complain("start", &format!("profile {}", profile));
+
+    tokio::spawn(async {
+        let mut tick = tokio::time::interval(Duration::from_secs(TAMPER_HEARTBEAT_MINUTES * 60));
+        // The first tick is right away, just after the start entry
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            record_or_complain("alive", "");
+        }
+    });
+
+    tokio::spawn(async {
+        let (Ok(mut terminate), Ok(mut interrupt)) = (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) else {
+            warn!("Failed to watch for SIGTERM, stops won't be logged");
+            return;
+        };
+        let name = tokio::select! {
+            _ = terminate.recv() => "SIGTERM",
+            _ = interrupt.recv() => "SIGINT",
+        };
+        info!("Stopping on {}", name);
+        record_or_complain("stop", name);
+        std::process::exit(0);
+    });
+}
+
+fn parse(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+fn minutes_between(from: &str, to: &str) -> i64 {
+    parse(from).zip(parse(to)).map_or(0, |(from, to)| (to - from).num_minutes())
+}
+
+// `perimedes audit`: verify the chain and print when enforcement was off
+pub fn audit() -> Result<()> {
+    let entries = load()?;
+    let Some(first) = entries.first() else {
+        println!("The tamper log is empty");
+        return Ok(());
+    };
+
+    let mut broken = 0;
+    if first.seq != 0 || !first.prev.is_empty() {
+        println!("BROKEN  the log starts at entry {}, earlier entries were removed", first.seq);
+        broken += 1;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        if hash(entry.seq, &entry.time, &entry.kind, &entry.detail, &entry.prev) != entry.hash {
+            println!("BROKEN  entry {} ({}) was edited", entry.seq, entry.time);
+            broken += 1;
+        }
+        if let Some(before) = i.checked_sub(1).map(|i| &entries[i]) {
+            if entry.prev != before.hash || entry.seq != before.seq + 1 {
+                println!("BROKEN  entries missing between {} ({}) and {} ({})",
+                         before.seq, before.time, entry.seq, entry.time);
+                broken += 1;
+            }
+        }
+    }
+    match broken {
+        0 => println!("Chain intact, {} entries since {}", entries.len(), first.time),
+        n => println!("Chain broken in {} places", n),
+    }
+
+    println!();
+    for (i, entry) in entries.iter().enumerate() {
+        let before = i.checked_sub(1).map(|i| &entries[i]);
+        match (entry.kind.as_str(), before) {
+            ("alive", _) => {},
+            ("start", Some(before)) if before.kind == "stop" => {
+                println!("{}  not running for {} min, stopped by {}",
+                         before.time, minutes_between(&before.time, &entry.time), before.detail);
+            },
+            ("start", Some(before)) => {
+                println!("{}  not running for up to {} min, no clean stop (killed or crashed)",
+                         before.time, minutes_between(&before.time, &entry.time));
+            },
+            ("start" | "stop", _) => {},
+            ("config", Some(_)) => println!("{}  binary or config files changed", entry.time),
+            ("config", None) => {},
+            (kind, _) => println!("{}  {} {}", entry.time, kind, entry.detail),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,209 @@
+// Telegram bot: messages an authorized chat when the screen locks and takes
+// remote commands from it:
+//   /status      - current state, last verdict and spend
+//   /unlock      - end the running lock
+//   /extend <n>  - add n minutes to the running timed lock
+
+use anyhow::{Result, Context, anyhow};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::constants::{MAX_LOCK_MINUTES, TELEGRAM_API_URL, TELEGRAM_CHAT_ID, TELEGRAM_POLL_SECS};
+use crate::ipc::{self, Event};
+use crate::redact;
+
+#[derive(Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<IncomingMessage>,
+}
+
+#[derive(Deserialize)]
+struct IncomingMessage {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+struct Bot {
+    client: Client,
+    token: String,
+    chat_id: i64,
+}
+
+impl Bot {
+    fn url(&self, method: &str) -> String {
+        format!("{}/bot{}/{}", TELEGRAM_API_URL, self.token, method)
+    }
+
+    async fn send(&self, text: &str) -> Result<()> {
+        self.client.post(self.url("sendMessage"))
+            .json(&json!({ "chat_id": self.chat_id, "text": text }))
+            .send().await
+            .context("Failed to send Telegram message")?
+            .error_for_status()
+            .context("Telegram rejected the message")?;
+        Ok(())
+    }
+
+    // Message with a row of buttons opening the links
+    async fn send_links(&self, text: &str, links: &[(&str, &str)]) -> Result<()> {
+        let buttons: Vec<_> = links.iter().map(|(label, url)| json!({ "text": label, "url": url })).collect();
+        self.client.post(self.url("sendMessage"))
+            .json(&json!({ "chat_id": self.chat_id, "text": text, "reply_markup": { "inline_keyboard": [buttons] } }))
+            .send().await
+            .context("Failed to send Telegram message")?
+            .error_for_status()
+            .context("Telegram rejected the message")?;
+        Ok(())
+    }
+
+    // Wait for new messages, long-polling for up to TELEGRAM_POLL_SECS
+    // Long-polls for up to `timeout` seconds
+    async fn updates(&self, offset: i64, timeout: u64) -> Result<Vec<Update>> {
+        let updates: Updates = self.client.get(self.url("getUpdates"))
+            .query(&[("offset", offset), ("timeout", timeout as i64)])
+            .timeout(Duration::from_secs(TELEGRAM_POLL_SECS + 10))
+            .send().await
+            .context("Failed to poll Telegram")?
+            .error_for_status()
+            .context("Telegram rejected the poll")?
+            .json().await
+            .context("Failed to parse Telegram updates")?;
+        Ok(updates.result)
+    }
+}
+
+fn bot() -> Option<Bot> {
+    let (Some(chat_id), Ok(token)) = (TELEGRAM_CHAT_ID, std::env::var("TELEGRAM_BOT_TOKEN")) else {
+        return None;
+    };
+    if token.is_empty() {
+        return None;
+    }
+    redact::register_secret(&token);
+    Some(Bot { client: Client::new(), token, chat_id })
+}
+
+// Start the bot if TELEGRAM_CHAT_ID and $TELEGRAM_BOT_TOKEN are set
+pub fn spawn() {
+    let Some(bot) = bot() else {
+        return;
+    };
+
+    let bot = Arc::new(bot);
+    tokio::spawn(announce_locks(bot.clone(), ipc::subscribe()));
+    tokio::spawn(serve_commands(bot));
+}
+
+// Send links as buttons to the chat, e.g. for approving an unlock on the phone
+pub async fn send_links(text: &str, links: &[(&str, &str)]) -> Result<()> {
+    let bot = bot().ok_or_else(|| anyhow!("The Telegram bot isn't configured"))?;
+    bot.send_links(text, links).await
+}
+
+async fn announce_locks(bot: Arc<Bot>, mut events: broadcast::Receiver<Event>) {
+    loop {
+        match events.recv().await {
+            Ok(Event::State { time, state, .. }) if state.starts_with("locked") => {
+                if let Err(e) = bot.send(&format!("{} perimedes {}", time, state)).await {
+                    warn!("{}", redact::scrub(&e.to_string()));
+                }
+            },
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {},
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn serve_commands(bot: Arc<Bot>) {
+    // Commands sent while the daemon was down are stale, e.g. an /unlock
+    // from yesterday. Offset -1 returns only the last update, and polling
+    // past it confirms the whole backlog.
+    let mut offset = loop {
+        match bot.updates(-1, 0).await {
+            Ok(updates) => break updates.last().map_or(0, |update| update.update_id + 1),
+            Err(e) => {
+                warn!("{}", redact::scrub(&e.to_string()));
+                tokio::time::sleep(Duration::from_secs(TELEGRAM_POLL_SECS)).await;
+            },
+        }
+    };
+    loop {
+        let updates = match bot.updates(offset, TELEGRAM_POLL_SECS).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("{}", redact::scrub(&e.to_string()));
+                tokio::time::sleep(Duration::from_secs(TELEGRAM_POLL_SECS)).await;
+                continue;
+            },
+        };
+
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+
+            // Only the configured chat may control the daemon
+            let Some(message) = update.message.filter(|message| message.chat.id == bot.chat_id) else {
+                continue;
+            };
+            let Some(text) = message.text else {
+                continue;
+            };
+
+            let reply = command(&text);
+            if let Err(e) = bot.send(&reply).await {
+                warn!("{}", redact::scrub(&e.to_string()));
+            }
+        }
+    }
+}
+
+fn command(text: &str) -> String {
+    let mut words = text.split_whitespace();
+    // Commands may be addressed to the bot, e.g. /status@my_bot
+    let name = words.next().unwrap_or("").split('@').next().unwrap_or("");
+
+    match (name, words.next()) {
+        ("/status", None) => {
+            let status = ipc::current_status();
+            let verdict = match status.last_verdict {
+                Some(true) => "PROCRASTINATING",
+                Some(false) => "NOT PROCRASTINATING",
+                None => "none yet",
+            };
+            format!(
+                "profile: {}\nstate: {}\nlast check: {} ({})\nspent today: ${:.4}",
+                status.profile, status.state,
+                status.last_check.as_deref().unwrap_or("never"), verdict,
+                status.spent_today_usd,
+            )
+        },
+        ("/unlock", None) => match ipc::unlock_remotely() {
+            true => "Unlocking".to_string(),
+            false => "The screen isn't locked".to_string(),
+        },
+        ("/extend", Some(minutes)) => match minutes.parse::<u64>() {
+            Ok(minutes) if minutes == 0 || minutes > MAX_LOCK_MINUTES => {
+                format!("Extensions are 1-{} minutes", MAX_LOCK_MINUTES)
+            },
+            Ok(minutes) if ipc::extend_lock(minutes) => format!("Extended the lock by {} minutes", minutes),
+            Ok(_) => "No timed lock is running".to_string(),
+            Err(_) => format!("Not a number of minutes: {}", minutes),
+        },
+        _ => "Commands: /status, /unlock, /extend <minutes>".to_string(),
+    }
+}
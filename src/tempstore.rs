@@ -0,0 +1,132 @@
+// Short-lived files of the capture pipeline: screenshots, OCR output and tiles
+//
+// They live in a private directory, on tmpfs where there is one, so screen
+// contents never hit the disk or other users. Artifacts are removed once
+// processed, the directory is kept under TEMP_MAX_MB, and files left behind
+// by a crash are swept at daemon start.
+
+use anyhow::{Result, Context, anyhow};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::constants::{TEMP_MAX_MB, TEMP_STALE_MINUTES};
+
+// Prefix of the captures older versions wrote straight into /tmp
+const LEGACY_PREFIX: &str = "perimedes_";
+
+fn uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+// The per-user runtime directory is tmpfs by spec, /dev/shm usually is
+fn location() -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("perimedes"),
+        _ if Path::new("/dev/shm").is_dir() => PathBuf::from(format!("/dev/shm/perimedes-{}", uid())),
+        _ => std::env::temp_dir().join(format!("perimedes-{}", uid())),
+    }
+}
+
+// The directory, created 0700 if needed; one we don't own is refused, as
+// another user could read or swap our captures
+pub fn dir() -> Result<PathBuf> {
+    let dir = location();
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {},
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+
+    let metadata = std::fs::symlink_metadata(&dir)
+        .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != uid() {
+        return Err(anyhow!("{} is not a directory of ours, not writing captures there", dir.display()));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    }
+    Ok(dir)
+}
+
+// Path for a new artifact, making room for it under the size cap first
+pub fn file(name: &str) -> Result<PathBuf> {
+    let dir = dir()?;
+    enforce_cap(&dir);
+    Ok(dir.join(name))
+}
+
+// Remove a processed capture and its OCR output; files elsewhere, such as
+// the fixtures of tests, are left alone
+pub fn release(path: &Path) {
+    if !path.starts_with(location()) {
+        return;
+    }
+    for path in [path.to_path_buf(), path.with_extension("txt")] {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Files of the directory, newest first, with their size and age
+fn entries(dir: &Path) -> Vec<(PathBuf, u64, Duration)> {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let now = SystemTime::now();
+    let mut entries: Vec<_> = read.flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+            let age = metadata.modified().ok().and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+            Some((entry.path(), metadata.len(), age))
+        })
+        .collect();
+    entries.sort_by_key(|(_, _, age)| *age);
+    entries
+}
+
+// Drop the oldest files once the directory outgrows TEMP_MAX_MB
+fn enforce_cap(dir: &Path) {
+    let mut total = 0;
+    for (path, size, _) in entries(dir) {
+        total += size;
+        if total > TEMP_MAX_MB * 1024 * 1024 {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+// Remove what earlier runs left behind: files older than TEMP_STALE_MINUTES,
+// and captures of ours in /tmp from before the private directory
+pub fn sweep() -> Result<()> {
+    let stale = Duration::from_secs(TEMP_STALE_MINUTES * 60);
+    let dir = dir()?;
+    let mut removed = 0;
+    for (path, _, age) in entries(&dir) {
+        if age > stale && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+
+    for (path, _, _) in entries(Path::new("/tmp")) {
+        let legacy = path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(LEGACY_PREFIX) && (name.ends_with(".png") || name.ends_with(".txt")));
+        let ours = std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.uid() == uid());
+        if legacy && ours && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        info!("Removed {} stale capture files", removed);
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+// Incremental OCR: the capture is split into a grid of tiles and only tiles
+// whose pixels changed are OCRed, the rest come from the OCR cache
+
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::constants::OCR_TILE_GRID;
+use crate::dedup;
+use crate::ocr_cache;
+
+// Text of the capture, tile by tile in reading order, OCRing tiles with `ocr`
+pub async fn ocr(path: &Path, ocr: impl AsyncFn(&Path) -> Result<String>) -> Result<String> {
+    let (columns, rows) = OCR_TILE_GRID;
+    if columns * rows <= 1 {
+        return ocr(path).await;
+    }
+
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load {}", path.display()))?
+        .into_rgb8();
+    let (width, height) = image.dimensions();
+    let (tile_width, tile_height) = (width.div_ceil(columns), height.div_ceil(rows));
+
+    let mut texts = Vec::new();
+    let mut fresh = 0;
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * tile_width, row * tile_height);
+            if x >= width || y >= height {
+                continue;
+            }
+            let tile = image::imageops::crop_imm(&image, x, y, tile_width.min(width - x), tile_height.min(height - y))
+                .to_image();
+
+            let hash = dedup::pixels_hash(&tile);
+            let text = match ocr_cache::lookup(&hash) {
+                Some(text) => text,
+                None => {
+                    let tile_path = tile_path(path, row, column);
+                    tile.save(&tile_path)
+                        .with_context(|| format!("Failed to write {}", tile_path.display()))?;
+                    let text = ocr(&tile_path).await;
+                    let _ = std::fs::remove_file(&tile_path);
+                    let _ = std::fs::remove_file(tile_path.with_extension("txt"));
+
+                    let text = text?;
+                    ocr_cache::store(hash, text.clone());
+                    fresh += 1;
+                    text
+                },
+            };
+
+            if !text.trim().is_empty() {
+                texts.push(text.trim_end().to_string());
+            }
+        }
+    }
+
+    info!("OCRed {}/{} tiles", fresh, columns * rows);
+    Ok(texts.join("\n"))
+}
+
+fn tile_path(path: &Path, row: u32, column: u32) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("capture");
+    path.with_file_name(format!("{}_tile_{}_{}.png", stem, row, column))
+}
@@ -0,0 +1,102 @@
+// Focused window and its workspace from i3 or sway, over their IPC socket
+//
+// Both put the socket's path into $I3SOCK or $SWAYSOCK. A message is the
+// magic "i3-ipc", the payload's length and the message type as native-endian
+// u32s, then the JSON payload; replies look the same. GET_TREE returns the
+// layout tree, in which the focused container has "focused": true and sits
+// below its workspace. Sway's Wayland windows have an app_id instead of an
+// X11 class, and don't show up over X11 at all.
+
+use anyhow::{Result, Context, anyhow};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::focus::ActiveWindow;
+use crate::redact;
+
+const MAGIC: &[u8] = b"i3-ipc";
+const GET_TREE: u32 = 4;
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+    #[serde(default)]
+    focused: bool,
+    app_id: Option<String>,
+    window_properties: Option<WindowProperties>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+fn socket_path() -> Option<PathBuf> {
+    ["I3SOCK", "SWAYSOCK"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+fn request(path: &Path, kind: u32, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to {}", path.display()))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut message = MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)?;
+
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(anyhow!("Unexpected reply from the window manager"));
+    }
+    let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    let mut reply = vec![0; length];
+    stream.read_exact(&mut reply)?;
+    Ok(reply)
+}
+
+// The focused node and the name of the workspace it's on
+fn find<'a>(node: &'a Node, workspace: Option<&'a str>) -> Option<(&'a Node, Option<&'a str>)> {
+    let workspace = if node.kind == "workspace" { node.name.as_deref() } else { workspace };
+    if node.focused {
+        return Some((node, workspace));
+    }
+    node.nodes.iter().chain(&node.floating_nodes).find_map(|child| find(child, workspace))
+}
+
+// The focused window under i3 or sway; None under other window managers.
+// An empty workspace in focus has neither class nor title.
+pub fn focused() -> Option<Result<ActiveWindow>> {
+    let path = socket_path()?;
+    Some(request(&path, GET_TREE, b"").and_then(|reply| {
+        let tree: Node = serde_json::from_slice(&reply).context("Unexpected layout tree")?;
+        let (node, workspace) = find(&tree, None).ok_or_else(|| anyhow!("Nothing is focused"))?;
+        let window = node.kind != "workspace";
+        let class = node.app_id.clone()
+            .or_else(|| node.window_properties.as_ref().and_then(|properties| properties.class.clone()))
+            .filter(|_| window)
+            .unwrap_or_default();
+        let title = node.name.as_deref().filter(|_| window).unwrap_or_default();
+        Ok(ActiveWindow {
+            class,
+            title: redact::ocr(title),
+            workspace: workspace.map(str::to_string),
+        })
+    }))
+}
@@ -1,19 +1,75 @@
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
 use std::sync::Arc;
+use std::sync::mpsc;
 use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Instant;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
 use x11rb::protocol::Event;
+use tracing::warn;
 
 // Import constants and window utilities
-use crate::constants::{BG_COLOR, TEXT_COLOR, FONT_NAME};
+use crate::constants::THEME;
+use crate::deadline::{ActiveLock, Deadline};
+use crate::emergency;
+use crate::grab::{self, GrabGuard};
+use crate::handoff;
+use crate::ipc;
 use crate::window;
 
+// What the timer loop is told about by the X event reader
+enum Input {
+    // The emergency chord; the reader waits until the prompt is over
+    Chord,
+    Expose(ExposeEvent),
+    Motion,
+}
+
+// When the displayed seconds change next
+fn next_second(deadline: &Deadline) -> Instant {
+    let fraction = deadline.remaining().subsec_nanos();
+    let wait = if fraction == 0 { Duration::from_secs(1) } else { Duration::from_nanos(fraction as u64) };
+    Instant::now() + wait
+}
+
+// Forward the timer window's events until the timer wakes it with a client
+// message. The emergency prompt reads events itself, so after a chord this
+// waits for the prompt to end.
+fn read_events(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    win: Window,
+    input: UnboundedSender<Input>,
+    paused: mpsc::Receiver<()>,
+) -> Result<()> {
+    loop {
+        let event = conn.wait_for_event()?;
+        grab::reassert(conn, &event)?;
+        let forwarded = match event {
+            Event::ClientMessage(message) if message.window == win => return Ok(()),
+            Event::KeyPress(key) if emergency::is_chord(conn, &key)? => {
+                if input.send(Input::Chord).is_err() || paused.recv().is_err() {
+                    return Ok(());
+                }
+                continue;
+            },
+            Event::Expose(expose) => input.send(Input::Expose(expose)),
+            Event::MotionNotify(_) => input.send(Input::Motion),
+            _ => Ok(()),
+        };
+        if forwarded.is_err() {
+            return Ok(());
+        }
+    }
+}
+
 // Function to display a X11 lock timer window
 // Using RustConnection directly since that's what x11rb::connect returns
+// An optional label (e.g. the reason for a self-lock) is shown below the countdown.
+// Locks handed over from another machine run until its deadline and aren't
+// published again.
 pub async fn display_lock_timer(
-    minutes: u64,
-    grab_func: fn(&Arc<x11rb::rust_connection::RustConnection>, &Screen) -> Result<()>
+    mut lock: ActiveLock,
 ) -> Result<()> {
     // Connect to the X server
     let (conn, screen_num) = x11rb::connect(None)
@@ -25,9 +81,10 @@ pub async fn display_lock_timer(
     // Create a fullscreen timer window
     let win = conn.generate_id()?;
     let values = CreateWindowAux::new()
-        .background_pixel(BG_COLOR)
+        .background_pixel(THEME.background)
         .override_redirect(1)
-        .event_mask(EventMask::EXPOSURE | EventMask::KEY_PRESS);
+        .event_mask(EventMask::EXPOSURE | EventMask::KEY_PRESS | EventMask::POINTER_MOTION
+            | EventMask::VISIBILITY_CHANGE | EventMask::FOCUS_CHANGE);
 
     conn.create_window(
         screen.root_depth,
@@ -41,82 +98,170 @@ pub async fn display_lock_timer(
         &values,
     )?;
 
+    window::set_class(&conn, win)?;
+
     // Create invisible cursor
     let cursor = window::create_invisible_cursor(&conn, win)?;
     let values = ChangeWindowAttributesAux::new().cursor(cursor);
     conn.change_window_attributes(win, &values)?;
 
     // Load font
-    let font = conn.generate_id()?;
-    conn.open_font(font, FONT_NAME.as_bytes())?;
+    let font = window::load_text_font(&conn, screen);
 
     // Create graphics context
     let gc = conn.generate_id()?;
     let gc_aux = CreateGCAux::new()
-        .foreground(TEXT_COLOR)
-        .background(BG_COLOR)
-        .font(font);
+        .foreground(THEME.text)
+        .background(THEME.background)
+        .font(font.core_font());
     conn.create_gc(gc, win, &gc_aux)?;
 
-    // Grab keyboard and mouse
-    grab_func(&conn, screen)?;
-
-    // Map the window
+    // Map the window before grabbing, so the pointer can be confined to it
     conn.map_window(win)?;
     conn.flush()?;
 
-    // Initialize timer
-    let start_time = std::time::Instant::now();
-    let lock_duration = Duration::from_secs(minutes * 60);
+    // Grab keyboard and mouse; the guard releases them however this ends
+    let guard = GrabGuard::new(&conn, screen, vec![win], cursor);
+    grab::grab_keyboard_and_mouse(&conn, screen, win, cursor).await?;
+
+    // The deadline keeps running during suspend, and the lock through a
+    // restart
+    let label = lock.label.clone();
+    let label = label.as_deref();
+    if let Err(e) = lock.save() {
+        warn!("Failed to save the lock deadline: {}", e);
+    }
+    if !lock.handed_over {
+        handoff::publish(&lock.deadline, label).await;
+    }
+
+    // Frames are drawn into a back buffer and copied to the window in one
+    // go, so the countdown doesn't flicker
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let buffer = conn.generate_id()?;
+    conn.create_pixmap(screen.root_depth, buffer, win, width, height)?;
+    let clear_gc = conn.generate_id()?;
+    conn.create_gc(clear_gc, win, &CreateGCAux::new().foreground(THEME.background))?;
+
+    // X events are read on a blocking task; a frame is only drawn when the
+    // displayed seconds change, on ticks aligned with the deadline
+    let (input_tx, mut input) = tokio::sync::mpsc::unbounded_channel();
+    let (resume, paused) = std::sync::mpsc::channel();
+    let reader = tokio::task::spawn_blocking({
+        let conn = conn.clone();
+        move || read_events(&conn, win, input_tx, paused)
+    });
+    // The first tick draws right away
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut shown: Option<String> = None;
 
     // Timer loop
-    let mut running = true;
-    while running {
-        // Check for keyboard events (any key exits the timer)
-        while let Ok(Some(event)) = conn.poll_for_event() {
-            match event {
-                Event::KeyPress(_) => {
-                    // Ignore key presses - timer must complete
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {},
+            next = input.recv() => match next {
+                // Other key presses are ignored - timer must complete
+                Some(Input::Chord) => {
+                    let unlocked = emergency::prompt(&conn, win, gc, &font, screen).await;
+                    let _ = resume.send(());
+                    if unlocked? {
+                        break;
+                    }
+                    // The prompt drew over the window
+                    shown = None;
                 },
-                Event::Expose(_) => {
-                    // Redraw on expose
+                Some(Input::Expose(expose)) => {
+                    if shown.is_some() {
+                        conn.copy_area(
+                            buffer, win, gc,
+                            expose.x as i16, expose.y as i16, expose.x as i16, expose.y as i16,
+                            expose.width, expose.height,
+                        )?;
+                        conn.flush()?;
+                    }
+                    continue;
                 },
-                _ => {}
-            }
+                Some(Input::Motion) => {
+                    window::recenter_pointer(&conn, win, screen)?;
+                    continue;
+                },
+                None => return Err(reader.await?.err().unwrap_or_else(|| anyhow!("X event reader stopped"))),
+            },
         }
 
-        // Update timer display
-        let elapsed = start_time.elapsed();
-        if elapsed >= lock_duration {
-            running = false;
-        } else {
-            let remaining = lock_duration - elapsed;
-            let remaining_minutes = remaining.as_secs() / 60;
-            let remaining_seconds = remaining.as_secs() % 60;
+        // Remote control, e.g. over Telegram
+        if ipc::take_unlock() {
+            break;
+        }
+        let extension = ipc::take_extension();
+        if extension > 0 {
+            lock.deadline.extend(Duration::from_secs(extension.saturating_mul(60)));
+            if let Err(e) = lock.save() {
+                warn!("Failed to save the lock deadline: {}", e);
+            }
+            if !lock.handed_over {
+                handoff::publish(&lock.deadline, label).await;
+            }
+        }
 
-            // Clear window
-            conn.clear_area(true, win, 0, 0, 0, 0)?;
+        let remaining = lock.deadline.remaining();
+        if remaining.is_zero() {
+            break;
+        }
+        // Suspend and extensions move the second boundaries
+        tick.reset_at(next_second(&lock.deadline));
 
-            // Calculate center positions
-            let center_x = screen.width_in_pixels as i16 / 2 - 100; // Approximate text width offset
-            let center_y = screen.height_in_pixels as i16 / 2;
+        // Round up, so 0:00 is never shown while locked
+        let seconds = remaining.as_millis().div_ceil(1000) as u64;
+        let countdown_text = format!("{}:{:02}", seconds / 60, seconds % 60);
+        if shown.as_deref() == Some(countdown_text.as_str()) {
+            continue;
+        }
 
-            // Draw centered timer text
-            let countdown_text = format!("{}:{:02}", remaining_minutes, remaining_seconds);
+        // Calculate center positions
+        let center_x = width as i16 / 2 - 100; // Approximate text width offset
+        let center_y = height as i16 / 2;
 
-            // Draw text centered on screen
-            window::draw_text(&conn, win, gc, &countdown_text, center_x, center_y - 20, TEXT_COLOR)?;
-            conn.flush()?;
+        conn.poly_fill_rectangle(buffer, clear_gc, &[Rectangle { x: 0, y: 0, width, height }])?;
+        // Lines two line heights apart
+        let line = 2 * THEME.line_height;
+        window::draw_text(&conn, buffer, gc, &font, &countdown_text, center_x, center_y - line / 2, THEME.text)?;
+        if let Some(label) = label {
+            window::draw_text(&conn, buffer, gc, &font, label, center_x, center_y + line / 2, THEME.text)?;
         }
-
-        // Sleep briefly
-        std::thread::sleep(Duration::from_millis(100));
+        if let Some(snippet) = &lock.snippet {
+            window::draw_text(&conn, buffer, gc, &font, snippet, center_x, center_y + line * 3 / 2, THEME.text)?;
+        }
+        let hint_y = height as i16 - THEME.margin - 5;
+        window::draw_text(&conn, buffer, gc, &font, &emergency::hint(), THEME.margin, hint_y, THEME.text)?;
+        conn.copy_area(buffer, win, gc, 0, 0, 0, 0, width, height)?;
+        conn.flush()?;
+        shown = Some(countdown_text);
     }
 
-    // Close the window
-    conn.unmap_window(win)?;
-    conn.destroy_window(win)?;
+    // Wake the reader, so it sees the timer is over
+    drop(input);
+    drop(resume);
+    let wake = ClientMessageEvent::new(32, win, AtomEnum::NONE, [0u32; 5]);
+    conn.send_event(false, win, EventMask::NO_EVENT, wake)?;
     conn.flush()?;
+    if let Err(e) = reader.await? {
+        warn!("Failed to read X events: {}", e);
+    }
+
+    conn.free_gc(clear_gc)?;
+    conn.free_pixmap(buffer)?;
+
+    if let Err(e) = Deadline::clear() {
+        warn!("Failed to clear the lock deadline: {}", e);
+    }
+    if !lock.handed_over {
+        handoff::clear().await;
+    }
+
+    // Ungrab and close the window
+    drop(guard);
 
     Ok(())
 }
@@ -0,0 +1,157 @@
+// Lock chats that ended in a decision by the judge, kept in transcripts.jsonl
+// and exported with `perimedes export` as evaluation or fine-tuning data
+//
+// The export is one conversation per line in the common chat format: a
+// system message, the user and assistant turns, and the decision as a call
+// of the decision tool. The metadata holds the outcome and, if the verdict
+// that led to the lock was labeled since, whether it was right.
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::constants::{DECISION_TOOL, SAVE_TRANSCRIPTS};
+use crate::lockscreen::SCREEN_CONTEXT_INTRO;
+use crate::events;
+use crate::ipc;
+use crate::redact;
+use crate::storage;
+use crate::types::{LockResult, Message, Persona, Profile};
+use crate::verdicts;
+
+#[derive(Serialize, Deserialize)]
+pub struct Transcript {
+    #[serde(default)]
+    pub id: String,
+    // Id of the lock the chat was in
+    #[serde(default)]
+    pub lock: Option<String>,
+    pub time: String,
+    pub profile: String,
+    pub persona: String,
+    pub model: String,
+    // The persona prompt first, then the chat
+    pub messages: Vec<Message>,
+    // Input of the decision tool
+    pub decision: serde_json::Value,
+}
+
+const TRANSCRIPTS_FILE: &str = "transcripts.jsonl";
+const JUDGE_SNIPPET_CHARS: usize = 100;
+
+pub fn record(profile: &Profile, persona: &Persona, system: &str, messages: &[Message], result: &LockResult) -> Result<()> {
+    if !SAVE_TRANSCRIPTS {
+        return Ok(());
+    }
+
+    let transcript = Transcript {
+        id: events::new_id(),
+        lock: ipc::current_status().lock,
+        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        profile: profile.name.to_string(),
+        persona: persona.name.to_string(),
+        model: persona.model.unwrap_or(profile.judge_model).to_string(),
+        messages: std::iter::once(Message { role: "system".to_string(), content: redact::scrub(system) })
+            .chain(messages.iter().map(|message| Message { role: message.role.clone(), content: redact::scrub(&message.content) }))
+            .collect(),
+        decision: decision_input(result),
+    };
+    storage::append_jsonl(TRANSCRIPTS_FILE, &transcript)
+}
+
+// The tool input that parse_decision turns into this result
+fn decision_input(result: &LockResult) -> serde_json::Value {
+    match result {
+        LockResult::Unlocked => json!({ "action": "unlock" }),
+        LockResult::UnlockedFor { minutes, purpose } => {
+            json!({ "action": "unlock_for", "minutes": minutes, "purpose": purpose })
+        },
+        LockResult::TimedLock(minutes) => json!({ "action": "lock", "minutes": minutes }),
+    }
+}
+
+// What the user wrote in the profile's last lock chat, if it ended at or
+// after `since`, for holding them to it
+pub fn last_said(profile: &str, since: &str) -> Result<Option<String>> {
+    let transcripts: Vec<Transcript> = storage::load_jsonl(TRANSCRIPTS_FILE)?;
+    let Some(transcript) = transcripts.iter().rev().find(|t| t.profile == profile) else {
+        return Ok(None);
+    };
+    if transcript.time.as_str() < since {
+        return Ok(None);
+    }
+
+    let said: Vec<&str> = transcript.messages.iter()
+        .filter(|message| message.role == "user" && !message.content.starts_with(SCREEN_CONTEXT_INTRO))
+        .map(|message| message.content.trim())
+        .filter(|content| !content.is_empty())
+        .collect();
+    Ok((!said.is_empty()).then(|| said.join(" / ")))
+}
+
+// The judge's last reply in the chat of the given lock, shortened to a line
+pub fn judge_said(lock: &str) -> Result<Option<String>> {
+    let transcripts: Vec<Transcript> = storage::load_jsonl(TRANSCRIPTS_FILE)?;
+    let Some(transcript) = transcripts.iter().rev().find(|t| t.lock.as_deref() == Some(lock)) else {
+        return Ok(None);
+    };
+    Ok(transcript.messages.iter()
+        .rev()
+        .find(|message| message.role == "assistant" && !message.content.trim().is_empty())
+        .map(|message| {
+            let line = message.content.split_whitespace().collect::<Vec<_>>().join(" ");
+            match line.char_indices().nth(JUDGE_SNIPPET_CHARS) {
+                Some((end, _)) => format!("{}...", &line[..end]),
+                None => line,
+            }
+        }))
+}
+
+// Print every transcript as a JSONL chat example
+pub fn export_judge() -> Result<()> {
+    let transcripts: Vec<Transcript> = storage::load_jsonl(TRANSCRIPTS_FILE)?;
+    let verdicts = verdicts::load()?;
+
+    for transcript in transcripts {
+        // The positive verdict that led to the lock: no checks run during one
+        let verdict = verdicts.iter()
+            .rev()
+            .find(|v| v.profile == transcript.profile && v.procrastinating && v.time <= transcript.time);
+
+        let mut messages: Vec<serde_json::Value> = transcript.messages.iter()
+            .filter(|message| !message.content.trim().is_empty())
+            .enumerate()
+            .map(|(i, message)| {
+                // The persona prompt goes first, an assistant turn in older transcripts
+                let role = if i == 0 { "system" } else { message.role.as_str() };
+                json!({ "role": role, "content": redact::scrub(&message.content) })
+            })
+            .collect();
+        messages.push(json!({
+            "role": "assistant",
+            "tool_calls": [{
+                "type": "function",
+                "function": { "name": DECISION_TOOL, "arguments": transcript.decision.to_string() },
+            }],
+        }));
+
+        let example = json!({
+            "messages": messages,
+            "metadata": {
+                "id": transcript.id,
+                "lock": transcript.lock,
+                "time": transcript.time,
+                "profile": transcript.profile,
+                "persona": transcript.persona,
+                "model": transcript.model,
+                "decision": transcript.decision,
+                "verdict_id": verdict.map(|v| v.id.clone()),
+                "verdict_time": verdict.map(|v| v.time.clone()),
+                "verdict_correct": verdict.and_then(|v| v.correct),
+            },
+        });
+        println!("{}", example);
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// Trust earned by staying on track: the longer since the last offense, the
+// less perimedes watches
+//
+// Offenses are locks triggered by a verdict, emergency bypasses and
+// circumvention attempts. Each TRUST_LEVELS entry applies once the user has
+// gone that many days without one, counted from the first verdict; an
+// offense drops back to full monitoring.
+
+use anyhow::Result;
+use chrono::{Local, NaiveDateTime};
+use tracing::warn;
+
+use crate::circumvention;
+use crate::constants::TRUST_LEVELS;
+use crate::events;
+use crate::locks;
+use crate::types::TrustLevel;
+use crate::verdicts;
+
+pub struct Trust {
+    pub clean_days: u64,
+    // The highest level reached, if any
+    pub level: Option<&'static TrustLevel>,
+}
+
+fn parse(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+pub fn current() -> Result<Trust> {
+    let first_verdict = verdicts::load()?.first().and_then(|verdict| parse(&verdict.time));
+    let last_lock = locks::load()?.into_iter()
+        .rev()
+        .find(|lock| lock.verdict.is_some())
+        .and_then(|lock| parse(&lock.time));
+    let last_bypass = events::load()?.into_iter()
+        .rev()
+        .find(|event| event.kind == "emergency_bypass" || event.kind == circumvention::EVENT_KIND)
+        .and_then(|event| parse(&event.time));
+
+    let since = first_verdict.max(last_lock).max(last_bypass);
+    let clean_days = since.map_or(0, |since| (Local::now().naive_local() - since).num_days().max(0) as u64);
+    let level = TRUST_LEVELS.iter()
+        .filter(|level| clean_days >= level.clean_days)
+        .max_by_key(|level| level.clean_days);
+    Ok(Trust { clean_days, level })
+}
+
+// Full monitoring if the history can't be read
+pub fn current_or_none() -> Trust {
+    current().unwrap_or_else(|e| {
+        warn!("Failed to compute trust: {}", e);
+        Trust { clean_days: 0, level: None }
+    })
+}
+
+impl Trust {
+    pub fn interval_factor(&self) -> u64 {
+        self.level.map_or(1, |level| level.interval_factor.max(1))
+    }
+
+    // Send window titles only, not the text on the screen
+    pub fn metadata_only(&self) -> bool {
+        self.level.is_some_and(|level| level.metadata_only)
+    }
+
+    pub fn extra_pauses(&self) -> u32 {
+        self.level.map_or(0, |level| level.extra_pauses)
+    }
+
+    pub fn describe(&self) -> String {
+        let Some(level) = self.level else {
+            return format!("{} days without an offense, full monitoring", self.clean_days);
+        };
+        format!("{} days without an offense: checks {}x less often{}, {} extra pauses",
+                self.clean_days, self.interval_factor(),
+                if level.metadata_only { ", window titles only" } else { "" },
+                level.extra_pauses)
+    }
+}
@@ -1,20 +1,117 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Local};
 
+use crate::focus::ActiveWindow;
+
 // Types shared across multiple modules
 
-// API request structure
+// API request structure; messages with images are VisionMessages
 #[derive(Serialize)]
-pub struct AnthropicRequest {
+pub struct AnthropicRequest<M = Message> {
     pub model: String,
-    pub messages: Vec<Message>,
+    // Instructions for the model, apart from the chat
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<InputBlock>,
+    pub messages: Vec<M>,
     pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    // Answer with server-sent events, see stream
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+}
+
+// Tool the model can call, described by a JSON schema for its input
+#[derive(Serialize)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
 }
 
 // API response structure
 #[derive(Deserialize)]
 pub struct AnthropicResponse {
     pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+impl AnthropicResponse {
+    // All text blocks of the response, joined
+    pub fn text(&self) -> String {
+        self.content.iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.trim()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Input of the first call to the named tool, if the model made one
+    pub fn tool_input(&self, tool: &str) -> Option<&serde_json::Value> {
+        self.content.iter().find_map(|block| match block {
+            ContentBlock::ToolUse { name, input } if name == tool => Some(input),
+            _ => None,
+        })
+    }
+}
+
+// Token counts reported by the API for a single request
+#[derive(Deserialize, Default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // Input tokens written to and read from the prompt cache, on top of
+    // input_tokens
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    pub fn all_input_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_creation_input_tokens + self.cache_read_input_tokens
+    }
+}
+
+// Models and spending limit for one usage context (e.g. "evening", "deep-work")
+pub struct Profile {
+    pub name: &'static str,
+    pub classify_model: &'static str,
+    // With a model here, the classifier only screens the latest capture and
+    // positive verdicts are confirmed by this model on the full context
+    pub confirm_model: Option<&'static str>,
+    pub judge_model: &'static str,
+    pub daily_budget_usd: f64,
+}
+
+// Character the lock screen judge plays, see PERSONAS
+pub struct Persona {
+    pub name: &'static str,
+    // Who the judge is; "{journal}" is replaced by excerpts of `journal`.
+    // The rules for deciding are appended.
+    pub prompt: &'static str,
+    // Overrides the profile's judge model
+    pub model: Option<&'static str>,
+    pub temperature: Option<f32>,
+    // Text file of journal entries, paragraphs separated by blank lines
+    pub journal: Option<&'static str>,
+}
+
+// How the judge persona is chosen for each lock
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum PersonaSelection {
+    // Always the persona with this name
+    Fixed(&'static str),
+    // The next persona for every lock
+    Rotate,
+    // Picked on the lock screen before the chat
+    Pick,
 }
 
 // Chat message types
@@ -26,31 +123,494 @@ pub enum ChatMessage {
     Decision(String),
 }
 
+// Where the lock chat's input field sits
+#[allow(dead_code)] // Variants are picked in constants.rs
+#[derive(Clone, Copy, PartialEq)]
+pub enum InputPosition {
+    // Below the messages, which grow upwards from it
+    Bottom,
+    // Above the messages
+    Top,
+}
+
+// Colors, text and layout of the lock screen and the lock timer
+pub struct Theme {
+    pub background: u32,
+    pub text: u32,
+    // Per message type on the lock screen
+    pub system: u32,
+    pub user: u32,
+    pub assistant: u32,
+    pub decision: u32,
+    pub font_size: f32, // Pixels
+    pub margin: i16,
+    pub line_height: i16,
+    // Put before each message of the type; decisions are framed by both
+    pub system_prefix: &'static str,
+    pub user_prefix: &'static str,
+    pub assistant_prefix: &'static str,
+    pub decision_prefix: &'static str,
+    pub decision_suffix: &'static str,
+    pub input_prefix: &'static str,
+    pub input_position: InputPosition,
+}
+
+impl Theme {
+    // Gruvbox dark
+    pub const DARK: Theme = Theme {
+        background: 0x282828,
+        text: 0xebdbb2,
+        system: 0xfabd2f,
+        user: 0x83a598,
+        assistant: 0xb8bb26,
+        decision: 0xebdbb2,
+        font_size: 18.0,
+        margin: 20,
+        line_height: 20,
+        system_prefix: "System: ",
+        user_prefix: "You: ",
+        assistant_prefix: "Claude: ",
+        decision_prefix: "=== ",
+        decision_suffix: " ===",
+        input_prefix: "Input: ",
+        input_position: InputPosition::Bottom,
+    };
+
+    // Gruvbox light, for bright rooms
+    pub const LIGHT: Theme = Theme {
+        background: 0xfbf1c7,
+        text: 0x3c3836,
+        system: 0xb57614,
+        user: 0x076678,
+        assistant: 0x79740e,
+        decision: 0x3c3836,
+        ..Theme::DARK
+    };
+}
+
 // Message struct for API calls
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
 
+// Message whose content mixes images and text
+#[derive(Serialize)]
+pub struct VisionMessage {
+    pub role: String,
+    pub content: Vec<InputBlock>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputBlock {
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image { source: ImageSource },
+}
+
+// Marks the end of a prefix for the API to cache, see PROMPT_CACHING
+#[derive(Serialize, Clone, Copy)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+impl CacheControl {
+    pub const EPHEMERAL: CacheControl = CacheControl { kind: "ephemeral" };
+}
+
+// A base64-encoded image
+#[derive(Serialize)]
+pub struct ImageSource {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub media_type: String,
+    pub data: String,
+}
+
+// Why the screen was locked, as named by the classifier or a rule
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockReason {
+    SocialMedia,
+    Video,
+    News,
+    Shopping,
+    Unclear,
+}
+
+impl LockReason {
+    pub const ALL: [LockReason; 5] = [
+        LockReason::SocialMedia, LockReason::Video, LockReason::News, LockReason::Shopping, LockReason::Unclear,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LockReason::SocialMedia => "social-media",
+            LockReason::Video => "video",
+            LockReason::News => "news",
+            LockReason::Shopping => "shopping",
+            LockReason::Unclear => "unclear",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<LockReason> {
+        LockReason::ALL.into_iter().find(|reason| reason.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+// What the screen showed, as named by the classifier with every verdict
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    SocialMedia,
+    Video,
+    News,
+    Shopping,
+    Reading,
+    Messaging,
+    Work,
+    Other,
+}
+
+impl Category {
+    pub const ALL: [Category; 8] = [
+        Category::SocialMedia, Category::Video, Category::News, Category::Shopping,
+        Category::Reading, Category::Messaging, Category::Work, Category::Other,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::SocialMedia => "social-media",
+            Category::Video => "video",
+            Category::News => "news",
+            Category::Shopping => "shopping",
+            Category::Reading => "reading",
+            Category::Messaging => "messaging",
+            Category::Work => "work",
+            Category::Other => "other",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Category> {
+        Category::ALL.into_iter().find(|category| category.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    // The reason given for a lock over this category
+    pub fn reason(self) -> LockReason {
+        match self {
+            Category::SocialMedia => LockReason::SocialMedia,
+            Category::Video => LockReason::Video,
+            Category::News => LockReason::News,
+            Category::Shopping => LockReason::Shopping,
+            _ => LockReason::Unclear,
+        }
+    }
+}
+
+// Which step of a check decided its verdict
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    // RULES or a policy script or module
+    Policy,
+    // OFFLINE_DISTRACTIONS, past the monthly budget
+    Local,
+    // The profile's classify_model
+    Classifier,
+    // The profile's confirm_model
+    Confirm,
+}
+
+// What becomes of verdicts in a category, see CATEGORY_POLICIES
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum CategoryPolicy {
+    // Never procrastinating
+    Allow,
+    // Not procrastinating for this many minutes a day, then up to the classifier
+    Minutes(u64),
+}
+
+// Local rule in RULES, matching when all of its regexes do
+pub struct Rule {
+    // Over the focused window's class, title and workspace, the browser
+    // tab's URL and the latest capture's text
+    pub class: Option<&'static str>,
+    pub title: Option<&'static str>,
+    pub workspace: Option<&'static str>,
+    pub url: Option<&'static str>,
+    pub text: Option<&'static str>,
+    pub action: RuleAction,
+}
+
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum RuleAction {
+    // Not procrastinating
+    Allow,
+    // Procrastinating, for this reason
+    Deny(LockReason),
+    // Added up over the matching rules, positive towards procrastinating
+    Weight(i32),
+}
+
+// What the daemon does outside the focus hours of SCHEDULE
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum OutsideSchedule {
+    // No captures or checks
+    Idle,
+    // Verdicts are recorded, but nothing is enforced
+    LogOnly,
+}
+
+// What the daemon does during a meeting from CALENDARS
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum MeetingMode {
+    // Verdicts are recorded, but nothing is enforced
+    Suppress,
+    // The classifier is told about the meeting, see MEETING_PROMPT
+    Relax,
+}
+
+// How many PROCRASTINATING verdicts it takes to trigger a lock
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum LockTrigger {
+    // The last n verdicts were all positive
+    Consecutive(usize),
+    // At least `needed` of the last `window` verdicts were positive
+    Majority { window: usize, needed: usize },
+}
+
+// Something done about a PROCRASTINATING verdict, see ENFORCEMENT
+#[allow(dead_code)] // Variants are picked in constants.rs
+#[derive(Clone, Copy)]
+pub enum Action {
+    // Desktop notification
+    Notify,
+    // Warning overlay for GRACE_PERIOD_SECS, which can be contested
+    Nudge,
+    // Translucent banner on the monitor in use for BANNER_SECS, see overlay.rs
+    Banner,
+    // Pause media players over MPRIS
+    PauseMedia,
+    // Lock screen with the judge chat
+    LockChat,
+    // Lock screen with a countdown of this many minutes, no chat
+    TimedLock(u64),
+}
+
+// One step of the enforcement pipeline, run only if its conditions hold
+pub struct EnforcementStep {
+    pub action: Action,
+    // Positive verdicts in the LOCK_TRIGGER window
+    pub min_severity: usize,
+    // Earlier enforcements since the ladder was reset, see LADDER_RESET_MINUTES
+    pub min_repeats: u32,
+    // The last enforcement the step is on the ladder for; None for all after
+    pub max_repeats: Option<u32>,
+}
+
+// Kinds of desktop notifications, enabled in NOTIFICATIONS
+#[allow(dead_code)] // Variants are picked in constants.rs
+#[derive(PartialEq)]
+pub enum Notification {
+    // A capture is about to be classified
+    Classification,
+    // The classifier's verdict
+    Verdict,
+    // Warnings before a lock
+    Warning,
+    // A lock ended
+    LockEnd,
+    // The API couldn't be reached
+    ApiError,
+    // The daily budget is spent
+    Budget,
+    // Bypasses, failed locks and other things that need attention
+    Alert,
+    // The classifier wasn't sure, see MIN_CONFIDENCE
+    Clarification,
+}
+
+// Daily time span during which the screen is locked regardless of activity
+pub struct HardBlock {
+    pub hour: u32,
+    pub minute: u32,
+    pub minutes: u64, // Length, may extend past midnight
+}
+
+// How captures are taken
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum CaptureBackend {
+    // SCROT_CMD
+    Scrot,
+    // GetImage on the root window
+    Native,
+}
+
+// A stage of the ONBOARDING ramp
+#[allow(dead_code)] // Variants are picked in constants.rs
+#[derive(Clone, Copy)]
+pub enum OnboardingPhase {
+    // Verdicts are logged but not acted on
+    Observe,
+    // Notifications and nudges, no locks
+    NudgesOnly,
+    // Every lock is a timed lock of at most this many minutes
+    ShortLocks(u64),
+}
+
+// Monitoring eased after enough days without an offense
+pub struct TrustLevel {
+    pub clean_days: u64,
+    // Multiplies API_CALL_INTERVAL_SECS
+    pub interval_factor: u64,
+    // Classify on window titles alone, without the screen text
+    pub metadata_only: bool,
+    // On top of MAX_PAUSES_PER_DAY
+    pub extra_pauses: u32,
+}
+
+// What the lock chat does when the judge can't be reached
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum OfflinePolicy {
+    // Keep the screen locked for this many minutes
+    TimedLock(u64),
+    // Decide locally from the conversation, see OFFLINE_DISTRACTIONS
+    LocalJudge,
+    // Unlock, reporting an "offline bypass" event
+    Bypass,
+}
+
+// Where emergency unlocks are sent for an accountability partner to approve
+pub struct PartnerApproval {
+    // Receives a POST with {"token", "text"} for each request
+    pub request_url: &'static str,
+    // Polled with the token appended, answers {"approved": true|false}
+    pub status_url: &'static str,
+    // Without an answer by then, fall back to the password prompt
+    pub timeout_minutes: u64,
+}
+
+pub struct PhoneApproval {
+    pub push: ApprovalPush,
+    // Public base URL forwarded to listen_port on this machine; the approve
+    // and deny links are made from it
+    pub relay_url: &'static str,
+    pub listen_port: u16,
+    // Without a tap by then, fall back to the partner or password
+    pub timeout_minutes: u64,
+}
+
+// Where the push with the approval links goes
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum ApprovalPush {
+    // Topic URL on an ntfy server, e.g. "https://ntfy.sh/<secret topic>";
+    // $NTFY_TOKEN is sent as the access token if set
+    Ntfy(&'static str),
+    // The chat of TELEGRAM_CHAT_ID, through the bot
+    Telegram,
+}
+
+// Which lock outcomes clear the rolling screen records, so the next
+// classification isn't dominated by what was on screen before the lock
+#[allow(dead_code)] // Variants are picked in constants.rs
+pub enum ContextReset {
+    Never,
+    AfterUnlock,
+    AfterTimedLock,
+    Always,
+}
+
 // Result of a lock screen session
 pub enum LockResult {
     Unlocked,
     TimedLock(u64), // Minutes
+    // Unlocked with leniency for a declared purpose, after which checking
+    // resumes with the purpose in the context
+    UnlockedFor { minutes: u64, purpose: String },
 }
 
 // State for the X11 lock screen
 pub enum LockState {
     Init,
     Chat, // New state for chat mode
+    CheckIn, // Morning check-in, without a lock
 }
 
 #[derive(Deserialize)]
-pub struct ContentBlock {
-    pub text: String,
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text { text: String },
+    ToolUse { name: String, input: serde_json::Value },
+    #[serde(other)]
+    Other,
 }
 
 pub struct ScreenRecord {
+    pub id: String,
+    pub timestamp: DateTime<Local>,
+    pub text: String,
+    pub window: Option<ActiveWindow>,
+    // The browser's active tab, if it's what the window shows
+    pub tab: Option<Tab>,
+    // Media playing at the time, see media.rs
+    pub media: Vec<Media>,
+}
+
+// Active tab as reported by the browser extension, see browser.rs
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Tab {
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+}
+
+// A media player that's playing, from its MPRIS metadata
+#[derive(Clone, Debug)]
+pub struct Media {
+    pub player: String,
+    pub title: String,
+    pub artist: String,
+}
+
+// Something that happened between captures, e.g. a lock, shown in the
+// context so the gap or the lock screen's own text isn't misread
+pub struct ContextNote {
     pub timestamp: DateTime<Local>,
     pub text: String,
 }
+
+impl ScreenRecord {
+    // Record as it appears in the prompt, headed by the time and focused window
+    pub fn format(&self) -> String {
+        self.format_with(&self.text)
+    }
+
+    // Same, with the text replaced, e.g. after removing repeated lines
+    pub fn format_with(&self, text: &str) -> String {
+        let window = match &self.window {
+            Some(window) => {
+                let workspace = window.workspace.as_ref()
+                    .map_or(String::new(), |workspace| format!(" on workspace \"{}\"", workspace));
+                format!(" in {}: \"{}\"{}", window.class, window.title, workspace)
+            },
+            None => String::new(),
+        };
+        let tab = match &self.tab {
+            Some(tab) => format!(" at {}", tab.url),
+            None => String::new(),
+        };
+        let media: String = self.media.iter()
+            .map(|media| match media.artist.as_str() {
+                "" => format!("[playing in {}: \"{}\"]\n", media.player, media.title),
+                artist => format!("[playing in {}: \"{}\" by {}]\n", media.player, media.title, artist),
+            })
+            .collect();
+        format!("--- Screenshot at {}{}{} ---\n{}{}", self.timestamp.format("%Y-%m-%d %H:%M:%S"), window, tab, media, text)
+    }
+}
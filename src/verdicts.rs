@@ -0,0 +1,200 @@
+// Verdict history and classifier accuracy
+//
+// Every verdict is appended to verdicts.jsonl in the state directory.
+// Verdicts are labeled right or wrong afterwards: contesting a warning labels
+// it wrong, stopping after a warning or a lock upheld by the judge labels it
+// right. The labeled ones give a rolling accuracy score. While the
+// score is below ACCURACY_THRESHOLD, perimedes only observes and doesn't lock.
+//
+// The user can also label the latest verdict with `perimedes feedback` or
+// on the lock screen; the last FEEDBACK_EXAMPLES of those are shown to the
+// classifier as examples, so it adapts to what the user counts as work.
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{ACCURACY_THRESHOLD, FEEDBACK_EXAMPLES, FEEDBACK_PROMPT, SCOREBOARD_MIN_LABELS, SCOREBOARD_WINDOW};
+use crate::events;
+use crate::storage;
+use crate::types::{Category, LockReason, Stage};
+
+#[derive(Serialize, Deserialize)]
+pub struct Verdict {
+    #[serde(default)]
+    pub id: String,
+    pub time: String,
+    pub profile: String,
+    pub procrastinating: bool,
+    // The latest capture the verdict was on, and its focused window
+    #[serde(default)]
+    pub capture: Option<String>,
+    #[serde(default)]
+    pub window: Option<String>,
+    // How sure the classifier was, 0 to 100
+    #[serde(default)]
+    pub confidence: Option<u8>,
+    // For a positive verdict
+    #[serde(default)]
+    pub reason: Option<LockReason>,
+    // What the classifier said the screen showed
+    #[serde(default)]
+    pub category: Option<Category>,
+    // Which step of the check decided
+    #[serde(default)]
+    pub stage: Option<Stage>,
+    // Whether the verdict was right, once labeled
+    #[serde(default)]
+    pub correct: Option<bool>,
+    // Whether the user labeled it
+    #[serde(default)]
+    pub feedback: bool,
+}
+
+const VERDICTS_FILE: &str = "verdicts.jsonl";
+
+pub fn load() -> Result<Vec<Verdict>> {
+    storage::load_jsonl(VERDICTS_FILE)
+}
+
+fn save(verdicts: &[Verdict]) -> Result<()> {
+    storage::save_jsonl(VERDICTS_FILE, verdicts)
+}
+
+// Returns the id of the recorded verdict
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+    profile: &str,
+    procrastinating: bool,
+    confidence: Option<u8>,
+    reason: Option<LockReason>,
+    category: Option<Category>,
+    stage: Option<Stage>,
+    capture: Option<&str>,
+    window: Option<&str>,
+) -> Result<String> {
+    let verdict = Verdict {
+        id: events::new_id(),
+        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        profile: profile.to_string(),
+        procrastinating,
+        capture: capture.map(str::to_string),
+        window: window.map(str::to_string),
+        confidence,
+        reason,
+        category,
+        stage,
+        correct: None,
+        feedback: false,
+    };
+
+    storage::append_jsonl(VERDICTS_FILE, &verdict)?;
+    Ok(verdict.id)
+}
+
+// Label the verdict with this id; labeling it again with the same label
+// changes nothing. Returns the verdict, if there is one.
+pub fn label(id: &str, correct: bool) -> Result<Option<Verdict>> {
+    let mut verdicts = load()?;
+    let Some(index) = verdicts.iter().position(|verdict| verdict.id == id) else {
+        return Ok(None);
+    };
+    if verdicts[index].correct != Some(correct) {
+        verdicts[index].correct = Some(correct);
+        save(&verdicts)?;
+    }
+    Ok(Some(verdicts.swap_remove(index)))
+}
+
+// Label the latest verdict on the user's word, overriding automatic labels.
+// Returns the verdict, if there is one.
+pub fn feedback(correct: bool) -> Result<Option<Verdict>> {
+    let mut verdicts = load()?;
+    let Some(verdict) = verdicts.last_mut() else {
+        return Ok(None);
+    };
+    verdict.correct = Some(correct);
+    verdict.feedback = true;
+    save(&verdicts)?;
+    Ok(verdicts.pop())
+}
+
+// The user's latest labels as few-shot guidance for the classifier, or an
+// empty string without any
+pub fn examples() -> Result<String> {
+    let verdicts = load()?;
+    let mut examples = verdicts.iter()
+        .rev()
+        .filter(|verdict| verdict.feedback)
+        .filter_map(|verdict| Some((verdict, verdict.window.as_deref()?, verdict.correct?)))
+        .take(FEEDBACK_EXAMPLES)
+        .map(|(verdict, window, correct)| {
+            let said = if verdict.procrastinating { "PROCRASTINATING" } else { "NOT PROCRASTINATING" };
+            let right = if correct { "right" } else { "wrong" };
+            format!("* With {} focused, you said {}, which was {}.", window, said, right)
+        })
+        .collect::<Vec<_>>();
+    if examples.is_empty() {
+        return Ok(String::new());
+    }
+    examples.reverse();
+    Ok(FEEDBACK_PROMPT.replace("{}", &examples.join("\n")))
+}
+
+// Scores over the last SCOREBOARD_WINDOW labeled verdicts
+pub struct Score {
+    pub labeled: usize,
+    pub accuracy: Option<f64>,
+    pub precision: Option<f64>,
+    pub recall: Option<f64>,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+pub fn score(verdicts: &[Verdict]) -> Score {
+    let labeled = verdicts.iter()
+        .rev()
+        .filter_map(|v| v.correct.map(|correct| (v.procrastinating, correct)))
+        .take(SCOREBOARD_WINDOW)
+        .collect::<Vec<_>>();
+
+    let count = |procrastinating: bool, correct: bool| {
+        labeled.iter().filter(|v| **v == (procrastinating, correct)).count()
+    };
+    let (tp, fp) = (count(true, true), count(true, false));
+    let (tn, fn_) = (count(false, true), count(false, false));
+
+    Score {
+        labeled: labeled.len(),
+        accuracy: ratio(tp + tn, labeled.len()),
+        precision: ratio(tp, tp + fp),
+        recall: ratio(tp, tp + fn_),
+    }
+}
+
+// Accuracy, if it's known well enough and below the threshold
+pub fn poor_accuracy() -> Result<Option<f64>> {
+    let score = score(&load()?);
+    Ok(score.accuracy.filter(|accuracy| {
+        score.labeled >= SCOREBOARD_MIN_LABELS && *accuracy < ACCURACY_THRESHOLD
+    }))
+}
+
+// Scoreboard lines for the `stats` command
+pub fn print_score() -> Result<()> {
+    let score = score(&load()?);
+    let percent = |value: Option<f64>| match value {
+        Some(value) => format!("{:.0}%", value * 100.0),
+        None => "-".to_string(),
+    };
+
+    println!("classifier over the last {} labeled verdicts: accuracy {}, precision {}, recall {}",
+             score.labeled, percent(score.accuracy), percent(score.precision), percent(score.recall));
+    if let Some(accuracy) = poor_accuracy()? {
+        println!("accuracy {:.0}% is below {:.0}%: observing only, not locking",
+                 accuracy * 100.0, ACCURACY_THRESHOLD * 100.0);
+    }
+    Ok(())
+}
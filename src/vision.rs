@@ -0,0 +1,88 @@
+// Vision mode: the classifier also sees the last few distinct captures,
+// downscaled and tiled into a single image, so it gets what OCR misses
+// (videos, images, layout) and how the screen changed, for one image's cost
+
+use anyhow::{Result, Context, anyhow};
+use base64::Engine;
+use chrono::{DateTime, Local};
+use image::imageops::{self, FilterType};
+use image::{ImageOutputFormat, RgbImage};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::constants::{VISION_FRAMES, VISION_FRAME_WIDTH, VISION_MAX_BYTES, VISION_MAX_SIDE};
+
+// Downscaled captures, oldest first
+#[derive(Default)]
+pub struct Frames {
+    frames: VecDeque<(DateTime<Local>, RgbImage)>,
+}
+
+impl Frames {
+    pub fn enabled() -> bool {
+        VISION_FRAMES.is_some_and(|frames| frames > 0)
+    }
+
+    // Add a capture, dropping the oldest beyond VISION_FRAMES
+    pub fn push(&mut self, timestamp: DateTime<Local>, path: &Path) -> Result<()> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load {}", path.display()))?
+            .into_rgb8();
+        let (width, height) = image.dimensions();
+        let frame_width = VISION_FRAME_WIDTH.min(width).max(1);
+        let frame_height = (height as u64 * frame_width as u64 / width.max(1) as u64).max(1) as u32;
+        self.frames.push_back((timestamp, imageops::resize(&image, frame_width, frame_height, FilterType::Triangle)));
+        while self.frames.len() > VISION_FRAMES.unwrap_or(0) {
+            self.frames.pop_front();
+        }
+        Ok(())
+    }
+
+    pub fn prune(&mut self, before: DateTime<Local>) {
+        while self.frames.front().is_some_and(|(timestamp, _)| *timestamp < before) {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // The frames in a grid, left to right and top to bottom, as a PNG within
+    // VISION_MAX_SIDE and VISION_MAX_BYTES; None without frames
+    pub fn tiled(&self) -> Result<Option<Vec<u8>>> {
+        if self.frames.is_empty() {
+            return Ok(None);
+        }
+        let columns = (self.frames.len() as f64).sqrt().ceil() as u32;
+        let rows = (self.frames.len() as u32).div_ceil(columns);
+        let cell_width = self.frames.iter().map(|(_, frame)| frame.width()).max().unwrap_or(1);
+        let cell_height = self.frames.iter().map(|(_, frame)| frame.height()).max().unwrap_or(1);
+
+        let mut canvas = RgbImage::new(columns * cell_width, rows * cell_height);
+        for (i, (_, frame)) in self.frames.iter().enumerate() {
+            let (column, row) = (i as u32 % columns, i as u32 / columns);
+            imageops::replace(&mut canvas, frame, (column * cell_width) as i64, (row * cell_height) as i64);
+        }
+
+        let (width, height) = canvas.dimensions();
+        let scale = VISION_MAX_SIDE as f64 / width.max(height) as f64;
+        if scale < 1.0 {
+            let (width, height) = ((width as f64 * scale) as u32, (height as f64 * scale) as u32);
+            canvas = imageops::resize(&canvas, width.max(1), height.max(1), FilterType::Triangle);
+        }
+
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(canvas).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .context("Failed to encode the frames")?;
+        if png.len() > VISION_MAX_BYTES {
+            return Err(anyhow!("Tiled frames are {} KB, over the limit of {} KB", png.len() / 1024, VISION_MAX_BYTES / 1024));
+        }
+        Ok(Some(png))
+    }
+}
+
+pub fn base64(png: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(png)
+}
@@ -0,0 +1,138 @@
+// Grace-period warning shown before the lock screen engages
+
+use anyhow::{Result, Context};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
+
+use crate::constants::{
+    THEME, CONTEST_KEY, CONTEST_KEY_NAME
+};
+use crate::notify;
+use crate::types::Notification;
+use crate::window;
+
+pub enum GraceOutcome {
+    Elapsed,
+    Contested,
+}
+
+const WARNING_WIDTH: u16 = 520;
+const WARNING_HEIGHT: u16 = 40;
+
+// Find a keycode producing the given keysym in the current keyboard mapping
+fn keycode_for(conn: &Arc<x11rb::rust_connection::RustConnection>, keysym: u32) -> Result<Option<Keycode>> {
+    let setup = conn.setup();
+    let count = setup.max_keycode - setup.min_keycode + 1;
+    let mapping = conn.get_keyboard_mapping(setup.min_keycode, count)?.reply()?;
+    let per_keycode = mapping.keysyms_per_keycode as usize;
+
+    Ok(mapping.keysyms
+        .chunks(per_keycode.max(1))
+        .position(|syms| syms.contains(&keysym))
+        .map(|i| setup.min_keycode + i as u8))
+}
+
+// Show a notification and a small countdown overlay without grabbing input.
+// Returns whether the countdown ran out or the user pressed the contest key.
+pub async fn run_grace_period(seconds: u64) -> Result<GraceOutcome> {
+    notify::send(
+        Notification::Warning,
+        "Procrastination detected",
+        &format!("Locking in {}s. Get back to work, or press {} to contest.", seconds, CONTEST_KEY_NAME),
+    );
+
+    let (conn, screen_num) = x11rb::connect(None)
+        .context("Failed to connect to X server")?;
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+
+    // Small always-on-top window in the top right corner
+    let win = conn.generate_id()?;
+    let values = CreateWindowAux::new()
+        .background_pixel(THEME.background)
+        .override_redirect(1)
+        .event_mask(EventMask::EXPOSURE);
+
+    conn.create_window(
+        screen.root_depth,
+        win,
+        screen.root,
+        (screen.width_in_pixels - WARNING_WIDTH) as i16 - 20, 20,
+        WARNING_WIDTH, WARNING_HEIGHT,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &values,
+    )?;
+
+    window::set_class(&conn, win)?;
+
+    let font = window::load_text_font(&conn, screen);
+    let gc = conn.generate_id()?;
+    let gc_aux = CreateGCAux::new()
+        .foreground(THEME.system)
+        .background(THEME.background)
+        .font(font.core_font());
+    conn.create_gc(gc, win, &gc_aux)?;
+
+    // Listen for the contest hotkey globally. Grabs match the modifier state
+    // exactly, so the chord is grabbed again with CapsLock and NumLock on.
+    let chord = ModMask::CONTROL | ModMask::M1;
+    let modifiers = [ModMask::from(0u16), ModMask::LOCK, ModMask::M2, ModMask::LOCK | ModMask::M2]
+        .map(|extra| chord | extra);
+    let contest_keycode = keycode_for(&conn, CONTEST_KEY)?;
+    if let Some(keycode) = contest_keycode {
+        for modifiers in modifiers {
+            conn.grab_key(true, screen.root, modifiers, keycode, GrabMode::ASYNC, GrabMode::ASYNC)?;
+        }
+    }
+
+    conn.map_window(win)?;
+    conn.flush()?;
+
+    let start = Instant::now();
+    let duration = Duration::from_secs(seconds);
+    let mut shown_secs = None;
+    let mut outcome = GraceOutcome::Elapsed;
+
+    while start.elapsed() < duration {
+        let mut redraw = false;
+        while let Some(event) = conn.poll_for_event()? {
+            match event {
+                Event::KeyPress(key) if Some(key.detail) == contest_keycode => {
+                    outcome = GraceOutcome::Contested;
+                },
+                Event::Expose(_) => redraw = true,
+                _ => {}
+            }
+        }
+        if let GraceOutcome::Contested = outcome {
+            break;
+        }
+
+        // Redraw when the displayed number of seconds changes
+        let remaining = (duration - start.elapsed()).as_secs() + 1;
+        if redraw || shown_secs != Some(remaining) {
+            shown_secs = Some(remaining);
+            conn.clear_area(false, win, 0, 0, 0, 0)?;
+            let text = format!("Locking in {}s - press {} to contest", remaining, CONTEST_KEY_NAME);
+            window::draw_text(&conn, win, gc, &font, &text, 12, 26, THEME.system)?;
+            conn.flush()?;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    if let Some(keycode) = contest_keycode {
+        for modifiers in modifiers {
+            conn.ungrab_key(keycode, screen.root, modifiers)?;
+        }
+    }
+    conn.destroy_window(win)?;
+    conn.flush()?;
+
+    Ok(outcome)
+}
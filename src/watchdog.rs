@@ -0,0 +1,109 @@
+// Companion process that restarts the daemon, and is restarted by it
+//
+// The daemon runs `perimedes watchdog` with a pipe on its stdin and writes
+// each state change into it. The pipe closes when the daemon dies, even
+// from kill -9, and the watchdog then starts a new daemon unless it was
+// paused or off schedule; the new daemon starts a new watchdog. A dying
+// watchdog is restarted by the daemon. Both are logged. Under systemd the
+// service restarts the daemon, so the watchdog only logs.
+
+use anyhow::{Result, Context};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::constants::{WATCHDOG_COMPANION, WATCHDOG_RESTART_SECS};
+use crate::events;
+use crate::ipc;
+use crate::service;
+use crate::types::Profile;
+
+// States in which a dead daemon isn't missed
+const INACTIVE_STATES: &[&str] = &["paused", "allowance", "off schedule"];
+
+// Keep a watchdog running alongside the daemon
+pub fn spawn(profile: &'static Profile) {
+    if !WATCHDOG_COMPANION {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            match watch(profile).await {
+                Ok(()) => {
+                    info!("The watchdog died, restarting it");
+                    if let Err(e) = events::log("watchdog_died", "restarted by the daemon") {
+                        warn!("Failed to log the watchdog's death: {}", e);
+                    }
+                },
+                Err(e) => warn!("Watchdog: {:#}", e),
+            }
+            tokio::time::sleep(Duration::from_secs(WATCHDOG_RESTART_SECS)).await;
+        }
+    });
+}
+
+// Run one watchdog and feed it the states until it exits
+async fn watch(profile: &Profile) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the perimedes binary")?;
+    let mut child = Command::new(exe)
+        .args(["--profile", profile.name, "watchdog"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to start the watchdog")?;
+    let mut pipe = child.stdin.take().context("Watchdog has no stdin")?;
+
+    let mut states = ipc::subscribe();
+    let mut state = ipc::current_status().state;
+    loop {
+        // A watchdog that stopped reading still counts as alive
+        let _ = pipe.write_all(format!("{}\n", state).as_bytes()).await;
+        state = loop {
+            tokio::select! {
+                _ = child.wait() => return Ok(()),
+                event = states.recv() => match event {
+                    Ok(ipc::Event::State { state, .. }) => break state,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {},
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+            }
+        };
+    }
+}
+
+// `perimedes watchdog`: wait for the daemon to close the pipe
+pub async fn run(profile: &Profile) -> Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut state = String::new();
+    while let Some(line) = lines.next_line().await? {
+        state = line;
+    }
+
+    let active = !INACTIVE_STATES.iter().any(|inactive| state.starts_with(inactive));
+    let detail = format!("last state: {}", if state.is_empty() { "unknown" } else { &state });
+    let (kind, restart) = match (active, service::supervised()) {
+        (false, _) => ("daemon_stopped", false),
+        (true, true) => ("daemon_died", false),
+        (true, false) => ("daemon_died", true),
+    };
+    events::log(kind, &detail)?;
+    if !restart {
+        return Ok(());
+    }
+
+    // A new daemon may already be starting
+    tokio::time::sleep(Duration::from_secs(WATCHDOG_RESTART_SECS)).await;
+    if ipc::daemon_running().await {
+        return Ok(());
+    }
+    warn!("perimedes died ({}), restarting it", detail);
+    let exe = std::env::current_exe().context("Failed to find the perimedes binary")?;
+    Command::new(exe)
+        .args(["--profile", profile.name])
+        .stdin(Stdio::null())
+        .spawn()
+        .context("Failed to restart perimedes")?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// Wind-down before scheduled hard blocks: the screen is progressively dimmed
+// through the RandR gamma ramps, then locked for the rest of the block
+
+use anyhow::{Result, Context, anyhow};
+use chrono::{DateTime, Duration, Local, NaiveTime};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, Crtc, GetCrtcGammaReply};
+use x11rb::rust_connection::RustConnection;
+use tracing::warn;
+
+use crate::constants::{HARD_BLOCKS, WIND_DOWN_MINUTES, WIND_DOWN_MIN_BRIGHTNESS, WIND_DOWN_EXPONENT};
+
+// Where we are relative to the hard blocks
+pub enum Phase {
+    Free,
+    // Share of the wind-down that has passed, from 0 to 1
+    WindDown(f32),
+    // Minutes left in the current block
+    Blocked(u64),
+}
+
+pub fn phase(now: DateTime<Local>) -> Phase {
+    for block in HARD_BLOCKS {
+        let Some(time) = NaiveTime::from_hms_opt(block.hour, block.minute, 0) else {
+            continue;
+        };
+
+        // A block started yesterday may still last past midnight
+        for days_ago in [0, 1] {
+            let date = now.date_naive() - Duration::days(days_ago);
+            let Some(start) = date.and_time(time).and_local_timezone(Local).earliest() else {
+                continue;
+            };
+
+            let end = start + Duration::minutes(block.minutes as i64);
+            let wind_down = start - Duration::minutes(WIND_DOWN_MINUTES as i64);
+
+            if now >= start && now < end {
+                let left = (end - now).num_seconds() as u64;
+                return Phase::Blocked(left.div_ceil(60));
+            }
+            if WIND_DOWN_MINUTES > 0 && now >= wind_down && now < start {
+                let progress = (now - wind_down).num_seconds() as f32 / (WIND_DOWN_MINUTES * 60) as f32;
+                return Phase::WindDown(progress);
+            }
+        }
+    }
+
+    Phase::Free
+}
+
+// Brightness for a point in the wind-down, following the configured curve
+pub fn brightness(progress: f32) -> f32 {
+    1.0 - (1.0 - WIND_DOWN_MIN_BRIGHTNESS) * progress.clamp(0.0, 1.0).powf(WIND_DOWN_EXPONENT)
+}
+
+// Scales the gamma ramps of all CRTCs; the original ramps are restored on drop
+pub struct Dimmer {
+    conn: RustConnection,
+    ramps: Vec<(Crtc, GetCrtcGammaReply)>,
+}
+
+impl Dimmer {
+    pub fn new() -> Result<Dimmer> {
+        let (conn, screen_num) = x11rb::connect(None)
+            .context("Failed to connect to X server")?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let resources = conn.randr_get_screen_resources_current(root)?.reply()
+            .context("RandR is not available")?;
+
+        let mut ramps = Vec::new();
+        for crtc in resources.crtcs {
+            let gamma = conn.randr_get_crtc_gamma(crtc)?.reply()?;
+            if !gamma.red.is_empty() {
+                ramps.push((crtc, gamma));
+            }
+        }
+
+        if ramps.is_empty() {
+            return Err(anyhow!("No CRTC with a gamma ramp"));
+        }
+        Ok(Dimmer { conn, ramps })
+    }
+
+    pub fn set_brightness(&self, brightness: f32) -> Result<()> {
+        let scale = |ramp: &[u16]| -> Vec<u16> {
+            ramp.iter().map(|v| (*v as f32 * brightness) as u16).collect()
+        };
+
+        for (crtc, gamma) in &self.ramps {
+            self.conn.randr_set_crtc_gamma(*crtc, &scale(&gamma.red), &scale(&gamma.green), &scale(&gamma.blue))?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Dimmer {
+    fn drop(&mut self) {
+        if let Err(e) = self.set_brightness(1.0) {
+            warn!("Failed to restore screen brightness: {}", e);
+        }
+    }
+}
@@ -1,9 +1,26 @@
 // Shared X11 window utilities
 
-use anyhow::Result;
-use std::sync::Arc;
-use x11rb::connection::Connection;
+use anyhow::{Result, Context, anyhow};
+use fontdue::{FontSettings, Metrics};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::wrapper::ConnectionExt as _;
 use x11rb::protocol::xproto::*;
+use tracing::warn;
+
+use crate::exclude;
+use crate::constants::{
+    CORE_FONTS, FONT_FAMILY, THEME, BACKGROUND_BLUR_FACTOR, BACKGROUND_DIM
+};
+
+// Tag a window as ours, so it's excluded from captures
+pub fn set_class(conn: &Arc<x11rb::rust_connection::RustConnection>, win: Window) -> Result<()> {
+    conn.change_property8(PropMode::REPLACE, win, AtomEnum::WM_CLASS, AtomEnum::STRING, exclude::WM_CLASS)?;
+    Ok(())
+}
 
 // Create an invisible cursor for lock screens
 pub fn create_invisible_cursor(conn: &Arc<x11rb::rust_connection::RustConnection>, win: Window) -> Result<Cursor> {
@@ -28,22 +45,342 @@ pub fn create_invisible_cursor(conn: &Arc<x11rb::rust_connection::RustConnection
     Ok(cursor)
 }
 
-// Draw text on a window with specified color
+// Move the pointer back to the middle of the lock window. Called on every
+// motion event, so the pointer can't drift onto another output.
+pub fn recenter_pointer(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    win: Window,
+    screen: &Screen,
+) -> Result<()> {
+    let x = (screen.width_in_pixels / 2) as i16;
+    let y = (screen.height_in_pixels / 2) as i16;
+    conn.warp_pointer(x11rb::NONE, win, 0, 0, 0, 0, x, y)?;
+    conn.flush()?;
+    Ok(())
+}
+
+// Whether a compositing manager is running on the screen
+pub fn compositor_running(conn: &Arc<x11rb::rust_connection::RustConnection>, screen_num: usize) -> Result<bool> {
+    let name = format!("_NET_WM_CM_S{}", screen_num);
+    let atom = conn.intern_atom(false, name.as_bytes())?.reply()?.atom;
+    let owner = conn.get_selection_owner(atom)?.reply()?.owner;
+    Ok(owner != x11rb::NONE)
+}
+
+// Fade a mapped window in from transparent to opaque. Uses the compositor's
+// _NET_WM_WINDOW_OPACITY hint rather than an ARGB visual, so text is drawn
+// the same way whether or not a compositor is running. Without a compositor
+// this does nothing and the window simply appears.
+pub async fn fade_in(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    win: Window,
+    screen_num: usize,
+    duration_ms: u64,
+) -> Result<()> {
+    if duration_ms == 0 || !compositor_running(conn, screen_num)? {
+        return Ok(());
+    }
+
+    let opacity = conn.intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?.reply()?.atom;
+    let steps = (duration_ms / 16).max(1);
+
+    for step in 0..steps {
+        let value = (u32::MAX as u64 * step / steps) as u32;
+        conn.change_property32(PropMode::REPLACE, win, opacity, AtomEnum::CARDINAL, &[value])?;
+        conn.flush()?;
+        tokio::time::sleep(std::time::Duration::from_millis(16)).await;
+    }
+
+    // No opacity property means fully opaque
+    conn.delete_property(win, opacity)?;
+    conn.flush()?;
+    Ok(())
+}
+
+// Whether the screen uses a 24 bit visual stored as 32 bits per pixel, the
+// only pixel format we write client-side images in
+fn supports_rgb_images(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> bool {
+    let bpp = conn.setup().pixmap_formats.iter()
+        .find(|format| format.depth == screen.root_depth)
+        .map(|format| format.bits_per_pixel);
+    screen.root_depth == 24 && bpp == Some(32)
+}
+
+// Build a screen-sized pixmap from a screenshot, blurred beyond legibility
+// by downscaling and scaling back up, and dimmed towards the background so the
+// chat stays readable on top of it
+pub fn blurred_background(
+    conn: &Arc<x11rb::rust_connection::RustConnection>,
+    screen: &Screen,
+    win: Window,
+    path: &Path,
+) -> Result<Pixmap> {
+    if !supports_rgb_images(conn, screen) {
+        return Err(anyhow!("Unsupported visual (depth {})", screen.root_depth));
+    }
+
+    let (width, height) = (screen.width_in_pixels as u32, screen.height_in_pixels as u32);
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load screenshot {}", path.display()))?
+        .to_rgb8();
+    let small = image::imageops::resize(
+        &image,
+        (width / BACKGROUND_BLUR_FACTOR).max(1),
+        (height / BACKGROUND_BLUR_FACTOR).max(1),
+        image::imageops::FilterType::Triangle,
+    );
+    let blurred = image::imageops::resize(&small, width, height, image::imageops::FilterType::Triangle);
+
+    let dim = |value: u8, shift: u32| {
+        let bg = ((THEME.background >> shift) & 0xff) as f32;
+        (value as f32 * (1.0 - BACKGROUND_DIM) + bg * BACKGROUND_DIM) as u32
+    };
+    let data: Vec<u8> = blurred.pixels()
+        .flat_map(|p| ((dim(p[0], 16) << 16) | (dim(p[1], 8) << 8) | dim(p[2], 0)).to_le_bytes())
+        .collect();
+
+    let pixmap = conn.generate_id()?;
+    conn.create_pixmap(screen.root_depth, pixmap, win, width as u16, height as u16)?;
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, pixmap, &CreateGCAux::new())?;
+
+    // Upload in strips that fit into a single request
+    let row_bytes = width as usize * 4;
+    let rows_per_strip = ((conn.maximum_request_bytes() - 64) / row_bytes).max(1);
+    for (i, strip) in data.chunks(rows_per_strip * row_bytes).enumerate() {
+        conn.put_image(
+            ImageFormat::Z_PIXMAP,
+            pixmap,
+            gc,
+            width as u16,
+            (strip.len() / row_bytes) as u16,
+            0,
+            (i * rows_per_strip) as i16,
+            0,
+            screen.root_depth,
+            strip,
+        )?;
+    }
+    conn.free_gc(gc)?;
+
+    Ok(pixmap)
+}
+
+// Client-side TrueType text rendering. Glyphs are rasterized with fontdue,
+// blended onto the background color and sent to the server with put_image.
+pub struct TrueTypeFont {
+    font: fontdue::Font,
+    size: f32,
+    depth: u8,
+    glyphs: Mutex<HashMap<char, (Metrics, Vec<u8>)>>,
+}
+
+impl TrueTypeFont {
+    // Load the configured font family, if fontconfig can find it and the
+    // screen uses a 32 bits per pixel true color format we can write directly
+    pub fn load(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> Result<TrueTypeFont> {
+        let depth = screen.root_depth;
+        if !supports_rgb_images(conn, screen) {
+            return Err(anyhow!("Unsupported visual (depth {})", depth));
+        }
+
+        let output = Command::new("fc-match")
+            .arg("-f")
+            .arg("%{file}")
+            .arg(FONT_FAMILY)
+            .output()
+            .context("Failed to run fc-match. Is fontconfig installed?")?;
+        let path = String::from_utf8_lossy(&output.stdout).to_string();
+        if path.is_empty() {
+            return Err(anyhow!("fc-match found no font for '{}'", FONT_FAMILY));
+        }
+
+        let data = std::fs::read(&path)
+            .with_context(|| format!("Failed to read font file {}", path))?;
+        let font = fontdue::Font::from_bytes(data, FontSettings::default())
+            .map_err(|e| anyhow!("Failed to parse font {}: {}", path, e))?;
+
+        Ok(TrueTypeFont {
+            font,
+            size: THEME.font_size,
+            depth,
+            glyphs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        conn: &Arc<x11rb::rust_connection::RustConnection>,
+        win: Window,
+        gc: Gcontext,
+        text: &str,
+        x: i16,
+        y: i16,
+        color: u32
+    ) -> Result<()> {
+        let (ascent, descent) = match self.font.horizontal_line_metrics(self.size) {
+            Some(metrics) => (metrics.ascent.ceil() as i32, metrics.descent.floor() as i32),
+            None => (self.size.ceil() as i32, 0),
+        };
+
+        let mut glyphs = self.glyphs.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for c in text.chars() {
+            glyphs.entry(c).or_insert_with(|| self.font.rasterize(c, self.size));
+        }
+
+        let width: f32 = text.chars().map(|c| glyphs[&c].0.advance_width).sum();
+        let width = width.ceil() as i32;
+        let height = ascent - descent;
+        if width <= 0 || height <= 0 {
+            return Ok(());
+        }
+
+        // Fill with the background, then blend each glyph's coverage in
+        let mut pixels = vec![THEME.background; (width * height) as usize];
+        let mut pen_x = 0.0f32;
+        for c in text.chars() {
+            let (metrics, coverage) = &glyphs[&c];
+            let left = pen_x.round() as i32 + metrics.xmin;
+            let top = ascent - metrics.height as i32 - metrics.ymin;
+
+            for row in 0..metrics.height as i32 {
+                for col in 0..metrics.width as i32 {
+                    let (px, py) = (left + col, top + row);
+                    if px < 0 || py < 0 || px >= width || py >= height {
+                        continue;
+                    }
+                    let alpha = coverage[(row * metrics.width as i32 + col) as usize];
+                    let pixel = &mut pixels[(py * width + px) as usize];
+                    *pixel = blend(*pixel, color, alpha);
+                }
+            }
+
+            pen_x += metrics.advance_width;
+        }
+
+        let data: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.to_le_bytes()).collect();
+        conn.put_image(
+            ImageFormat::Z_PIXMAP,
+            win,
+            gc,
+            width as u16,
+            height as u16,
+            x,
+            y - ascent as i16,
+            0,
+            self.depth,
+            &data,
+        )?;
+
+        Ok(())
+    }
+}
+
+// Mix two 0xRRGGBB colors by an 8-bit coverage value
+fn blend(bg: u32, fg: u32, alpha: u8) -> u32 {
+    let alpha = alpha as u32;
+    let channel = |shift: u32| {
+        let b = (bg >> shift) & 0xff;
+        let f = (fg >> shift) & 0xff;
+        ((f * alpha + b * (255 - alpha)) / 255) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+// The font used for all lock screen text. Loading never fails: it falls
+// back from TrueType to the core fonts and finally to boxes.
+pub enum TextFont {
+    TrueType(Box<TrueTypeFont>),
+    Core(Font),
+    Boxes,
+}
+
+// Size of a character cell when drawing boxes
+const BOX_WIDTH: i16 = 9;
+const BOX_HEIGHT: i16 = 14;
+
+impl TextFont {
+    // The core font to set on a graphics context, if any
+    pub fn core_font(&self) -> Option<Font> {
+        match self {
+            TextFont::Core(font) => Some(*font),
+            _ => None,
+        }
+    }
+}
+
+// Open the first core font from CORE_FONTS that the server has
+fn open_core_font(conn: &Arc<x11rb::rust_connection::RustConnection>) -> Option<Font> {
+    for name in CORE_FONTS {
+        let font = conn.generate_id().ok()?;
+        // open_font errors arrive asynchronously, so check each attempt
+        match conn.open_font(font, name.as_bytes()).map(|cookie| cookie.check()) {
+            Ok(Ok(())) => return Some(font),
+            _ => warn!("Failed to open core font {}", name),
+        }
+    }
+    None
+}
+
+pub fn load_text_font(conn: &Arc<x11rb::rust_connection::RustConnection>, screen: &Screen) -> TextFont {
+    match TrueTypeFont::load(conn, screen) {
+        Ok(font) => return TextFont::TrueType(Box::new(font)),
+        Err(e) => warn!("Falling back to core X font: {}", e),
+    }
+
+    match open_core_font(conn) {
+        Some(font) => TextFont::Core(font),
+        None => {
+            warn!("No usable font found, drawing text as boxes");
+            TextFont::Boxes
+        }
+    }
+}
+
+// Draw text on a window with specified color in the given font
+#[allow(clippy::too_many_arguments)]
 pub fn draw_text(
     conn: &Arc<x11rb::rust_connection::RustConnection>,
     win: Window,
     gc: Gcontext,
+    font: &TextFont,
     text: &str,
     x: i16,
     y: i16,
     color: u32
 ) -> Result<()> {
+    if let TextFont::TrueType(font) = font {
+        return font.draw(conn, win, gc, text, x, y, color);
+    }
+
     // Update color
     let values = ChangeGCAux::new().foreground(color);
     conn.change_gc(gc, &values)?;
 
+    if let TextFont::Boxes = font {
+        // One outlined box per visible character, keeping the text's shape
+        let boxes: Vec<Rectangle> = text.chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .map(|(i, _)| Rectangle {
+                x: x + i as i16 * BOX_WIDTH,
+                y: y - BOX_HEIGHT,
+                width: BOX_WIDTH as u16 - 2,
+                height: BOX_HEIGHT as u16,
+            })
+            .collect();
+        conn.poly_rectangle(win, gc, &boxes)?;
+        return Ok(());
+    }
+
+    // Core fonts are Latin-1; replace anything outside of it
+    let bytes: Vec<u8> = text.chars()
+        .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+        .collect();
+
     // Draw text
-    conn.image_text8(win, gc, x, y, text.as_bytes())?;
+    conn.image_text8(win, gc, x, y, &bytes)?;
 
     Ok(())
-}
\ No newline at end of file
+}
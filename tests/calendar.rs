@@ -0,0 +1,68 @@
+// Meetings from iCalendar data: single, recurring and moved events
+
+use chrono::{Local, TimeZone};
+use perimedes::calendar::{self, meeting_at};
+
+fn at(date: (i32, u32, u32), hour: u32, minute: u32) -> chrono::DateTime<Local> {
+    Local.with_ymd_and_hms(date.0, date.1, date.2, hour, minute, 0).unwrap()
+}
+
+const ICS: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Standup\r
+DTSTART;TZID=Europe/Berlin:20261019T093000\r
+DTEND;TZID=Europe/Berlin:20261019T094500\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;UNTIL=20261130T000000Z\r
+EXDATE;TZID=Europe/Berlin:20261023T093000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID;TZID=Europe/Berlin:20261021T093000\r
+SUMMARY:Standup\r
+DTSTART;TZID=Europe/Berlin:20261021T110000\r
+DURATION:PT15M\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review\r
+SUMMARY:Paper review with\r
+  Alex\r
+DTSTART:20261020T140000\r
+DTEND:20261020T150000\r
+ATTENDEE;CN=\"Alex: reviewer\":mailto:alex@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:focus\r
+SUMMARY:Deep work\r
+DTSTART:20261020T090000\r
+DTEND:20261020T120000\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+#[test]
+fn meetings_are_found() {
+    let events = calendar::parse(ICS, &calendar::meeting_pattern().unwrap());
+    assert_eq!(events.len(), 4);
+
+    let review = meeting_at(&events, at((2026, 10, 20), 14, 30)).unwrap();
+    assert_eq!(review.summary, "Paper review with Alex");
+    // Events without attendees or a meeting-like summary don't count
+    assert_eq!(meeting_at(&events, at((2026, 10, 20), 10, 0)), None);
+    assert_eq!(meeting_at(&events, at((2026, 10, 20), 15, 0)), None);
+}
+
+#[test]
+fn recurring_meetings() {
+    let events = calendar::parse(&ICS.replace("Standup", "Team sync"), &calendar::meeting_pattern().unwrap());
+
+    let monday = meeting_at(&events, at((2026, 10, 26), 9, 40)).unwrap();
+    assert_eq!((monday.start, monday.end), (at((2026, 10, 26), 9, 30), at((2026, 10, 26), 9, 45)));
+    // Not on Tuesdays, not on the excluded Friday, not after UNTIL
+    assert_eq!(meeting_at(&events, at((2026, 10, 27), 9, 40)), None);
+    assert_eq!(meeting_at(&events, at((2026, 10, 23), 9, 40)), None);
+    assert_eq!(meeting_at(&events, at((2026, 11, 30), 9, 40)), None);
+    // Moved to 11:00 that Wednesday
+    assert_eq!(meeting_at(&events, at((2026, 10, 21), 9, 40)), None);
+    assert!(meeting_at(&events, at((2026, 10, 21), 11, 5)).is_some());
+}
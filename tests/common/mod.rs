@@ -0,0 +1,21 @@
+// Keeps tests away from the real state, socket and notifications
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// Point the state and config directories and the control socket at a scratch
+// directory and hide the session bus and service manager
+pub fn isolate() -> PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("perimedes-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create the test directory");
+        std::env::set_var("XDG_STATE_HOME", &dir);
+        std::env::set_var("XDG_RUNTIME_DIR", &dir);
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+        for var in ["DBUS_SESSION_BUS_ADDRESS", "NOTIFY_SOCKET", "TELEGRAM_BOT_TOKEN"] {
+            std::env::remove_var(var);
+        }
+        dir
+    }).clone()
+}
@@ -0,0 +1,76 @@
+// The monitoring loop with mock stages, in paused time
+
+mod common;
+
+use perimedes::constants::{API_CALL_INTERVAL_SECS, PROFILES};
+use perimedes::daemon;
+use perimedes::ipc::{self, Event};
+use perimedes::mock::{MockCapturer, MockJudge, MockOcr};
+use std::time::Duration;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::time;
+
+// The daemon's state and socket are global, so runs mustn't overlap
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn states(events: &mut tokio::sync::broadcast::Receiver<Event>) -> Vec<String> {
+    let mut states = Vec::new();
+    loop {
+        match events.try_recv() {
+            Ok(Event::State { state, .. }) => states.push(state),
+            Ok(_) | Err(TryRecvError::Lagged(_)) => {},
+            Err(_) => return states,
+        }
+    }
+}
+
+// One check right away and one per interval: not procrastinating, then two
+// positive verdicts in a row, which trigger the lock
+#[tokio::test(start_paused = true)]
+async fn consecutive_verdicts_trigger_a_lock() {
+    let _serial = SERIAL.lock().await;
+    let dir = common::isolate();
+    // Warnings and locks must fail instead of covering the developer's screen
+    std::env::remove_var("DISPLAY");
+    let mut events = ipc::subscribe();
+
+    let capturer = MockCapturer::new(dir.join("capture.png"));
+    let ocr = MockOcr::new(["main.rs - editor", "reddit.com - front page"]);
+    let judge = MockJudge::new([false, true, true]);
+
+    let run = daemon::run_with(&PROFILES[0], "test-key", capturer.clone(), ocr.clone(), judge.clone());
+    let checks = 3 * API_CALL_INTERVAL_SECS - API_CALL_INTERVAL_SECS / 2;
+    let result = time::timeout(Duration::from_secs(checks), run).await;
+    assert!(result.is_err(), "the loop stopped: {:?}", result);
+
+    let contexts = judge.contexts();
+    assert_eq!(contexts.len(), 3);
+    assert!(contexts[0].contains("main.rs - editor"));
+    assert!(contexts[2].contains("reddit.com - front page"));
+    assert_eq!(capturer.captures(), ocr.calls());
+
+    // Without a display the warning and the lock screen fail, but are tried
+    // after the third check only
+    let states = states(&mut events);
+    let warned = states.iter().position(|state| state == "warning").expect("no warning");
+    assert_eq!(states[..warned].iter().filter(|state| *state == "checking").count(), 3);
+}
+
+// A failed check is reported and monitoring goes on with the next one
+#[tokio::test(start_paused = true)]
+async fn failed_check_keeps_monitoring() {
+    let _serial = SERIAL.lock().await;
+    let dir = common::isolate();
+    std::env::remove_var("DISPLAY");
+
+    let capturer = MockCapturer::new(dir.join("capture.png"));
+    let ocr = MockOcr::new(["main.rs - editor"]);
+    let judge = MockJudge::new([false, false]);
+    judge.fail_next(1);
+
+    let run = daemon::run_with(&PROFILES[0], "test-key", capturer.clone(), ocr.clone(), judge.clone());
+    let checks = 3 * API_CALL_INTERVAL_SECS - API_CALL_INTERVAL_SECS / 2;
+    let result = time::timeout(Duration::from_secs(checks), run).await;
+    assert!(result.is_err(), "the loop stopped: {:?}", result);
+    assert_eq!(judge.contexts().len(), 3);
+}
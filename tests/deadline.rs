@@ -0,0 +1,17 @@
+// Lock deadlines
+
+use perimedes::deadline::Deadline;
+use std::time::Duration;
+
+// An absurd extension, e.g. sent over Telegram, saturates instead of
+// wrapping around into a deadline that already passed
+#[test]
+fn huge_extension_keeps_the_lock() {
+    let mut deadline = Deadline::after(Duration::from_secs(60));
+    let wall_ms = deadline.wall_ms();
+    deadline.extend(Duration::MAX);
+    assert!(deadline.wall_ms() > wall_ms);
+    assert!(deadline.remaining() > Duration::from_secs(60));
+    deadline.extend(Duration::from_secs(u64::MAX));
+    assert_eq!(deadline.wall_ms(), i64::MAX);
+}
@@ -0,0 +1,196 @@
+// The classifier against a fake Anthropic server, and parsing of the
+// judge's decisions
+
+mod common;
+
+use perimedes::constants::{MAX_ALLOWANCE_MINUTES, MAX_LOCK_MINUTES, MIN_LOCK_MINUTES, PROFILES};
+use perimedes::judge::{AnthropicJudge, Classification, ProcrastinationJudge};
+use perimedes::lockscreen::parse_decision;
+use perimedes::stats;
+use perimedes::types::{Category, LockReason, LockResult};
+use perimedes::verdicts;
+use reqwest::Client;
+use serde_json::json;
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+// Messages endpoint answering every request with the given text
+async fn fake_anthropic(reply: &str) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/messages"))
+        .and(header("x-api-key", "test-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{ "type": "text", "text": reply }],
+            "usage": { "input_tokens": 100, "output_tokens": 10 },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn classification(reply: &str) -> Classification {
+    common::isolate();
+    let server = fake_anthropic(reply).await;
+    let mut judge = AnthropicJudge::new(Client::new(), "test-key")
+        .with_url(&format!("{}/v1/messages", server.uri()));
+    judge.classify(&PROFILES[0], "reddit.com - front page", None).await
+        .expect("Classification failed")
+}
+
+async fn classify(reply: &str) -> (bool, Option<String>) {
+    let classification = classification(reply).await;
+    (classification.procrastinating, classification.message)
+}
+
+#[tokio::test]
+async fn procrastinating_with_message() {
+    let verdict = classify("PROCRASTINATING\nMESSAGE: Scrolling reddit instead of writing.").await;
+    assert_eq!(verdict, (true, Some("Scrolling reddit instead of writing.".to_string())));
+}
+
+#[tokio::test]
+async fn procrastinating_without_message() {
+    assert_eq!(classify("PROCRASTINATING").await, (true, None));
+}
+
+#[tokio::test]
+async fn not_procrastinating() {
+    assert_eq!(classify("NOT PROCRASTINATING").await, (false, None));
+}
+
+#[tokio::test]
+async fn unclear_response_does_not_lock() {
+    assert_eq!(classify("I can't tell from this text.").await, (false, None));
+}
+
+#[tokio::test]
+async fn confidence_is_parsed() {
+    let unsure = classification("MESSAGE: Reddit.\nCONFIDENCE: 40\nPROCRASTINATING").await;
+    assert_eq!(unsure.confidence, Some(40));
+    assert_eq!(classification("CONFIDENCE: 95%\nNOT PROCRASTINATING").await.confidence, Some(95));
+    assert_eq!(classification("NOT PROCRASTINATING").await.confidence, None);
+}
+
+#[tokio::test]
+async fn lock_reason_is_parsed() {
+    let video = classification("MESSAGE: An hour of YouTube.\nREASON: video\nPROCRASTINATING").await;
+    assert_eq!(video.reason, Some(LockReason::Video));
+    let other = classification("REASON: gardening\nPROCRASTINATING").await;
+    assert_eq!(other.reason, Some(LockReason::Unclear));
+}
+
+#[tokio::test]
+async fn category_is_parsed() {
+    let messaging = classification("CATEGORY: messaging\nNOT PROCRASTINATING").await;
+    assert_eq!((messaging.category, messaging.reason), (Some(Category::Messaging), None));
+    let video = classification("CATEGORY: video\nMESSAGE: An hour of YouTube.\nPROCRASTINATING").await;
+    assert_eq!((video.category, video.reason), (Some(Category::Video), Some(LockReason::Video)));
+    let other = classification("CATEGORY: gardening\nPROCRASTINATING").await;
+    assert_eq!((other.category, other.reason), (Some(Category::Other), Some(LockReason::Unclear)));
+}
+
+#[tokio::test]
+async fn feedback_reaches_the_prompt() {
+    common::isolate();
+    verdicts::record("default", true, None, None, None, None, None, Some("firefox \"Rust by Example\"")).unwrap();
+    let labeled = verdicts::feedback(false).unwrap().expect("No verdict to label");
+    assert_eq!(labeled.correct, Some(false));
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("Rust by Example"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{ "type": "text", "text": "NOT PROCRASTINATING" }],
+            "usage": { "input_tokens": 100, "output_tokens": 10 },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let mut judge = AnthropicJudge::new(Client::new(), "test-key")
+        .with_url(&format!("{}/v1/messages", server.uri()));
+    judge.classify(&PROFILES[0], "doc.rust-lang.org", None).await.unwrap();
+}
+
+#[tokio::test]
+async fn goals_reach_the_prompt() {
+    let dir = common::isolate().join("perimedes");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("goals.md"), "Finish the thesis chapter on grounding\n").unwrap();
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("thesis chapter on grounding"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{ "type": "text", "text": "NOT PROCRASTINATING" }],
+            "usage": { "input_tokens": 100, "output_tokens": 10 },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let mut judge = AnthropicJudge::new(Client::new(), "test-key")
+        .with_url(&format!("{}/v1/messages", server.uri()));
+    judge.classify(&PROFILES[0], "arxiv.org", None).await.unwrap();
+}
+
+#[tokio::test]
+async fn frames_are_sent_as_an_image() {
+    common::isolate();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_string_contains(r#""media_type":"image/png""#))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": [{ "type": "text", "text": "NOT PROCRASTINATING" }],
+            "usage": { "input_tokens": 100, "output_tokens": 10 },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let mut judge = AnthropicJudge::new(Client::new(), "test-key")
+        .with_url(&format!("{}/v1/messages", server.uri()));
+    judge.classify(&PROFILES[0], "youtube.com", Some(b"\x89PNG")).await.unwrap();
+}
+
+#[tokio::test]
+async fn server_error_is_reported() {
+    common::isolate();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(529).set_body_string("overloaded"))
+        .mount(&server)
+        .await;
+    let mut judge = AnthropicJudge::new(Client::new(), "test-key")
+        .with_url(&format!("{}/v1/messages", server.uri()));
+    assert!(judge.classify(&PROFILES[0], "text", None).await.is_err());
+}
+
+const RANGE: (u64, u64) = (MIN_LOCK_MINUTES, MAX_LOCK_MINUTES);
+
+#[test]
+fn unlock_decision() {
+    assert!(matches!(parse_decision(&json!({ "action": "unlock" }), RANGE), LockResult::Unlocked));
+}
+
+#[test]
+fn allowance_is_clamped() {
+    let result = parse_decision(&json!({ "action": "unlock_for", "minutes": 600, "purpose": "email" }), RANGE);
+    assert!(matches!(result, LockResult::UnlockedFor { minutes, ref purpose }
+        if minutes == MAX_ALLOWANCE_MINUTES && purpose == "email"));
+}
+
+#[test]
+fn malformed_decision_keeps_the_lock() {
+    let result = parse_decision(&json!({ "action": "maybe" }), RANGE);
+    assert!(matches!(result, LockResult::TimedLock(minutes) if minutes == MIN_LOCK_MINUTES));
+}
+
+#[test]
+fn repeat_locks_get_longer() {
+    assert_eq!(stats::lock_range(0), RANGE);
+    let (min, max) = stats::lock_range(10);
+    assert!(min >= MIN_LOCK_MINUTES && max >= MAX_LOCK_MINUTES);
+
+    let result = parse_decision(&json!({ "action": "lock", "minutes": 0 }), (min, max));
+    assert!(matches!(result, LockResult::TimedLock(minutes) if minutes == min));
+}
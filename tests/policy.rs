@@ -0,0 +1,30 @@
+// Sandboxing of policy scripts
+
+use perimedes::policy::{Script, Signals};
+
+// A script can't pull in other files with `import`
+#[test]
+fn script_import_fails() {
+    let dir = std::env::temp_dir().join(format!("perimedes-policy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("helper.rhai"), "fn verdict() { \"focused\" }").unwrap();
+    let path = dir.join("policy.rhai");
+    std::fs::write(&path, "import \"helper\" as helper; helper::verdict()").unwrap();
+
+    let signals = Signals {
+        time: "10:00".to_string(),
+        profile: "default",
+        text: "",
+        window: None,
+        url: "",
+        domains: Vec::new(),
+        dwell_minutes: 0.0,
+        winding_down: false,
+        history: Vec::new(),
+    };
+    // Rhai resolves imports relative to the working directory
+    std::env::set_current_dir(&dir).unwrap();
+    let result = Script::load(&path).and_then(|script| script.decide(&signals));
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(result.is_err(), "the import succeeded");
+}
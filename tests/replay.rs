@@ -0,0 +1,47 @@
+// Recorded key sequences in tests/recordings, replayed through XTEST against
+// the windowed lock UI. Record more with PERIMEDES_RECORD_KEYS=<file> set
+// while using the lock screen.
+
+mod common;
+
+use perimedes::lockscreen;
+use perimedes::replay::{self, Step};
+use std::path::PathBuf;
+
+fn recordings() -> Vec<(PathBuf, Vec<Step>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/recordings");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("Failed to list recordings")
+        .map(|entry| entry.expect("Failed to read recordings").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    paths.sort();
+    paths.into_iter()
+        .map(|path| {
+            let steps = replay::load(&path).expect("Failed to load recording");
+            (path, steps)
+        })
+        .collect()
+}
+
+#[test]
+fn recordings_end_with_a_submission() {
+    let recordings = recordings();
+    assert!(!recordings.is_empty());
+    for (path, steps) in recordings {
+        assert!(matches!(steps.last(), Some(Step::Submit(_))), "{} has trailing keys", path.display());
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs an X server with XTEST, e.g. Xvfb"]
+async fn recordings_replay() {
+    common::isolate();
+    for (path, steps) in recordings() {
+        let expected = replay::submissions(&steps);
+        let player = tokio::task::spawn_blocking(move || replay::play(&steps));
+        let submitted = lockscreen::replay_session(expected.len()).await.expect("Lock UI failed");
+        player.await.expect("Replay panicked").expect("Replay failed");
+        assert_eq!(submitted, expected, "{}", path.display());
+    }
+}
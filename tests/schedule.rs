@@ -0,0 +1,53 @@
+// Focus hours: weekly entries, date overrides and holidays
+
+use chrono::{Local, TimeZone};
+use perimedes::schedule::{Schedule, Status};
+
+fn at(date: (i32, u32, u32), hour: u32, minute: u32) -> chrono::DateTime<Local> {
+    Local.with_ymd_and_hms(date.0, date.1, date.2, hour, minute, 0).unwrap()
+}
+
+// 2026-10-19 is a Monday
+const MONDAY: (i32, u32, u32) = (2026, 10, 19);
+const SATURDAY: (i32, u32, u32) = (2026, 10, 24);
+
+#[test]
+fn weekly_hours() {
+    let schedule = Schedule { weekly: &["Mon-Fri 09:00-12:00,13:00-18:00"], overrides: &[], holidays: &[] };
+    schedule.check().unwrap();
+    assert_eq!(schedule.status(at(MONDAY, 9, 0)), Status::Focus);
+    assert_eq!(schedule.status(at(MONDAY, 12, 30)), Status::Off(None));
+    assert_eq!(schedule.status(at(MONDAY, 18, 0)), Status::Off(None));
+    assert_eq!(schedule.status(at(SATURDAY, 10, 0)), Status::Off(None));
+}
+
+#[test]
+fn overrides_and_holidays() {
+    let schedule = Schedule {
+        weekly: &["Mon-Fri 09:00-18:00"],
+        overrides: &["2026-10-24 10:00-11:00", "2026-10-20 off"],
+        holidays: &[("Vacation", "2026-10-21..2026-10-22")],
+    };
+    schedule.check().unwrap();
+    assert_eq!(schedule.status(at(SATURDAY, 10, 30)), Status::Focus);
+    assert_eq!(schedule.status(at((2026, 10, 20), 10, 0)), Status::Off(None));
+    assert_eq!(schedule.status(at((2026, 10, 22), 10, 0)), Status::Off(Some("Vacation".to_string())));
+    assert_eq!(schedule.status(at((2026, 10, 23), 10, 0)), Status::Focus);
+}
+
+#[test]
+fn empty_schedule_is_always_on() {
+    let schedule = Schedule { weekly: &[], overrides: &[], holidays: &[] };
+    assert_eq!(schedule.status(at(SATURDAY, 3, 0)), Status::Focus);
+}
+
+#[test]
+fn invalid_entries_are_rejected() {
+    for weekly in ["Mon-Fri", "Mon-Fry 09:00-18:00", "Fri-Mon 09:00-18:00", "Mon 18:00-09:00", "Mon 9-18"] {
+        let schedule = Schedule { weekly: &["Mon 09:00-10:00", weekly], overrides: &[], holidays: &[] };
+        let error = schedule.check().expect_err(weekly);
+        assert!(error.to_string().starts_with("SCHEDULE[1]"), "{}", error);
+    }
+    let schedule = Schedule { weekly: &[], overrides: &[], holidays: &[("Vacation", "2026-13-01")] };
+    assert!(schedule.check().is_err());
+}